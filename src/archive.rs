@@ -0,0 +1,244 @@
+use crate::{load_ignore_file, to_text, DirContents, Filter, ReadOptions, RenderError};
+use ignore::gitignore::Gitignore;
+use prodash::Progress;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// Like [`unicode_content()`](crate::unicode_content()), but read the UTF-8 encoded files of the `.tar`,
+/// `.tar.gz`, `.tgz` or `.zip` file at `archive` without unpacking it.
+///
+/// Files are filtered like the files of a directory, with the patterns of [`ReadOptions::ignore_file`] being
+/// relative to the root of the archive, which has no ignore file that is used by default. Paths are kept as they
+/// are stored in the archive, like `project-1.0/src/main.rs`, and files are returned in the order they are stored in.
+/// Files with paths that would point outside of the archive, like `../secret`, are skipped.
+pub fn unicode_content_of_archive(
+    archive: &Path,
+    ReadOptions {
+        ignore_extensions,
+        ignore_file,
+        hidden,
+        normalize_eol,
+        modified_since,
+        binary_as_hexdump,
+    }: ReadOptions<'_>,
+    mut progress: impl Progress,
+    should_interrupt: &AtomicBool,
+) -> Result<(DirContents, usize), RenderError> {
+    let start = std::time::Instant::now();
+    let kind = ArchiveKind::of(archive).ok_or_else(|| RenderError::UnsupportedArchive {
+        path: archive.to_owned(),
+    })?;
+    let archive_error = |source| RenderError::Archive {
+        path: archive.to_owned(),
+        source,
+    };
+    progress.init(None, Some(prodash::unit::label("files")));
+    let mut content_progress = progress.add_child("content");
+    content_progress.init(
+        None,
+        Some(prodash::unit::dynamic_and_mode(
+            prodash::unit::Bytes,
+            prodash::unit::display::Mode::with_throughput(),
+        )),
+    );
+
+    let filter = Filter {
+        extensions: ignore_extensions,
+        // an archive has no ignore file that is used by default.
+        patterns: match ignore_file {
+            Some(ignore_file) => load_ignore_file(Path::new(""), Some(ignore_file))?,
+            None => Gitignore::empty(),
+        },
+        modified_since,
+    };
+    let mut paths = Vec::new();
+    let mut ignored = 0;
+    let mut add_entry = |path: PathBuf, modified: Option<SystemTime>, entry: &mut dyn Read| {
+        if should_interrupt.load(Ordering::Relaxed) {
+            return Err(RenderError::Interrupted);
+        }
+        progress.inc();
+        // like when searching a directory, hidden files are skipped unless asked for, and `.git` is never read.
+        let mut names = path.components().filter_map(|component| match component {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        });
+        if names.any(|name| name == ".git" || (!hidden && name.to_string_lossy().starts_with('.')))
+        {
+            return Ok(());
+        }
+        if filter.is_ignored_entry(&path, || true, || modified) {
+            ignored += 1;
+            return Ok(());
+        }
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).map_err(archive_error)?;
+        content_progress.inc_by(content.len());
+        if let Some(content) = to_text(content, normalize_eol, binary_as_hexdump) {
+            paths.push((path, content));
+        }
+        Ok(())
+    };
+
+    let file = BufReader::new(File::open(archive).map_err(archive_error)?);
+    match kind {
+        ArchiveKind::Tar => read_tar(file, &mut add_entry),
+        ArchiveKind::TarGz => read_tar(flate2::bufread::GzDecoder::new(file), &mut add_entry),
+        ArchiveKind::Zip => read_zip(file, &mut add_entry),
+    }
+    .map_err(|err| match err {
+        ReadError::Io(source) => archive_error(source),
+        ReadError::Entry(err) => err,
+    })?;
+
+    progress.show_throughput(start);
+    content_progress.show_throughput(start);
+    Ok((
+        DirContents {
+            parent_dir: PathBuf::new(),
+            children_content: paths,
+        },
+        ignored,
+    ))
+}
+
+/// The kinds of archives that can be read, as determined by their extension.
+#[derive(Clone, Copy)]
+enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reading an archive fails either because it can't be read, or because an entry couldn't be added.
+enum ReadError {
+    Io(std::io::Error),
+    Entry(RenderError),
+}
+
+impl From<std::io::Error> for ReadError {
+    fn from(err: std::io::Error) -> Self {
+        ReadError::Io(err)
+    }
+}
+
+/// The function that is called with the path, modification time and content of each file in an archive.
+type AddEntry<'a> =
+    dyn FnMut(PathBuf, Option<SystemTime>, &mut dyn Read) -> Result<(), RenderError> + 'a;
+
+/// Pass all regular files of the tar archive in `reader` to `add_entry`.
+///
+/// Files with paths that would point outside of the archive, like `../secret` or `/etc/passwd`, are skipped.
+fn read_tar(reader: impl Read, add_entry: &mut AddEntry<'_>) -> Result<(), ReadError> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        if path.components().any(|component| {
+            matches!(
+                component,
+                Component::ParentDir | Component::RootDir | Component::Prefix(_)
+            )
+        }) {
+            continue;
+        }
+        let modified = entry
+            .header()
+            .mtime()
+            .ok()
+            .map(|seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+        add_entry(path, modified, &mut entry).map_err(ReadError::Entry)?;
+    }
+    Ok(())
+}
+
+/// Pass all files of the zip archive in `reader` to `add_entry`.
+///
+/// Files with paths that would point outside of the archive, like `../secret`, are skipped.
+fn read_zip(reader: impl BufRead + Seek, add_entry: &mut AddEntry<'_>) -> Result<(), ReadError> {
+    let mut archive = zip::ZipArchive::new(reader).map_err(std::io::Error::from)?;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(std::io::Error::from)?;
+        if !file.is_file() {
+            continue;
+        }
+        let Some(path) = file.enclosed_name().map(Path::to_owned) else {
+            continue;
+        };
+        let modified = zip_time(file.last_modified());
+        add_entry(path, modified, &mut file).map_err(ReadError::Entry)?;
+    }
+    Ok(())
+}
+
+/// Convert the time a file in a zip archive was last modified to a system time, assuming it's in UTC as zip archives
+/// don't store time zones.
+fn zip_time(time: zip::DateTime) -> Option<SystemTime> {
+    // the days since 1970-01-01 of the given date, with years starting in March to put leap days last.
+    let (year, month, day) = (time.year() as i64, time.month() as i64, time.day() as i64);
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    let seconds = days * 86_400
+        + time.hour() as i64 * 3600
+        + time.minute() as i64 * 60
+        + time.second() as i64;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(u64::try_from(seconds).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tar_entries_outside_of_the_archive_are_skipped() {
+        let mut builder = tar::Builder::new(Vec::new());
+        for path in [
+            "proj/main.rs",
+            "../secret",
+            "proj/../../secret",
+            "/etc/secret",
+        ] {
+            let mut header = tar::Header::new_gnu();
+            // set the name directly, as the builder refuses to write paths like these.
+            header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(1);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, &b"x"[..]).unwrap();
+        }
+        let archive = builder.into_inner().unwrap();
+
+        let mut paths = Vec::new();
+        let read = read_tar(archive.as_slice(), &mut |path, _modified, _content| {
+            paths.push(path);
+            Ok(())
+        });
+        assert!(read.is_ok());
+        assert_eq!(paths, [Path::new("proj/main.rs")]);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::RenderError;
+use bstr::ByteSlice;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The author of each line of all files known to git, as a color derived from their name.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LineAuthors {
+    /// The color of the author of each line by the path of its file, indexed by line number starting at 0.
+    ///
+    /// Lines that aren't committed yet, or are past the end, have no author.
+    files: HashMap<PathBuf, Vec<Option<image::Rgb<u8>>>>,
+    /// The amount of lines each author wrote, by their name.
+    lines_by_author: HashMap<String, usize>,
+}
+
+impl LineAuthors {
+    /// Run `git blame` in `dir` on all files at `relative_paths` with `threads` threads, or as many as there are
+    /// logical cores if 0, to learn who last changed each of their lines.
+    ///
+    /// Files that aren't known to git, like untracked files, have no authors.
+    pub fn from_git(
+        dir: &Path,
+        relative_paths: &[PathBuf],
+        threads: usize,
+        should_interrupt: &AtomicBool,
+    ) -> Result<Self, RenderError> {
+        let threads = (threads == 0)
+            .then(num_cpus::get)
+            .unwrap_or(threads)
+            .clamp(1, relative_paths.len().max(1));
+        let next_path = AtomicUsize::default();
+        let blames = std::thread::scope(|scope| {
+            let workers = (0..threads)
+                .map(|_| {
+                    let next_path = &next_path;
+                    scope.spawn(move || -> Result<Vec<(PathBuf, Vec<u8>)>, RenderError> {
+                        let mut blames = Vec::new();
+                        while let Some(path) =
+                            relative_paths.get(next_path.fetch_add(1, Ordering::SeqCst))
+                        {
+                            if should_interrupt.load(Ordering::Relaxed) {
+                                return Err(RenderError::Interrupted);
+                            }
+                            let output = std::process::Command::new("git")
+                                .arg("-C")
+                                .arg(dir)
+                                .args(["blame", "--line-porcelain", "--"])
+                                .arg(path)
+                                .output()
+                                .map_err(|source| RenderError::Git {
+                                    dir: dir.to_owned(),
+                                    source,
+                                })?;
+                            // git refuses to blame files it doesn't know.
+                            if output.status.success() {
+                                blames.push((path.clone(), output.stdout));
+                            }
+                        }
+                        Ok(blames)
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("blaming doesn't panic"))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        Ok(Self::from_line_porcelain(
+            blames
+                .iter()
+                .flatten()
+                .map(|(path, blame)| (path.clone(), blame.as_slice())),
+        ))
+    }
+
+    /// Parse the output of `git blame --line-porcelain` of each file, along with its path, into the authors of
+    /// its lines.
+    pub fn from_line_porcelain<'a>(blames: impl IntoIterator<Item = (PathBuf, &'a [u8])>) -> Self {
+        let mut lines_by_author = HashMap::<String, usize>::new();
+        let files = blames
+            .into_iter()
+            .map(|(path, blame)| {
+                let mut authors = Vec::new();
+                let mut author = None::<&[u8]>;
+                let mut committed = false;
+                for line in blame.lines() {
+                    if line.starts_with(b"\t") {
+                        let name = author.filter(|_| committed).map(|name| name.to_str_lossy());
+                        if let Some(name) = &name {
+                            *lines_by_author
+                                .entry(name.clone().into_owned())
+                                .or_default() += 1;
+                        }
+                        authors.push(name.map(|name| author_color(&name)));
+                    } else if let Some(name) = line.strip_prefix(b"author ") {
+                        author = Some(name);
+                    } else if let Some(commit) = line.split_str(" ").next().filter(|commit| {
+                        commit.len() == 40 && commit.iter().all(u8::is_ascii_hexdigit)
+                    }) {
+                        // lines that aren't committed yet are attributed to a commit of zeroes.
+                        committed = commit.iter().any(|digit| *digit != b'0');
+                    }
+                }
+                (path, authors)
+            })
+            .collect();
+        LineAuthors {
+            files,
+            lines_by_author,
+        }
+    }
+
+    /// Return the color of the author of each line of the file at `relative_path`, or `None` if it isn't known to git.
+    pub fn of(&self, relative_path: &Path) -> Option<&[Option<image::Rgb<u8>>]> {
+        self.files.get(relative_path).map(Vec::as_slice)
+    }
+
+    /// Return the name, color and amount of lines of each author, those who wrote the most lines first.
+    pub fn legend(&self) -> Vec<(&str, image::Rgb<u8>, usize)> {
+        let mut legend = self
+            .lines_by_author
+            .iter()
+            .map(|(name, lines)| (name.as_str(), author_color(name), *lines))
+            .collect::<Vec<_>>();
+        legend.sort_unstable_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)));
+        legend
+    }
+}
+
+/// Derive a bright color from the `name` of an author, which is the same for the same name in every run.
+pub fn author_color(name: &str) -> image::Rgb<u8> {
+    // FNV-1a, as the hashers of the standard library aren't guaranteed to be stable.
+    let hash = name.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    let hue = (hash % 360) as f32 / 60.0;
+    let (saturation, value) = (0.65, 0.9);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let channel = |c: f32| ((c + value - chroma) * 255.0).round() as u8;
+    image::Rgb([channel(r), channel(g), channel(b)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_authors_are_parsed_from_blames() {
+        let blame = b"1111111111111111111111111111111111111111 1 1 2
+author Alice
+author-mail <alice@example.com>
+summary first
+filename a.rs
+\tfn a() {
+1111111111111111111111111111111111111111 2 2
+author Alice
+author-mail <alice@example.com>
+summary first
+filename a.rs
+\t}
+2222222222222222222222222222222222222222 3 3 1
+author Bob
+author-mail <bob@example.com>
+summary second
+filename a.rs
+\tauthor Mallory
+0000000000000000000000000000000000000000 4 4 1
+author Not Committed Yet
+author-mail <not.committed.yet>
+summary Version of a.rs from a.rs
+filename a.rs
+\tuncommitted();
+";
+        let authors = LineAuthors::from_line_porcelain([("a.rs".into(), &blame[..])]);
+        let (alice, bob) = (author_color("Alice"), author_color("Bob"));
+        assert_ne!(alice, bob);
+        assert_eq!(
+        authors.of(Path::new("a.rs")),
+        Some(&[Some(alice), Some(alice), Some(bob), None][..]),
+        "content that looks like a header is part of the line, and uncommitted lines have no author"
+    );
+        assert_eq!(authors.of(Path::new("b.rs")), None);
+        assert_eq!(
+            authors.legend(),
+            [("Alice", alice, 2), ("Bob", bob, 1)],
+            "the authors of most lines come first"
+        );
+    }
+}
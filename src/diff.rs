@@ -0,0 +1,204 @@
+use crate::RenderError;
+use bstr::ByteSlice;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// How a line changed between two revisions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum LineChange {
+    Unchanged,
+    /// The line is new.
+    Added,
+    /// The line replaced another line.
+    Modified,
+}
+
+impl LineChange {
+    /// The color lines with this change are tinted towards, if any.
+    pub fn color(self) -> Option<image::Rgb<u8>> {
+        match self {
+            LineChange::Unchanged => None,
+            LineChange::Added => Some(image::Rgb([46, 160, 67])),
+            LineChange::Modified => Some(image::Rgb([210, 153, 34])),
+        }
+    }
+}
+
+/// The changes of each line of all files that changed between two revisions.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LineChanges {
+    /// The changes of each line by the path of its file, indexed by line number starting at 0.
+    ///
+    /// Lines past the end are unchanged.
+    files: HashMap<PathBuf, Vec<LineChange>>,
+}
+
+impl LineChanges {
+    /// Run `git diff` in `dir` to learn which lines changed between the revisions `since` and `to`, or between `since`
+    /// and the files in `dir` if `to` is `None`.
+    ///
+    /// Paths are relative to `dir`. Files that aren't known to git, like untracked files, are unchanged.
+    pub fn from_git(dir: &Path, since: &str, to: Option<&str>) -> Result<Self, RenderError> {
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "core.quotePath=false", "diff"])
+            .args(["--relative", "--no-color", "--no-ext-diff", "--unified=0"])
+            .args(["--src-prefix=a/", "--dst-prefix=b/", since])
+            .args(to)
+            .arg("--")
+            .output()
+            .map_err(|source| RenderError::Git {
+                dir: dir.to_owned(),
+                source,
+            })?;
+        if !output.status.success() {
+            return Err(RenderError::GitDiff {
+                dir: dir.to_owned(),
+                message: output.stderr.to_str_lossy().trim().to_owned(),
+            });
+        }
+        Ok(Self::from_unified_diff(&output.stdout))
+    }
+
+    /// Parse the output of `git diff`, preferably with `--unified=0`, into the changes of the new version of each file.
+    ///
+    /// In each hunk, as many added lines as were removed are modified, and the remaining ones are added.
+    /// Files that were deleted have no lines, and files that are new have only added lines.
+    pub fn from_unified_diff(diff: &[u8]) -> Self {
+        let mut files = HashMap::<PathBuf, Vec<LineChange>>::new();
+        let mut path = None::<PathBuf>;
+        // the lines of the current hunk that are still to come, which could look like headers.
+        let mut hunk_lines = 0;
+        for line in diff.lines() {
+            if hunk_lines > 0 && matches!(line.first(), Some(b'+' | b'-' | b' ')) {
+                hunk_lines -= 1;
+                continue;
+            }
+            hunk_lines = 0;
+            if let Some(new_path) = line.strip_prefix(b"+++ ") {
+                path = parse_path(new_path);
+            } else if let Some(hunk) = line.strip_prefix(b"@@ ") {
+                let Some(((_, removed), (start, added))) = parse_hunk_header(hunk) else {
+                    continue;
+                };
+                hunk_lines = removed + added;
+                let Some(path) = &path else {
+                    continue;
+                };
+                // with no added lines, `start` is the line before the removed ones.
+                let first = start.saturating_sub(1);
+                let changes = files.entry(path.clone()).or_default();
+                if changes.len() < first + added {
+                    changes.resize(first + added, LineChange::Unchanged);
+                }
+                for (offset, change) in changes[first..first + added].iter_mut().enumerate() {
+                    *change = if offset < removed {
+                        LineChange::Modified
+                    } else {
+                        LineChange::Added
+                    };
+                }
+            }
+        }
+        LineChanges { files }
+    }
+
+    /// Return the changes of each line of the file at `relative_path`, or `None` if it didn't change.
+    pub fn of(&self, relative_path: &Path) -> Option<&[LineChange]> {
+        self.files.get(relative_path).map(Vec::as_slice)
+    }
+}
+
+/// Parse the path after `+++ `, which is `None` for deleted files.
+fn parse_path(path: &[u8]) -> Option<PathBuf> {
+    let path = match path.strip_prefix(b"\"") {
+        Some(quoted) => unquote(quoted.strip_suffix(b"\"")?),
+        None => path.to_owned(),
+    };
+    Some(path.strip_prefix(b"b/")?.to_path_lossy().into_owned())
+}
+
+/// Undo the C-style quoting git uses for paths with unusual characters.
+fn unquote(quoted: &[u8]) -> Vec<u8> {
+    let mut path = Vec::with_capacity(quoted.len());
+    let mut bytes = quoted.iter().copied();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            path.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'n') => path.push(b'\n'),
+            Some(b't') => path.push(b'\t'),
+            Some(digit @ b'0'..=b'7') => {
+                let octal = bytes.clone().take(2).collect::<Vec<_>>();
+                bytes.nth(1);
+                path.push(
+                    octal
+                        .iter()
+                        .fold(digit - b'0', |value, digit| value * 8 + (digit - b'0')),
+                );
+            }
+            Some(escaped) => path.push(escaped),
+            None => {}
+        }
+    }
+    path
+}
+
+/// Parse `-a,b +c,d @@` into `((a, b), (c, d))`, where counts are 1 if omitted.
+fn parse_hunk_header(hunk: &[u8]) -> Option<((usize, usize), (usize, usize))> {
+    let mut ranges = hunk.to_str().ok()?.split(' ');
+    let range = |range: &str| -> Option<(usize, usize)> {
+        Some(match range.split_once(',') {
+            Some((start, count)) => (start.parse().ok()?, count.parse().ok()?),
+            None => (range.parse().ok()?, 1),
+        })
+    };
+    let old = range(ranges.next()?.strip_prefix('-')?)?;
+    let new = range(ranges.next()?.strip_prefix('+')?)?;
+    Some((old, new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_lines_are_parsed_from_diffs() {
+        let diff = b"diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -2 +2,2 @@ fn a() {
+-    old();
++    new();
++++ looks like a header;
+@@ -5,0 +7 @@ fn b() {
++    added();
+diff --git a/new.rs b/new.rs
+new file mode 100644
+--- /dev/null
++++ b/new.rs
+@@ -0,0 +1,2 @@
++a
++b
+diff --git a/gone.rs b/gone.rs
+deleted file mode 100644
+--- a/gone.rs
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+";
+        let changes = LineChanges::from_unified_diff(diff);
+        use LineChange::*;
+        assert_eq!(
+            changes.of(Path::new("src/lib.rs")),
+            Some(&[Unchanged, Modified, Added, Unchanged, Unchanged, Unchanged, Added][..]),
+            "hunk lines that look like headers are part of the hunk"
+        );
+        assert_eq!(changes.of(Path::new("new.rs")), Some(&[Added, Added][..]));
+        assert_eq!(changes.of(Path::new("gone.rs")), None);
+    }
+}
@@ -0,0 +1,154 @@
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::error::{EncodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::{ImageBuffer, ImageEncoder, Rgb};
+use prodash::Progress;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::Path;
+
+/// The image formats that rendered images can be encoded in.
+///
+/// WebP isn't one of them as the `image` crate can only encode it with the C library `libwebp`, which would have to
+/// be installed to build `codevis`. SVG isn't either, as it could only embed the pixels as another image format.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Bmp,
+}
+
+impl Format {
+    /// The format implied by the extension of `path`, if it's one of ours.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match image::ImageFormat::from_path(path).ok()? {
+            image::ImageFormat::Png => Some(Format::Png),
+            image::ImageFormat::Jpeg => Some(Format::Jpeg),
+            image::ImageFormat::Bmp => Some(Format::Bmp),
+            _ => None,
+        }
+    }
+}
+
+/// The amount of bits each channel of a pixel is encoded with.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum BitDepth {
+    #[default]
+    #[value(name = "8")]
+    Eight,
+    /// Only supported by [`Format::Png`], with each 8-bit value `v` widened to `v * 257` so white stays white.
+    #[value(name = "16")]
+    Sixteen,
+}
+
+impl From<Format> for image::ImageFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Png => image::ImageFormat::Png,
+            Format::Jpeg => image::ImageFormat::Jpeg,
+            Format::Bmp => image::ImageFormat::Bmp,
+        }
+    }
+}
+
+/// A writer that counts all bytes written to `inner` in `progress`.
+pub struct WriteProgress<W, P> {
+    pub inner: W,
+    pub progress: P,
+}
+
+impl<W: Write, P: Progress> Write for WriteProgress<W, P> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.progress.inc_by(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Encode `img` as `format` with `bit_depth` bits per channel into `writer`, which can be a file, a buffer, stdout
+/// or a socket alike, with the amount of written bytes shown in `progress`.
+///
+/// `writer` is flushed once the image is encoded. [`BitDepth::Sixteen`] is an error with formats other than PNG.
+pub fn render_to_writer<C>(
+    img: &ImageBuffer<Rgb<u8>, C>,
+    format: Format,
+    bit_depth: BitDepth,
+    writer: impl Write,
+    mut progress: impl Progress,
+) -> Result<(), image::ImageError>
+where
+    C: Deref<Target = [u8]>,
+{
+    let start = std::time::Instant::now();
+    progress.init(
+        None,
+        Some(prodash::unit::dynamic_and_mode(
+            prodash::unit::Bytes,
+            prodash::unit::display::Mode::with_throughput(),
+        )),
+    );
+    let mut writer = WriteProgress {
+        inner: writer,
+        progress,
+    };
+    let (width, height) = img.dimensions();
+    let color_type = image::ColorType::Rgb8;
+    match (format, bit_depth) {
+        (Format::Png, BitDepth::Sixteen) => write_png16(img, &mut writer),
+        (_, BitDepth::Sixteen) => Err(image::ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Exact(format.into()),
+                UnsupportedErrorKind::Color(image::ExtendedColorType::Rgb16),
+            ),
+        )),
+        (Format::Png, BitDepth::Eight) => {
+            PngEncoder::new(&mut writer).write_image(img, width, height, color_type)
+        }
+        (Format::Jpeg, BitDepth::Eight) => {
+            JpegEncoder::new(&mut writer).write_image(img, width, height, color_type)
+        }
+        (Format::Bmp, BitDepth::Eight) => {
+            BmpEncoder::new(&mut writer).write_image(img, width, height, color_type)
+        }
+    }?;
+    writer.flush()?;
+    writer.progress.show_throughput(start);
+    Ok(())
+}
+
+/// Encode `img` as PNG with 16 bits per channel into `writer`, one row at a time so no widened copy of the whole
+/// image is needed.
+fn write_png16<C>(
+    img: &ImageBuffer<Rgb<u8>, C>,
+    writer: impl Write,
+) -> Result<(), image::ImageError>
+where
+    C: Deref<Target = [u8]>,
+{
+    let encoding_error =
+        |err| image::ImageError::Encoding(EncodingError::new(image::ImageFormat::Png.into(), err));
+    let (width, height) = img.dimensions();
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Sixteen);
+    let mut writer = encoder.write_header().map_err(encoding_error)?;
+    let mut stream = writer.stream_writer().map_err(encoding_error)?;
+    let row_len = (width as usize * 3).max(1);
+    let mut row = Vec::with_capacity(row_len * 2);
+    for samples in img.as_raw().chunks_exact(row_len) {
+        row.clear();
+        // PNG stores 16-bit samples in big endian.
+        row.extend(
+            samples
+                .iter()
+                .flat_map(|&sample| (sample as u16 * 257).to_be_bytes()),
+        );
+        stream.write_all(&row)?;
+    }
+    stream.finish().map_err(encoding_error)
+}
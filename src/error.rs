@@ -0,0 +1,98 @@
+use crate::render::Rect;
+use std::path::PathBuf;
+
+/// The error returned by [`unicode_content()`][crate::unicode_content()] and [`render()`][crate::render()].
+#[derive(Debug, thiserror::Error)]
+pub enum RenderError {
+    #[error("Cancelled by user")]
+    Interrupted,
+    #[error("Did not find a single line to render in {num_files} files")]
+    NoLines { num_files: usize },
+    #[error(
+        "Could not find theme {name:?}{}, must be one of {}",
+        .suggestion.as_ref().map(|s| format!(" - did you mean {s:?}?")).unwrap_or_default(),
+        .available.iter().map(|s| format!("{s:?}")).collect::<Vec<_>>().join(", ")
+    )]
+    ThemeNotFound {
+        name: String,
+        /// The available theme with the most similar name, if any is similar enough.
+        suggestion: Option<String>,
+        available: Vec<String>,
+    },
+    #[error(
+        "The target width of {target_width}px is smaller than a single column of {column_width}px"
+    )]
+    TargetWidthTooSmall {
+        target_width: u32,
+        column_width: u32,
+    },
+    #[error("Crop region {}x{} at {},{} must not be empty", .region.width, .region.height, .region.x, .region.y)]
+    EmptyCrop { region: Rect },
+    #[error(
+        "Crop region {}x{} at {},{} does not fit into the {width}x{height} image",
+        .region.width, .region.height, .region.x, .region.y
+    )]
+    CropOutOfBounds {
+        region: Rect,
+        width: u32,
+        height: u32,
+    },
+    #[error(
+        "The image needs {} even with a line height of {line_height}px, more than the maximum of {}",
+        bytesize::ByteSize(*.num_bytes),
+        bytesize::ByteSize(*.max_memory)
+    )]
+    ExceedsMaxMemory {
+        num_bytes: u64,
+        max_memory: u64,
+        line_height: u32,
+    },
+    #[error(
+        "The buffer of {}x{} pixels does not match the {}x{} image to render",
+        .actual.0, .actual.1, .expected.0, .expected.1
+    )]
+    BufferSize {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    #[error("Could not create cache directory {dir:?}")]
+    CacheDir {
+        dir: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not load ignore patterns from {path:?}")]
+    IgnoreFile {
+        path: PathBuf,
+        source: ignore::Error,
+    },
+    #[error("Could not load syntaxes from {dir:?}")]
+    SyntaxDir {
+        dir: PathBuf,
+        source: syntect::LoadingError,
+    },
+    #[error("Archive {path:?} must end with .tar, .tar.gz, .tgz or .zip")]
+    UnsupportedArchive { path: PathBuf },
+    #[error("Could not read archive {path:?}")]
+    Archive {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not run git in {dir:?}")]
+    Git {
+        dir: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("git diff failed in {dir:?}: {message}")]
+    GitDiff { dir: PathBuf, message: String },
+    #[error("Invalid glob {glob:?}")]
+    InvalidGlob {
+        glob: String,
+        source: globset::Error,
+    },
+    #[error(transparent)]
+    Walk(#[from] ignore::Error),
+    #[error(transparent)]
+    Highlight(#[from] syntect::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
@@ -0,0 +1,78 @@
+//! Progress as JSON lines, for tools that wrap this one.
+//!
+//! Each line is an object like `{"phase":"render","current":1200,"total":5000}`, where `phase` is the name of
+//! the task, `current` is the amount of progress made so far and `total` is the amount at which the task is done,
+//! or `null` if that isn't known. A line is only written if `current` or `total` changed since the last one
+//! written for the same task.
+use prodash::progress::{Key, Step, Task};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+/// The handle to the thread writing progress.
+pub struct JoinHandle {
+    should_stop: Arc<AtomicBool>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+impl JoinHandle {
+    /// Write the final state of all tasks and wait for the thread to stop.
+    pub fn shutdown_and_wait(self) {
+        self.should_stop.store(true, Ordering::Relaxed);
+        self.thread.join().ok();
+    }
+}
+
+/// Write the progress of all tasks in `progress` to `out` every `interval`, until shut down
+/// or `progress` is dropped.
+pub fn render(
+    mut out: impl Write + Send + 'static,
+    progress: Weak<prodash::Tree>,
+    interval: Duration,
+) -> JoinHandle {
+    let should_stop = Arc::new(AtomicBool::new(false));
+    let thread = std::thread::spawn({
+        let should_stop = Arc::clone(&should_stop);
+        move || {
+            let mut tasks = Vec::<(Key, Task)>::new();
+            let mut last_written = HashMap::<Key, (Step, Option<Step>)>::new();
+            loop {
+                // Read the flag first so the final state is written after a shutdown.
+                let stop = should_stop.load(Ordering::Relaxed);
+                match progress.upgrade() {
+                    Some(progress) => progress.sorted_snapshot(&mut tasks),
+                    None => break,
+                }
+                for (key, task) in &tasks {
+                    let value = match &task.progress {
+                        Some(value) => value,
+                        None => continue,
+                    };
+                    let state = (value.step.load(Ordering::Relaxed), value.done_at);
+                    if last_written.get(key) == Some(&state) {
+                        continue;
+                    }
+                    last_written.insert(*key, state);
+                    let event = serde_json::json!({
+                        "phase": task.name,
+                        "current": state.0,
+                        "total": state.1,
+                    });
+                    if writeln!(out, "{event}").is_err() {
+                        return;
+                    }
+                }
+                if stop {
+                    break;
+                }
+                std::thread::sleep(interval);
+            }
+        }
+    });
+    JoinHandle {
+        should_stop,
+        thread,
+    }
+}
@@ -1,29 +1,317 @@
-use anyhow::bail;
+use bstr::ByteSlice;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use prodash::Progress;
 use std::ffi::OsString;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+
+mod archive;
+pub use archive::unicode_content_of_archive;
+
+mod blame;
+pub use blame::{author_color, LineAuthors};
+
+mod diff;
+pub use diff::{LineChange, LineChanges};
+
+mod encode;
+pub use encode::{render_to_writer, BitDepth, Format, WriteProgress};
+
+mod error;
+pub use error::RenderError;
+
+mod renderer;
+pub use renderer::Renderer;
+
+mod terminal_theme;
+pub use terminal_theme::{parse_ansi_colors, terminal_theme, TERMINAL_THEME};
+
+mod timing;
+pub use timing::{Phase, PhaseTimer};
 
 pub mod render;
-pub use render::function::render;
+pub use render::band::render_in_bands;
+pub use render::function::{compute_layout, render, render_into};
 
 // The number of lines used for displaying filenames at
 // the top of files.
 const FILENAME_LINE_COUNT: u32 = 1;
 
 pub struct DirContents {
+    /// The directory the files were found in.
     pub parent_dir: PathBuf,
+    /// Pairs of `(path, content)` of all files, with paths that can be read as they are.
+    ///
+    /// Use [`relative_path()`](DirContents::relative_path()) to display them.
     pub children_content: Vec<(PathBuf, String)>,
 }
 
+impl DirContents {
+    /// Create contents from `files` as `(path, content)` pairs that are already in memory, with paths that don't
+    /// have to exist, like `src/main.rs`.
+    ///
+    /// Rendering never reads files, so no I/O occurs when rendering these contents unless a `cache_dir` is set.
+    /// Syntaxes are detected by the name or extension of each path, or by the first line of its content.
+    pub fn from_memory(files: Vec<(String, String)>) -> Self {
+        DirContents {
+            parent_dir: PathBuf::new(),
+            children_content: files
+                .into_iter()
+                .map(|(path, content)| (path.into(), content))
+                .collect(),
+        }
+    }
+
+    /// Keep only the lines of all files that match `regex`, and drop files without a single match entirely.
+    /// Return how many files were dropped.
+    ///
+    /// Lines are matched without their line ending, so `$` matches at their end.
+    pub fn keep_matching_lines(&mut self, regex: &regex::Regex) -> usize {
+        let num_files = self.children_content.len();
+        self.children_content.retain_mut(|(_, content)| {
+            let kept = content
+                .split_inclusive('\n')
+                .filter(|line| regex.is_match(line.trim_end_matches(['\r', '\n'])))
+                .collect::<String>();
+            *content = kept;
+            !content.is_empty()
+        });
+        num_files - self.children_content.len()
+    }
+
+    /// Shorten runs of more than `max_blank_lines` consecutive blank lines in all files to `max_blank_lines`,
+    /// and return how many lines were removed.
+    ///
+    /// Lines that contain only whitespace are blank as well. The lines that are kept stay as they are.
+    pub fn collapse_blank_lines(&mut self, max_blank_lines: usize) -> usize {
+        let mut collapsed = 0;
+        for (_, content) in &mut self.children_content {
+            let mut kept = String::with_capacity(content.len());
+            let mut blank_lines = 0;
+            for line in content.split_inclusive('\n') {
+                if line.trim().is_empty() {
+                    blank_lines += 1;
+                    if blank_lines > max_blank_lines {
+                        collapsed += 1;
+                        continue;
+                    }
+                } else {
+                    blank_lines = 0;
+                }
+                kept.push_str(line);
+            }
+            if kept.len() != content.len() {
+                *content = kept;
+            }
+        }
+        collapsed
+    }
+
+    /// Return how many control characters besides tabs and line endings all files contain, which are drawn
+    /// as a placeholder glyph, or left out with `strip_control`.
+    pub fn count_control_chars(&self) -> usize {
+        self.children_content
+            .iter()
+            .map(|(_, content)| {
+                content
+                    .chars()
+                    .filter(|&chr| render::is_stray_control_char(chr))
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Keep only `num_files` of all files, dropping whole files, and return how many were dropped.
+    ///
+    /// Without a `seed`, the kept files are spread evenly, like every tenth file of ten times as many, so all
+    /// parts of the directory remain. With a `seed`, they are chosen at random, with the same seed always choosing
+    /// the same files. Kept files stay in their order either way.
+    pub fn sample_files(&mut self, num_files: usize, seed: Option<u64>) -> usize {
+        let total = self.children_content.len();
+        if num_files >= total {
+            return 0;
+        }
+        let mut keep = vec![false; total];
+        match seed {
+            Some(seed) => {
+                let mut indices = (0..total).collect::<Vec<_>>();
+                render::shuffle(&mut indices, seed);
+                for index in &indices[..num_files] {
+                    keep[*index] = true;
+                }
+            }
+            None => {
+                for nth in 0..num_files {
+                    keep[nth * total / num_files] = true;
+                }
+            }
+        }
+        let mut keep = keep.into_iter();
+        self.children_content
+            .retain(|_| keep.next().expect("one flag per file"));
+        total - num_files
+    }
+
+    /// Cut all files with more than `max_lines` lines down to their first `max_lines` lines, and return how many files
+    /// were truncated along with how many lines were removed from them.
+    pub fn truncate_files(&mut self, max_lines: usize) -> (usize, usize) {
+        let (mut truncated_files, mut removed_lines) = (0, 0);
+        for (_, content) in &mut self.children_content {
+            let mut lines = content.split_inclusive('\n');
+            let kept_bytes = lines.by_ref().take(max_lines).map(str::len).sum::<usize>();
+            let removed = lines.count();
+            if removed != 0 {
+                truncated_files += 1;
+                removed_lines += removed;
+                content.truncate(kept_bytes);
+            }
+        }
+        (truncated_files, removed_lines)
+    }
+
+    /// Return `path` relative to `parent_dir` and without `.` components, like `src/lib.rs`,
+    /// which is the same no matter where the files are located.
+    ///
+    /// Paths outside of `parent_dir` are only stripped of their `.` components.
+    pub fn relative_path(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.parent_dir)
+            .unwrap_or(path)
+            .components()
+            .filter(|component| component != &std::path::Component::CurDir)
+            .collect()
+    }
+}
+
+/// The name of the file in the searched directory with gitignore-style patterns of files not to render.
+pub const IGNORE_FILE_NAME: &str = ".codeviz-ignore";
+
+/// Which files [`unicode_content()`] and its siblings read, and how.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReadOptions<'a> {
+    /// Files with any of these extensions are ignored.
+    pub ignore_extensions: &'a [OsString],
+    /// A file with gitignore-style patterns of files to ignore, which defaults to [`IGNORE_FILE_NAME`] in the
+    /// searched directory if it exists. These patterns are independent of `.gitignore` files.
+    pub ignore_file: Option<&'a Path>,
+    /// If true, hidden files and directories, whose name starts with a dot, are read as well. They are still
+    /// subject to `.gitignore` files and the patterns above, and the `.git` directory is never read.
+    pub hidden: bool,
+    /// If true, `\r\n` line endings are turned into `\n`, so files render the same no matter which line endings
+    /// they use.
+    pub normalize_eol: bool,
+    /// If set, files last modified before it are ignored, as are files whose modification time isn't known, so only
+    /// recent activity is rendered.
+    pub modified_since: Option<SystemTime>,
+    /// If true, files that aren't UTF-8 encoded are read as their [`hexdump()`] instead of being skipped. This adds
+    /// a line for every 16 bytes, so large binary files can take up most of the image.
+    pub binary_as_hexdump: bool,
+}
+
+/// Read all UTF-8 encoded files below `search_path` as configured by `opts`, along with the amount of files that
+/// were ignored.
 pub fn unicode_content(
     search_path: &Path,
-    ignore_extensions: &[OsString],
+    ReadOptions {
+        ignore_extensions,
+        ignore_file,
+        hidden,
+        normalize_eol,
+        modified_since,
+        binary_as_hexdump,
+    }: ReadOptions<'_>,
+    mut progress: impl Progress,
+    should_interrupt: &AtomicBool,
+) -> Result<(DirContents, usize), RenderError> {
+    let start = std::time::Instant::now();
+    progress.init(None, Some(prodash::unit::label("entries")));
+    let filter = Filter {
+        extensions: ignore_extensions,
+        patterns: load_ignore_file(search_path, ignore_file)?,
+        modified_since,
+    };
+
+    // only list paths first, which is fast, so reading them can show how far along it is.
+    let walk = ignore::WalkBuilder::new(search_path)
+        .hidden(!hidden)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build();
+    let mut entries = Vec::new();
+    for entry in walk {
+        if should_interrupt.load(Ordering::Relaxed) {
+            return Err(RenderError::Interrupted);
+        }
+        progress.inc();
+        entries.push(entry?.into_path());
+    }
+
+    progress.init(
+        Some(entries.len()),
+        prodash::unit::label_and_mode("files", prodash::unit::display::Mode::with_throughput())
+            .into(),
+    );
+    progress.set(0);
+    let mut content_progress = progress.add_child("content");
+    content_progress.init(
+        None,
+        Some(prodash::unit::dynamic_and_mode(
+            prodash::unit::Bytes,
+            prodash::unit::display::Mode::with_throughput(),
+        )),
+    );
+    let mut paths = Vec::new();
+    let mut ignored = 0;
+    for path in entries {
+        if should_interrupt.load(Ordering::Relaxed) {
+            return Err(RenderError::Interrupted);
+        }
+        progress.inc();
+        read_unless_ignored(
+            &path,
+            &filter,
+            normalize_eol,
+            binary_as_hexdump,
+            &mut paths,
+            &mut ignored,
+            &mut content_progress,
+        );
+    }
+
+    progress.show_throughput(start);
+    content_progress.show_throughput(start);
+    Ok((
+        DirContents {
+            parent_dir: search_path.to_path_buf(),
+            children_content: paths,
+        },
+        ignored,
+    ))
+}
+
+/// Like [`unicode_content()`], but read the given `files` relative to `parent_dir` instead of searching it.
+///
+/// Hidden files are read like any other, as they were listed explicitly, so [`ReadOptions::hidden`] is ignored.
+/// Paths that aren't within `parent_dir`, like absolute paths elsewhere or `a/../../b`, are counted as ignored.
+pub fn unicode_content_of(
+    parent_dir: &Path,
+    files: &[PathBuf],
+    ReadOptions {
+        ignore_extensions,
+        ignore_file,
+        hidden: _,
+        normalize_eol,
+        modified_since,
+        binary_as_hexdump,
+    }: ReadOptions<'_>,
     mut progress: impl Progress,
     should_interrupt: &AtomicBool,
-) -> anyhow::Result<(DirContents, usize)> {
+) -> Result<(DirContents, usize), RenderError> {
     let start = std::time::Instant::now();
-    progress.init(None, Some(prodash::unit::label("files")));
+    progress.init(
+        Some(files.len()),
+        prodash::unit::label_and_mode("files", prodash::unit::display::Mode::with_throughput())
+            .into(),
+    );
     let mut content_progress = progress.add_child("content");
     content_progress.init(
         None,
@@ -33,36 +321,327 @@ pub fn unicode_content(
         )),
     );
 
+    let filter = Filter {
+        extensions: ignore_extensions,
+        patterns: load_ignore_file(parent_dir, ignore_file)?,
+        modified_since,
+    };
     let mut paths = Vec::new();
     let mut ignored = 0;
-    for entry in ignore::Walk::new(search_path) {
+    for file in files {
         if should_interrupt.load(Ordering::Relaxed) {
-            bail!("Cancelled by user")
+            return Err(RenderError::Interrupted);
         }
         progress.inc();
-        let entry = entry?;
-        let path = entry.path();
-        if !ignore_extensions.is_empty()
-            && path.extension().map_or(false, |ext| {
-                ignore_extensions.iter().any(|extension| ext == extension)
-            })
-        {
+        let Some(path) = path_within(parent_dir, file) else {
             ignored += 1;
             continue;
-        }
-        if let Ok(content) = std::fs::read_to_string(path) {
-            content_progress.inc_by(content.len());
-            paths.push((path.to_owned(), content));
-        }
+        };
+        read_unless_ignored(
+            &path,
+            &filter,
+            normalize_eol,
+            binary_as_hexdump,
+            &mut paths,
+            &mut ignored,
+            &mut content_progress,
+        );
     }
 
     progress.show_throughput(start);
     content_progress.show_throughput(start);
     Ok((
         DirContents {
-            parent_dir: search_path.to_path_buf(),
+            parent_dir: parent_dir.to_path_buf(),
             children_content: paths,
         },
         ignored,
     ))
 }
+
+/// Return `file` joined to `parent_dir`, or `None` if it's not within `parent_dir`.
+///
+/// `.` and `..` are resolved without looking at the file system, with `..` that would leave `parent_dir` making
+/// `file` fall outside of it. Absolute paths are within `parent_dir` only if they start with it.
+fn path_within(parent_dir: &Path, file: &Path) -> Option<PathBuf> {
+    let relative = if file.has_root() {
+        file.strip_prefix(parent_dir).ok()?
+    } else {
+        file
+    };
+    let mut path = parent_dir.to_path_buf();
+    let mut depth = 0_usize;
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => {
+                path.push(name);
+                depth += 1;
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                depth = depth.checked_sub(1)?;
+                path.pop();
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(path)
+}
+
+/// Split NUL-separated `input`, as produced by `find -print0` or `git diff --name-only -z`, into paths.
+///
+/// Empty records, like the one after a trailing NUL, are skipped.
+pub fn nul_separated_paths(input: &[u8]) -> Vec<PathBuf> {
+    input
+        .split(|byte| *byte == 0)
+        .filter(|record| !record.is_empty())
+        .map(|record| record.to_path_lossy().into_owned())
+        .collect()
+}
+
+/// Decides which files not to render.
+struct Filter<'a> {
+    extensions: &'a [OsString],
+    patterns: Gitignore,
+    /// Files last modified before this time, or at an unknown time, are ignored.
+    modified_since: Option<SystemTime>,
+}
+
+impl Filter<'_> {
+    fn is_ignored(&self, path: &Path) -> bool {
+        self.is_ignored_entry(
+            path,
+            || path.is_file(),
+            || {
+                path.metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            },
+        )
+    }
+
+    /// Like [`is_ignored()`](Self::is_ignored()), but for paths that don't have to exist, like the files of
+    /// an archive, with `is_file` and `modified` telling what the path is and when it was last modified.
+    fn is_ignored_entry(
+        &self,
+        path: &Path,
+        is_file: impl Fn() -> bool,
+        modified: impl FnOnce() -> Option<SystemTime>,
+    ) -> bool {
+        (!self.extensions.is_empty()
+            && path.extension().map_or(false, |ext| {
+                self.extensions.iter().any(|extension| ext == extension)
+            }))
+            // Directories are only ignored through the files in them.
+            || (!self.patterns.is_empty()
+                && is_file()
+                && self
+                    .patterns
+                    .matched_path_or_any_parents(path, false)
+                    .is_ignore())
+            || self.modified_since.map_or(false, |since| {
+                is_file() && modified().map_or(true, |modified| modified < since)
+            })
+    }
+}
+
+/// Load the patterns of files not to render from `ignore_file`, or from [`IGNORE_FILE_NAME`] in `root` if it exists.
+///
+/// Patterns are relative to `root`.
+fn load_ignore_file(root: &Path, ignore_file: Option<&Path>) -> Result<Gitignore, RenderError> {
+    let path = match ignore_file {
+        Some(path) => path.to_owned(),
+        None => {
+            let path = root.join(IGNORE_FILE_NAME);
+            if !path.is_file() {
+                return Ok(Gitignore::empty());
+            }
+            path
+        }
+    };
+    let mut builder = GitignoreBuilder::new(root);
+    if let Some(source) = builder.add(&path) {
+        return Err(RenderError::IgnoreFile { path, source });
+    }
+    builder
+        .build()
+        .map_err(|source| RenderError::IgnoreFile { path, source })
+}
+
+/// Add the content of the file at `path` to `paths` if it's UTF-8 encoded, or `binary_as_hexdump` is true, and not
+/// ignored by `filter`, or count it as `ignored` if it is.
+fn read_unless_ignored(
+    path: &Path,
+    filter: &Filter<'_>,
+    normalize_eol: bool,
+    binary_as_hexdump: bool,
+    paths: &mut Vec<(PathBuf, String)>,
+    ignored: &mut usize,
+    content_progress: &mut impl Progress,
+) {
+    if filter.is_ignored(path) {
+        *ignored += 1;
+        return;
+    }
+    let Ok(content) = std::fs::read(path) else {
+        return;
+    };
+    content_progress.inc_by(content.len());
+    if let Some(content) = to_text(content, normalize_eol, binary_as_hexdump) {
+        paths.push((path.to_owned(), content));
+    }
+}
+
+/// Turn the bytes of a file into the text to render, or `None` if it isn't UTF-8 encoded and not `binary_as_hexdump`.
+fn to_text(content: Vec<u8>, normalize_eol: bool, binary_as_hexdump: bool) -> Option<String> {
+    let mut content = match String::from_utf8(content) {
+        Ok(content) => content,
+        Err(err) if binary_as_hexdump => return Some(hexdump(err.as_bytes())),
+        Err(_) => return None,
+    };
+    if normalize_eol && content.contains("\r\n") {
+        content = content.replace("\r\n", "\n");
+    }
+    Some(content)
+}
+
+/// Format `bytes` like `xxd` does, with 16 bytes per line in groups of two, followed by those that are printable
+/// ASCII characters, like `00000000: 8950 4e47 0d0a 1a0a 0000 000d 4948 4452  .PNG........IHDR`.
+pub fn hexdump(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+    let mut dump = String::with_capacity(bytes.len().div_ceil(16) * 68);
+    for (index, line) in bytes.chunks(16).enumerate() {
+        let hex = line
+            .chunks(2)
+            .map(|pair| pair.iter().map(|byte| format!("{byte:02x}")).collect())
+            .collect::<Vec<String>>()
+            .join(" ");
+        let chars = line
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        // short lines are padded so their characters line up with those of full lines.
+        writeln!(dump, "{:08x}: {hex:<39}  {chars}", index * 16)
+            .expect("writing to a string succeeds");
+    }
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_characters_besides_whitespace_are_counted() {
+        let contents = DirContents::from_memory(vec![
+            ("a.txt".into(), "\tno\r\n".into()),
+            ("b.txt".into(), "form\u{c}feed\n\0\u{1b}[0m\n".into()),
+        ]);
+        assert_eq!(contents.count_control_chars(), 3);
+    }
+
+    #[test]
+    fn runs_of_blank_lines_are_collapsed() {
+        let content = "a\n\n\n\nb\n  \n\t\nc\n\n";
+        let collapse = |max_blank_lines| {
+            let mut contents = DirContents::from_memory(vec![("main.rs".into(), content.into())]);
+            let collapsed = contents.collapse_blank_lines(max_blank_lines);
+            (contents.children_content.remove(0).1, collapsed)
+        };
+        assert_eq!(collapse(1), ("a\n\nb\n  \nc\n\n".into(), 3));
+        assert_eq!(collapse(0), ("a\nb\nc\n".into(), 6));
+        assert_eq!(
+            collapse(3),
+            (content.into(), 0),
+            "runs that are short enough stay"
+        );
+    }
+
+    #[test]
+    fn files_with_too_many_lines_are_truncated() {
+        let huge = (0..1000).map(|n| format!("line {n}\n")).collect::<String>();
+        let mut contents = DirContents::from_memory(vec![
+            ("small.rs".into(), "a\nb\n".into()),
+            ("huge.rs".into(), huge),
+            ("exact.rs".into(), "a\nb\nc".into()),
+            ("empty.rs".into(), String::new()),
+        ]);
+        assert_eq!(contents.truncate_files(3), (1, 997));
+        let content_of = |name: &str| {
+            contents
+                .children_content
+                .iter()
+                .find(|(path, _)| path == Path::new(name))
+                .map(|(_, content)| content.as_str())
+                .unwrap()
+        };
+        assert_eq!(content_of("huge.rs"), "line 0\nline 1\nline 2\n");
+        assert_eq!(
+            content_of("small.rs"),
+            "a\nb\n",
+            "short files stay as they are"
+        );
+        assert_eq!(content_of("exact.rs"), "a\nb\nc");
+        assert_eq!(content_of("empty.rs"), "");
+    }
+
+    #[test]
+    fn sampling_keeps_exactly_the_requested_amount_of_files() {
+        let contents = || {
+            DirContents::from_memory(
+                (0..10)
+                    .map(|index| (format!("{index}.rs"), format!("// {index}\n")))
+                    .collect(),
+            )
+        };
+        let paths = |contents: &DirContents| {
+            contents
+                .children_content
+                .iter()
+                .map(|(path, _)| path.display().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut even = contents();
+        assert_eq!(even.sample_files(4, None), 6);
+        assert_eq!(
+            paths(&even),
+            ["0.rs", "2.rs", "5.rs", "7.rs"],
+            "evenly spread"
+        );
+
+        let mut random = contents();
+        assert_eq!(random.sample_files(3, Some(42)), 7);
+        let mut again = contents();
+        again.sample_files(3, Some(42));
+        assert_eq!(paths(&random).len(), 3);
+        assert_eq!(
+            paths(&random),
+            paths(&again),
+            "the same seed samples the same files"
+        );
+
+        let mut all = contents();
+        assert_eq!(
+            all.sample_files(20, Some(1)),
+            0,
+            "asking for more keeps all"
+        );
+        assert_eq!(all.children_content.len(), 10);
+    }
+
+    #[test]
+    fn hexdumps_look_like_the_ones_of_xxd() {
+        assert_eq!(hexdump(b""), "");
+        assert_eq!(
+            hexdump(b"abc"),
+            "00000000: 6162 63                                  abc\n"
+        );
+    }
+}
@@ -1,17 +1,24 @@
 use anyhow::Context;
-use image::{ImageBuffer, Rgb};
-use memmap2::MmapMut;
+use codevis::{Phase, PhaseTimer};
+use image::{ImageBuffer, ImageFormat, Rgb, RgbImage};
+use notify::{RecursiveMode, Watcher};
 use std::borrow::Cow;
-use std::path::Path;
-use std::sync::atomic::AtomicBool;
+use std::collections::HashSet;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use syntect::highlighting::ThemeSet;
-use syntect::parsing::SyntaxSet;
+use std::time::{Duration, Instant};
 
+mod json_progress;
 mod options;
+mod output_template;
 
 fn main() -> anyhow::Result<()> {
-    let mut args: options::Args = clap::Parser::parse();
+    let matches = <options::Args as clap::CommandFactory>::command().get_matches();
+    let mut args: options::Args =
+        clap::FromArgMatches::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
 
     let should_interrupt = Arc::new(AtomicBool::new(false));
     let _ = signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&should_interrupt));
@@ -26,34 +33,398 @@ fn main() -> anyhow::Result<()> {
     }
     .into();
 
-    let render_progress = prodash::render::line(
-        std::io::stderr(),
-        Arc::downgrade(&progress),
-        prodash::render::line::Options {
-            frames_per_second: 24.0,
-            initial_delay: None,
-            timestamp: false,
-            throughput: true,
-            hide_cursor: true,
-            level_filter: Some(0..=2),
-            ..prodash::render::line::Options::default()
-        }
-        .auto_configure(prodash::render::line::StreamKind::Stderr),
-    );
+    let render_progress = match args.progress_format {
+        options::ProgressFormat::Human => {
+            // this also fits the progress to the width of the terminal, and only colors it if it's a terminal.
+            let mut line_options = prodash::render::line::Options {
+                frames_per_second: 24.0,
+                initial_delay: None,
+                timestamp: args.watch,
+                throughput: true,
+                hide_cursor: true,
+                level_filter: Some(0..=2),
+                ..prodash::render::line::Options::default()
+            }
+            .auto_configure(prodash::render::line::StreamKind::Stderr);
+            line_options.colored &= !no_color();
+            ProgressRenderer::Human(prodash::render::line(
+                std::io::stderr(),
+                Arc::downgrade(&progress),
+                line_options,
+            ))
+        }
+        options::ProgressFormat::Json => ProgressRenderer::Json(json_progress::render(
+            std::io::stderr(),
+            Arc::downgrade(&progress),
+            Duration::from_millis(250),
+        )),
+    };
+
+    // load syntaxes and themes once, and determine themes to render files with
+    let mut renderer = codevis::Renderer::new();
+    if args.all_themes {
+        args.theme = renderer
+            .theme_set()
+            .themes
+            .keys()
+            .map(ToOwned::to_owned)
+            .collect();
+    }
+    if args.theme_from_terminal {
+        let terminal = progress.add_child("terminal");
+        match terminal_colors() {
+            Some((colors, source)) => {
+                terminal.info(format!("Highlighting with the colors of the terminal from {source}"));
+                renderer.add_theme(codevis::TERMINAL_THEME, codevis::terminal_theme(&colors));
+                args.theme = vec![codevis::TERMINAL_THEME.into()];
+            }
+            None => terminal.info(format!(
+                "Could not find the 16 colors of the terminal in ${TERMINAL_COLORS_VAR} or the colors of pywal, using {} instead",
+                args.theme.join(", ")
+            )),
+        }
+    }
+    if let Some(message) = line_height_message(&args, &matches) {
+        progress.add_child("image").info(message);
+    }
+    if args.fit_to_screen {
+        let (aspect, message) = screen_aspect(screen_size());
+        progress.add_child("screen").info(message);
+        args.aspect = aspect;
+    }
+    if let Some(template) = &args.output_template {
+        anyhow::ensure!(
+            args.theme.len() == 1 || template.has_theme(),
+            "--output-template must contain {{theme}} to render multiple themes into their own files"
+        );
+    }
+
+    let background_image = args
+        .background_image
+        .as_ref()
+        .map(|path| {
+            image::open(path)
+                .map(|img| img.to_rgb8())
+                .with_context(|| format!("Failed to load background image {path:?}"))
+        })
+        .transpose()?;
+
+    let files = args
+        .files_from0
+        .as_ref()
+        .map(|path| {
+            let input = if path == Path::new("-") {
+                let mut input = Vec::new();
+                std::io::Read::read_to_end(&mut std::io::stdin(), &mut input).map(|_| input)
+            } else {
+                std::fs::read(path)
+            };
+            input
+                .map(|input| codevis::nul_separated_paths(&input))
+                .with_context(|| format!("Failed to read the list of files from {path:?}"))
+        })
+        .transpose()?;
+
+    if !args.concat.is_empty() {
+        concat(&args, &progress)?;
+    } else if args.watch {
+        watch(
+            &args,
+            &progress,
+            &should_interrupt,
+            &renderer,
+            background_image.as_ref(),
+            files.as_deref(),
+        )?;
+    } else if args.bench {
+        bench(
+            &args,
+            &progress,
+            &should_interrupt,
+            &renderer,
+            background_image.as_ref(),
+            files.as_deref(),
+        )?;
+    } else {
+        render(
+            &args,
+            &progress,
+            &should_interrupt,
+            &renderer,
+            background_image.as_ref(),
+            files.as_deref(),
+            None,
+            &OutputPaths::default(),
+        )?;
+    }
+
+    render_progress.shutdown_and_wait();
+    Ok(())
+}
+
+/// The height in pixels of each line with `--readable`, which is the height of its glyphs.
+const READABLE_LINE_HEIGHT: u32 = 16;
+
+/// A warning about an explicit `--line-height-pixels` that is too short for the detail of glyphs, or that is
+/// ignored with `--readable`, unless `--quiet` is set.
+fn line_height_message(args: &options::Args, matches: &clap::ArgMatches) -> Option<String> {
+    let line_height = args.line_height_pixels;
+    let explicit =
+        matches.value_source("line_height_pixels") != Some(clap::parser::ValueSource::DefaultValue);
+    if args.quiet || !explicit {
+        return None;
+    }
+    if args.readable {
+        (line_height != READABLE_LINE_HEIGHT).then(|| {
+            format!(
+                "Ignoring --line-height-pixels {line_height} as the glyphs of --readable are always {READABLE_LINE_HEIGHT} pixels tall"
+            )
+        })
+    } else {
+        (line_height < READABLE_LINE_HEIGHT).then(|| {
+            format!(
+                "Lines are {line_height} pixels tall while glyphs are {READABLE_LINE_HEIGHT}, so characters are drawn as blocks without their detail. Use --readable to draw their glyphs, or --quiet to silence this"
+            )
+        })
+    }
+}
+
+/// The variable that can hold the 16 ANSI colors of the terminal for `--theme-from-terminal`.
+const TERMINAL_COLORS_VAR: &str = "CODEVIS_TERMINAL_COLORS";
+
+/// Return the 16 ANSI colors of the terminal along with where they are from, which is [`TERMINAL_COLORS_VAR`]
+/// or else the colors `pywal` last applied, or `None` if neither is set or valid.
+fn terminal_colors() -> Option<([image::Rgb<u8>; 16], String)> {
+    if let Some(colors) = std::env::var(TERMINAL_COLORS_VAR)
+        .ok()
+        .filter(|colors| !colors.is_empty())
+    {
+        return codevis::parse_ansi_colors(&colors)
+            .map(|colors| (colors, format!("${TERMINAL_COLORS_VAR}")));
+    }
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    let path = cache_dir.join("wal").join("colors");
+    let colors = std::fs::read_to_string(&path).ok()?;
+    codevis::parse_ansi_colors(&colors).map(|colors| (colors, path.display().to_string()))
+}
+
+/// Arrange the images of `--concat` in a grid and save it like a rendered image.
+fn concat(args: &options::Args, progress: &prodash::Tree) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let cells = args
+        .concat
+        .iter()
+        .map(|path| {
+            let img = image::open(path)
+                .with_context(|| format!("Failed to load image {path:?}"))?
+                .into_rgb8();
+            let label = args
+                .concat_labels
+                .then(|| {
+                    path.file_name()
+                        .unwrap_or(path.as_os_str())
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .unwrap_or_default();
+            Ok((label, img))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let img = match args.concat_cols {
+        Some(columns) => codevis::render::grid_with_columns(&cells, columns),
+        None => codevis::render::grid(&cells),
+    };
+    if !args.no_write {
+        sage_image(
+            &img,
+            &args.output_path,
+            args.format,
+            args.bit_depth,
+            progress.add_child("saving concatenated images"),
+        )?;
+    }
+    if args.print_hash {
+        println!(
+            "{}  {}",
+            image_hash(&img).to_hex(),
+            args.output_path.display()
+        );
+    }
+    if args.open {
+        open::that(&args.output_path)?;
+    }
+    progress.add_child("operation").done(format!(
+        "concatenated {} images into {:?} in {:.02}s",
+        cells.len(),
+        args.output_path,
+        start.elapsed().as_secs_f32()
+    ));
+    Ok(())
+}
+
+/// Return `true` if `NO_COLOR` is set to anything but an empty string, which asks for output without colors.
+fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").map_or(false, |value| !value.is_empty())
+}
+
+/// The aspect `--fit-to-screen` uses if there is no screen.
+const FALLBACK_SCREEN_ASPECT: (f64, f64) = (16.0, 9.0);
+
+/// Return the aspect of `screen` for `--fit-to-screen`, or [`FALLBACK_SCREEN_ASPECT`] if there is none, along with
+/// a message saying which one it is.
+fn screen_aspect(screen: Option<(u32, u32)>) -> ((f64, f64), String) {
+    match screen {
+        Some((width, height)) => (
+            (width as f64, height as f64),
+            format!("Fitting the image to the {width}x{height} screen"),
+        ),
+        None => (
+            FALLBACK_SCREEN_ASPECT,
+            "Could not find a screen, fitting the image to 16:9 instead".into(),
+        ),
+    }
+}
+
+/// Return the size in pixels of the primary display, or of the first one if none is primary, or `None` if there is
+/// no display to be found.
+#[cfg(feature = "display-info")]
+fn screen_size() -> Option<(u32, u32)> {
+    let displays = display_info::DisplayInfo::all().ok()?;
+    displays
+        .iter()
+        .find(|display| display.is_primary)
+        .or(displays.first())
+        .map(|display| (display.width, display.height))
+        .filter(|&(width, height)| width > 0 && height > 0)
+}
+
+/// Without the `display-info` feature there is no way to find a display.
+#[cfg(not(feature = "display-info"))]
+fn screen_size() -> Option<(u32, u32)> {
+    None
+}
+
+/// The way progress is shown on stderr.
+enum ProgressRenderer {
+    Human(prodash::render::line::JoinHandle),
+    Json(json_progress::JoinHandle),
+}
+
+impl ProgressRenderer {
+    fn shutdown_and_wait(self) {
+        match self {
+            ProgressRenderer::Human(handle) => handle.shutdown_and_wait(),
+            ProgressRenderer::Json(handle) => handle.shutdown_and_wait(),
+        }
+    }
+}
+
+/// The amount of work done by [`render()`].
+#[derive(Default)]
+struct Rendered {
+    lines: usize,
+    pixels: u64,
+}
+
+/// The absolute paths of all files [`render()`] wrote, so [`watch()`] can tell them from changed input files.
+#[derive(Default)]
+struct OutputPaths(std::sync::Mutex<HashSet<PathBuf>>);
+
+impl OutputPaths {
+    /// Remember `path` as one of ours, which has to happen before it's written so the change is known to be ours.
+    fn insert(&self, path: &Path) {
+        self.0
+            .lock()
+            .expect("no panics while locked")
+            .insert(absolute_path(path));
+    }
 
+    /// Return `true` if `path`, which must be absolute, was inserted before.
+    fn contains(&self, path: &Path) -> bool {
+        self.0
+            .lock()
+            .expect("no panics while locked")
+            .contains(path)
+    }
+}
+
+/// Return `path` as absolute path with symlinks of its directory resolved, like the paths of file system events are,
+/// even if the file itself doesn't exist yet.
+fn absolute_path(path: &Path) -> PathBuf {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    match (dir.canonicalize(), path.file_name()) {
+        (Ok(dir), Some(file_name)) => dir.join(file_name),
+        _ => std::path::absolute(path).unwrap_or_else(|_| path.to_owned()),
+    }
+}
+
+/// Collect all files in the input directory and render them once for each theme.
+///
+/// If `phase_timer` is set, the time spent in each phase is added to it. The paths of all files are added to
+/// `outputs` before they are written.
+#[allow(clippy::too_many_arguments)]
+fn render(
+    args: &options::Args,
+    progress: &Arc<prodash::Tree>,
+    should_interrupt: &AtomicBool,
+    renderer: &codevis::Renderer,
+    background_image: Option<&RgbImage>,
+    files: Option<&[PathBuf]>,
+    phase_timer: Option<&PhaseTimer>,
+    outputs: &OutputPaths,
+) -> anyhow::Result<Rendered> {
     // determine files to render
-    let (mut dir_contents, mut ignored) = codevis::unicode_content(
-        &args.input_dir,
-        &args.ignore_extension,
-        progress.add_child("search unicode files"),
-        &should_interrupt,
-    )
-    .with_context(|| {
-        format!(
-            "Failed to find input files in {:?} directory",
-            args.input_dir
+    let scan_start = Instant::now();
+    let read_options = codevis::ReadOptions {
+        ignore_extensions: &args.ignore_extension,
+        ignore_file: args.ignore_file.as_deref(),
+        hidden: args.hidden,
+        normalize_eol: args.normalize_eol,
+        modified_since: args
+            .modified_since
+            .and_then(|window| std::time::SystemTime::now().checked_sub(window)),
+        binary_as_hexdump: args.binary_as_hexdump,
+    };
+    let (mut dir_contents, mut ignored) = match (&args.archive, files) {
+        (Some(archive), _) => codevis::unicode_content_of_archive(
+            archive,
+            read_options,
+            progress.add_child("read unicode files from archive"),
+            should_interrupt,
         )
-    })?;
+        .with_context(|| format!("Failed to read input files from {archive:?} archive"))?,
+        (None, Some(files)) => codevis::unicode_content_of(
+            args.input_dir(),
+            files,
+            read_options,
+            progress.add_child("read listed unicode files"),
+            should_interrupt,
+        )
+        .with_context(|| {
+            format!(
+                "Failed to find input files in {:?} directory",
+                args.input_dir()
+            )
+        })?,
+        (None, None) => codevis::unicode_content(
+            args.input_dir(),
+            read_options,
+            progress.add_child("search unicode files"),
+            should_interrupt,
+        )
+        .with_context(|| {
+            format!(
+                "Failed to find input files in {:?} directory",
+                args.input_dir()
+            )
+        })?,
+    };
 
     // filter extensions if there is a whitelist
     if !args.whitelist_extension.is_empty() {
@@ -71,84 +442,408 @@ fn main() -> anyhow::Result<()> {
         ignored = whitelist_ignored;
     }
 
+    if let Some(regex) = &args.grep_only {
+        ignored += dir_contents.keep_matching_lines(regex);
+    }
+    let collapsed = args.collapse_blank_lines.map_or(0, |max_blank_lines| {
+        dir_contents.collapse_blank_lines(max_blank_lines)
+    });
+    let (truncated_files, removed_lines) = args
+        .max_file_lines
+        .map_or((0, 0), |max_lines| dir_contents.truncate_files(max_lines));
+    let control_chars = dir_contents.count_control_chars();
+
     dir_contents
         .children_content
         .sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
 
+    // sample after sorting, so the same files are chosen no matter the order they were found in.
+    let sample_seed = (args.sample_mode == options::SampleMode::Random)
+        .then(|| args.seed.unwrap_or_else(random_seed));
+    let sampled = args.sample_files.map(|num_files| {
+        let total = dir_contents.children_content.len();
+        (
+            total - dir_contents.sample_files(num_files, sample_seed),
+            total,
+        )
+    });
+    // counting lines is part of scanning, as they are the same for all themes.
+    let lines = dir_contents
+        .children_content
+        .iter()
+        .map(|(_, content)| content.lines().count())
+        .sum::<usize>();
+
+    if let Some(phase_timer) = phase_timer {
+        phase_timer.add(Phase::Scan, scan_start.elapsed());
+    }
+
     // log num ignored files
     if ignored != 0 {
         progress.add_child("input").info(format!(
-            "Ignored {ignored} files that matched ignored extensions"
+            "Ignored {ignored} files that matched ignored extensions or patterns"
+        ));
+    }
+    if collapsed != 0 {
+        progress
+            .add_child("input")
+            .info(format!("Collapsed {collapsed} blank lines"));
+    }
+    if control_chars != 0 {
+        progress.add_child("input").info(if args.strip_control {
+            format!("Leaving out {control_chars} control characters")
+        } else {
+            format!("Drawing {control_chars} control characters as a placeholder")
+        });
+    }
+    if let Some((kept, total)) = sampled {
+        progress.add_child("input").info(format!(
+            "Sampled {kept} of {total} files ({:.1}%){}",
+            kept as f32 / total.max(1) as f32 * 100.0,
+            sample_seed
+                .map(|seed| format!(" with --seed {seed}"))
+                .unwrap_or_default()
+        ));
+    }
+    if truncated_files != 0 {
+        progress.add_child("input").info(format!(
+            "Truncated {truncated_files} files by {removed_lines} lines in total to at most {} lines each",
+            args.max_file_lines.unwrap_or_default()
         ));
     }
 
-    // determine themes to render files with
-    let ts = ThemeSet::load_defaults();
-    if args.all_themes {
-        args.theme = ts.themes.keys().map(ToOwned::to_owned).collect();
+    let line_changes = args
+        .since_git_rev
+        .as_deref()
+        .map(|since| {
+            codevis::LineChanges::from_git(args.input_dir(), since, args.to_git_rev.as_deref())
+        })
+        .transpose()
+        .context("Failed to find changed lines")?;
+    let line_authors = args
+        .blame_tint
+        .then(|| {
+            let relative_paths = dir_contents
+                .children_content
+                .iter()
+                .map(|(path, _)| dir_contents.relative_path(path))
+                .collect::<Vec<_>>();
+            codevis::LineAuthors::from_git(
+                args.input_dir(),
+                &relative_paths,
+                args.threads,
+                should_interrupt,
+            )
+        })
+        .transpose()
+        .context("Failed to find the authors of lines")?;
+    if let Some(line_authors) = line_authors.as_ref().filter(|_| args.blame_legend) {
+        let legend = progress.add_child("blame");
+        for (name, color, lines) in line_authors.legend() {
+            legend.info(format!(
+                "#{:02x}{:02x}{:02x} {name}: {lines} lines",
+                color[0], color[1], color[2]
+            ));
+        }
     }
 
-    let ss = SyntaxSet::load_defaults_newlines();
-    for theme in &args.theme {
-        let start = std::time::Instant::now();
+    // shuffle the same way for all themes, and tell how to shuffle like this again.
+    let shuffle_seed = args
+        .shuffle_files
+        .then(|| args.seed.unwrap_or_else(random_seed));
+    if let Some(seed) = shuffle_seed {
+        progress
+            .add_child("input")
+            .info(format!("Shuffling files with --seed {seed}"));
+    }
 
-        let img = codevis::render(
-            &dir_contents,
-            progress.add_child("render"),
-            &should_interrupt,
-            &ss,
-            &ts,
-            codevis::render::Options {
-                column_width: args.column_width_pixels,
-                line_height: args.line_height_pixels,
-                readable: args.readable,
-                show_filenames: args.show_filenames,
-                target_aspect_ratio: args.aspect_width / args.aspect_height,
-                threads: args.threads,
-                highlight_truncated_lines: args.highlight_truncated_lines,
-                force_full_columns: !args.dont_force_full_columns,
-                plain: args.force_plain_syntax,
-                display_to_be_processed_file: args.display_to_be_processed_file,
-                theme,
-                fg_color: if args.readable {
-                    codevis::render::FgColor::Style
-                } else {
-                    args.fg_pixel_color
-                },
-                bg_color: args.bg_pixel_color,
-                color_modulation: args.color_modulation,
-                ignore_files_without_syntax: args.ignore_files_without_syntax,
-                tab_spaces: args.tab_spaces,
-                line_nums: args.line_nums,
+    let themes = if args.compare_themes.is_empty() {
+        &args.theme
+    } else {
+        &args.compare_themes
+    };
+    let mut rendered = Rendered::default();
+    // the scaled down images of `--compare-themes` along with their theme.
+    let mut grid_cells = Vec::new();
+    for theme in themes {
+        let start = Instant::now();
+
+        let defaults = codevis::render::Options::default();
+        let opts = codevis::render::Options {
+            column_width: args.column_chars,
+            line_height: args.line_height_pixels,
+            readable: args.readable,
+            show_filenames: args.show_filenames,
+            target_aspect_ratio: args.aspect_ratio(),
+            threads: args.threads,
+            highlight_truncated_lines: args.highlight_truncated_lines,
+            force_full_columns: !args.dont_force_full_columns,
+            plain: args.force_plain_syntax,
+            display_to_be_processed_file: args.display_to_be_processed_file,
+            theme,
+            theme_for_extension: &args.theme_for,
+            fg_color: match args.fg_hex {
+                Some(color) => codevis::render::FgColor::Fixed(color),
+                None if args.readable => codevis::render::FgColor::Style,
+                None => args.fg_pixel_color,
             },
-        )?;
+            bg_color: args.bg_pixel_color,
+            color_modulation: args.color_modulation,
+            ignore_files_without_syntax: args.ignore_files_without_syntax,
+            tab_spaces: args.tab_spaces,
+            line_nums: args.line_nums,
+            strip_control: args.strip_control,
+            background_image,
+            bg_fit: args.bg_fit,
+            grayscale: args.grayscale,
+            rtl_columns: args.rtl_columns,
+            cache_dir: args.cache_dir.as_deref(),
+            target_width: args.target_width,
+            file_summary: args.file_summary,
+            max_columns: args.max_columns,
+            highlight_trailing_whitespace: args.highlight_trailing_whitespace,
+            length_gradient: args.length_gradient.then_some(args.gradient_threshold),
+            phase_timer,
+            canvas_color: args.canvas_color,
+            exact_aspect: args.exact_aspect,
+            pad: args.pad,
+            uniform_file_height: args.uniform_file_height,
+            indent_by_depth: args.indent_by_depth.then_some(args.indent_cells),
+            render_order: args.render_order,
+            comment_opacity: args.comment_opacity,
+            only: args.only,
+            missing_glyph: args.missing_glyph,
+            letterbox_color: args.letterbox_color,
+            highlight_files: &args.highlight_file,
+            highlight_color: args
+                .highlight_color_or(args.highlight_file_color, defaults.highlight_color),
+            highlight_border_width: args.highlight_border_width,
+            max_memory: args.max_memory.map(|max_memory| max_memory.as_u64()),
+            shuffle_seed,
+            line_spacing: args.line_spacing,
+            display_rate_limit: (args.display_files_per_second != 0)
+                .then_some(args.display_files_per_second),
+            mono: args.mono.then_some((args.mono_fg, args.mono_bg)),
+            auto_column_width: (args.column_chars == 0).then_some(args.max_auto_width),
+            grep: args.grep.as_ref(),
+            grep_color: args.highlight_color_or(args.grep_color, defaults.grep_color),
+            grep_strength: args.highlight_strength_or(args.grep_strength, defaults.grep_strength),
+            min_column_lines: args.min_column_lines,
+            fg_boost: args.fg_boost,
+            border: args.border,
+            border_color: args.border_color,
+            shadow: args.shadow,
+            shadow_backdrop: args.shadow_backdrop,
+            badge: args.badge,
+            no_split_files: args.no_split_files,
+            strip_leading_indentation: args.strip_leading_indentation,
+            blend_space: args.blend_space,
+            tab_mode: args.tab_mode(),
+            glow_radius: if args.glow { args.glow_radius } else { 0 },
+            glow_intensity: args.glow_intensity,
+            uniform_background: args.uniform_background,
+            line_changes: line_changes.as_ref(),
+            line_authors: line_authors.as_ref(),
+            detect_by_content: !args.no_detect_by_content,
+            exclude_empty_columns: args.exclude_empty_columns,
+            width_for_extension: &args.width_for,
+            trailing_whitespace_color: args.highlight_color_or(
+                args.trailing_whitespace_color,
+                defaults.trailing_whitespace_color,
+            ),
+            length_gradient_color: args
+                .highlight_color_or(args.gradient_color, defaults.length_gradient_color),
+            length_gradient_strength: args
+                .highlight_strength_or(args.gradient_strength, defaults.length_gradient_strength),
+            glyph_outline: args.glyph_outline,
+            glyph_outline_color: args.outline_color,
+            deadline: args
+                .max_render_time
+                .map(|max_render_time| scan_start + max_render_time),
+            zebra_columns: args.zebra_columns,
+            zebra_strength: args.zebra_strength,
+            mark_blank_files: args.mark_blank_files,
+        };
+        if let Some(dir) = &args.per_file_output {
+            let theme_suffix = (args.theme.len() > 1).then_some(theme.as_str());
+            let (written, pixels) = render_per_file(
+                &dir_contents,
+                renderer,
+                opts,
+                dir,
+                theme_suffix,
+                args.format,
+                args.bit_depth,
+                progress.add_child("render per file"),
+                should_interrupt,
+                outputs,
+            )?;
+            rendered.lines += lines;
+            rendered.pixels += pixels;
+            progress.add_child("operation").done(format!(
+                "wrote {written} images to {dir:?} in {:.02}s",
+                start.elapsed().as_secs_f32()
+            ));
+            continue;
+        }
+        if !args.compare_themes.is_empty() {
+            let img = renderer.render(
+                &dir_contents,
+                opts,
+                progress.add_child(format!("render {theme}")),
+                should_interrupt,
+            )?;
+            rendered.lines += lines;
+            let width = img.width().min(args.compare_width);
+            let height = (img.height() as u64 * width as u64 / img.width() as u64).max(1) as u32;
+            grid_cells.push((
+                theme.to_owned(),
+                codevis::render::downscale(&img, width, height, args.downscale_filter),
+            ));
+            continue;
+        }
         let img_path = if args.theme.len() == 1 {
             Cow::Borrowed(&args.output_path)
         } else {
-            // mutate the output filename to include the theme in it.
-            let mut extension = theme.replace(['(', ')'], "").replace(' ', "-");
-            extension.push('.');
-            extension.push_str(
-                args.output_path
-                    .extension()
-                    .and_then(|ext| ext.to_str())
-                    .context("Output paths needs an extension")?,
-            );
-            let theme_specific_path = args.output_path.with_extension(extension);
-            Cow::Owned(theme_specific_path)
+            let extension = args
+                .output_path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .or_else(|| {
+                    args.format
+                        .map(|format| ImageFormat::from(format).extensions_str()[0])
+                })
+                .context("Output paths needs an extension")?;
+            Cow::Owned(theme_specific_path(&args.output_path, theme, extension))
         };
-        sage_image(
-            img,
-            img_path.as_ref(),
+        let save_progress = |img_path: &Path| {
             progress.add_child(format!(
                 "saving {}",
-                img_path
-                    .as_ref()
-                    .file_name()
-                    .and_then(|f| f.to_str())
-                    .unwrap_or("")
-            )),
-        )?;
+                img_path.file_name().and_then(|f| f.to_str()).unwrap_or("")
+            ))
+        };
+        rendered.lines += lines;
+
+        let (img_path, (width, height), hash) = if args.low_memory {
+            if !args.no_write {
+                outputs.insert(img_path.as_ref());
+            }
+            let (dimensions, hash) = save_png_in_bands(
+                &dir_contents,
+                progress.add_child("render"),
+                should_interrupt,
+                renderer,
+                opts,
+                (!args.no_write).then_some(img_path.as_ref()),
+                args.print_hash,
+                save_progress(img_path.as_ref()),
+            )?;
+            (img_path, dimensions, hash)
+        } else {
+            let img = renderer.render(
+                &dir_contents,
+                opts,
+                progress.add_child("render"),
+                should_interrupt,
+            )?;
+            let cropped = args
+                .crop
+                .map(|region| codevis::render::crop(&img, region))
+                .transpose()?;
+            // the template can only be expanded now that the size of the saved image is known.
+            let img_path = match &args.output_template {
+                Some(template) => {
+                    let (width, height) = cropped
+                        .as_ref()
+                        .map_or(img.dimensions(), |cropped| cropped.dimensions());
+                    Cow::Owned(template.expand(&output_template::Values {
+                        theme,
+                        width,
+                        height,
+                        lines,
+                        files: dir_contents.children_content.len(),
+                    }))
+                }
+                None => img_path,
+            };
+
+            let hash = args.print_hash.then(|| match &cropped {
+                Some(cropped) => image_hash(cropped),
+                None => image_hash(&img),
+            });
+            if !args.no_write {
+                let save_start = Instant::now();
+                let save_progress = save_progress(img_path.as_ref());
+                if args.split_output.is_none() {
+                    outputs.insert(img_path.as_ref());
+                }
+                match (&cropped, args.split_output) {
+                    (Some(cropped), None) => sage_image(
+                        cropped,
+                        img_path.as_ref(),
+                        args.format,
+                        args.bit_depth,
+                        save_progress,
+                    )?,
+                    (None, None) => sage_image(
+                        &img,
+                        img_path.as_ref(),
+                        args.format,
+                        args.bit_depth,
+                        save_progress,
+                    )?,
+                    (Some(cropped), Some(grid)) => save_tiles(
+                        cropped,
+                        img_path.as_ref(),
+                        grid,
+                        args.format,
+                        args.bit_depth,
+                        save_progress,
+                        outputs,
+                    )?,
+                    (None, Some(grid)) => save_tiles(
+                        &img,
+                        img_path.as_ref(),
+                        grid,
+                        args.format,
+                        args.bit_depth,
+                        save_progress,
+                        outputs,
+                    )?,
+                }
+                if let Some(phase_timer) = phase_timer {
+                    phase_timer.add(Phase::Save, save_start.elapsed());
+                }
+            }
+            if let Some(palette_path) = &args.export_palette {
+                let palette_path = if args.theme.len() == 1 {
+                    Cow::Borrowed(palette_path)
+                } else {
+                    let extension = palette_path
+                        .extension()
+                        .and_then(|ext| ext.to_str())
+                        .unwrap_or("json");
+                    Cow::Owned(theme_specific_path(palette_path, theme, extension))
+                };
+                outputs.insert(palette_path.as_ref());
+                let (palette, (width, height)) = match &cropped {
+                    Some(cropped) => (codevis::render::palette(cropped), cropped.dimensions()),
+                    None => (codevis::render::palette(&img), img.dimensions()),
+                };
+                export_palette(
+                    palette,
+                    width as u64 * height as u64,
+                    palette_path.as_ref(),
+                    args.palette_size,
+                    progress.add_child("palette"),
+                )?;
+            }
+            (img_path, img.dimensions(), hash)
+        };
+        rendered.pixels += width as u64 * height as u64;
+        if let Some(hash) = hash {
+            println!("{}  {}", hash.to_hex(), img_path.display());
+        }
 
         if args.open {
             progress
@@ -158,41 +853,862 @@ fn main() -> anyhow::Result<()> {
         }
         progress.add_child("operation").done(format!(
             "done in {:.02}s",
-            std::time::Instant::now()
+            Instant::now()
                 .checked_duration_since(start)
                 .unwrap_or_default()
                 .as_secs_f32()
         ));
     }
 
-    render_progress.shutdown_and_wait();
+    if !grid_cells.is_empty() {
+        let img = codevis::render::grid(&grid_cells);
+        if !args.no_write {
+            let save_start = Instant::now();
+            outputs.insert(&args.output_path);
+            sage_image(
+                &img,
+                &args.output_path,
+                args.format,
+                args.bit_depth,
+                progress.add_child("saving theme comparison"),
+            )?;
+            if let Some(phase_timer) = phase_timer {
+                phase_timer.add(Phase::Save, save_start.elapsed());
+            }
+        }
+        rendered.pixels += img.width() as u64 * img.height() as u64;
+        if args.print_hash {
+            println!(
+                "{}  {}",
+                image_hash(&img).to_hex(),
+                args.output_path.display()
+            );
+        }
+        if args.open {
+            open::that(&args.output_path)?;
+        }
+        progress.add_child("operation").done(format!(
+            "compared {} themes in {:?}",
+            grid_cells.len(),
+            args.output_path
+        ));
+    }
+
+    if let Some(report_path) = &args.report {
+        outputs.insert(report_path);
+        write_report(
+            report_path,
+            &dir_contents,
+            ignored,
+            &rendered,
+            renderer.syntax_set(),
+            !args.no_detect_by_content,
+        )
+        .with_context(|| format!("Failed to write report to {report_path:?}"))?;
+        progress
+            .add_child("report")
+            .done(format!("Wrote report to {report_path:?}"));
+    }
+
+    Ok(rendered)
+}
+
+/// Write the totals of `dir_contents` and the pixels of all images that were `rendered` as JSON to `path`, followed by the lines, bytes and syntax
+/// of each file.
+///
+/// Files are written one by one, so the report of a large directory doesn't have to fit into memory at once.
+fn write_report(
+    path: &Path,
+    dir_contents: &codevis::DirContents,
+    ignored: usize,
+    rendered: &Rendered,
+    syntax_set: &syntect::parsing::SyntaxSet,
+    detect_by_content: bool,
+) -> anyhow::Result<()> {
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let summary = serde_json::json!({
+        "files": dir_contents.children_content.len(),
+        "ignored_files": ignored,
+        "bytes": dir_contents
+            .children_content
+            .iter()
+            .map(|(_, content)| content.len())
+            .sum::<usize>(),
+        "lines": dir_contents
+            .children_content
+            .iter()
+            .map(|(_, content)| content.lines().count())
+            .sum::<usize>(),
+        "pixels": rendered.pixels,
+    });
+    write!(out, "{{\"summary\":{summary},\"files\":[")?;
+    for (index, (path, content)) in dir_contents.children_content.iter().enumerate() {
+        if index != 0 {
+            out.write_all(b",")?;
+        }
+        let file = serde_json::json!({
+            "path": dir_contents.relative_path(path).display().to_string(),
+            "lines": content.lines().count(),
+            "bytes": content.len(),
+            "syntax": codevis::render::find_syntax(syntax_set, path, content, detect_by_content)
+                .map(|syntax| syntax.name.as_str()),
+        });
+        serde_json::to_writer(&mut out, &file)?;
+    }
+    out.write_all(b"]}\n")?;
+    out.flush()?;
     Ok(())
 }
 
-fn sage_image(
-    img: ImageBuffer<Rgb<u8>, MmapMut>,
-    img_path: &Path,
-    mut progress: impl prodash::Progress,
+/// Render `--bench-iters` times and print the minimum, median and maximum time spent in each phase.
+fn bench(
+    args: &options::Args,
+    progress: &Arc<prodash::Tree>,
+    should_interrupt: &AtomicBool,
+    renderer: &codevis::Renderer,
+    background_image: Option<&RgbImage>,
+    files: Option<&[PathBuf]>,
 ) -> anyhow::Result<()> {
-    let start = std::time::Instant::now();
-    progress.init(
-        Some(img.width() as usize * img.height() as usize * 3),
+    let mut runs = Vec::new();
+    let mut rendered = Rendered::default();
+    for _ in 0..args.bench_iters {
+        let phase_timer = PhaseTimer::default();
+        let start = Instant::now();
+        rendered = render(
+            args,
+            progress,
+            should_interrupt,
+            renderer,
+            background_image,
+            files,
+            Some(&phase_timer),
+            &OutputPaths::default(),
+        )?;
+        runs.push((start.elapsed(), phase_timer));
+    }
+
+    let min_median_max = |mut durations: Vec<Duration>| {
+        durations.sort();
+        (
+            durations[0],
+            durations[durations.len() / 2],
+            durations[durations.len() - 1],
+        )
+    };
+    let print_row = |name: &str, (min, median, max): (Duration, Duration, Duration)| {
+        println!(
+            "{name:<10} {:>9.3}s {:>9.3}s {:>9.3}s",
+            min.as_secs_f64(),
+            median.as_secs_f64(),
+            max.as_secs_f64()
+        );
+    };
+    println!(
+        "{:<10} {:>10} {:>10} {:>10}",
+        "phase", "min", "median", "max"
+    );
+    for phase in Phase::ALL {
+        print_row(
+            phase.name(),
+            min_median_max(
+                runs.iter()
+                    .map(|(_, phase_timer)| phase_timer.elapsed(phase))
+                    .collect(),
+            ),
+        );
+    }
+    let total = min_median_max(runs.iter().map(|(total, _)| *total).collect());
+    print_row("total", total);
+    let median = total.1.as_secs_f64();
+    println!(
+        "{:.0} lines/s, {:.0} pixels/s (median)",
+        rendered.lines as f64 / median,
+        rendered.pixels as f64 / median
+    );
+    Ok(())
+}
+
+/// Render once, then render again whenever files below the input directory change, until interrupted.
+///
+/// A change that arrives while rendering cancels that render in favor of a new one.
+fn watch(
+    args: &options::Args,
+    progress: &Arc<prodash::Tree>,
+    should_interrupt: &AtomicBool,
+    renderer: &codevis::Renderer,
+    background_image: Option<&RgbImage>,
+    files: Option<&[PathBuf]>,
+) -> anyhow::Result<()> {
+    /// How long the input directory has to stay quiet before we render again.
+    const DEBOUNCE: Duration = Duration::from_millis(250);
+
+    // Set when a file changes or the user interrupts, cancelling the current render.
+    let interrupt_render = Arc::new(AtomicBool::new(false));
+    let _ = signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&interrupt_render));
+
+    // Our own output must not trigger another render, which are exactly the files we wrote and the cache.
+    let outputs = Arc::new(OutputPaths::default());
+    let cache_dir = match &args.cache_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            Some(dir.canonicalize()?)
+        }
+        None => None,
+    };
+    let is_own_output = {
+        let outputs = Arc::clone(&outputs);
+        move |path: &Path| {
+            outputs.contains(path)
+                || cache_dir
+                    .as_ref()
+                    .map_or(false, |dir| path.starts_with(dir))
+        }
+    };
+
+    let (tx, rx) = flume::unbounded();
+    let mut watcher = notify::recommended_watcher({
+        let interrupt_render = Arc::clone(&interrupt_render);
+        move |event: notify::Result<notify::Event>| {
+            let is_relevant = event.map_or(true, |event| {
+                !event.kind.is_access() && !event.paths.iter().all(|path| is_own_output(path))
+            });
+            if is_relevant {
+                interrupt_render.store(true, Ordering::Relaxed);
+                tx.send(()).ok();
+            }
+        }
+    })?;
+    watcher
+        .watch(&args.input_dir().canonicalize()?, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {:?} for changes", args.input_dir()))?;
+
+    let watch_progress = progress.add_child("watch");
+    loop {
+        interrupt_render.store(false, Ordering::Relaxed);
+        if should_interrupt.load(Ordering::Relaxed) {
+            break;
+        }
+        let start = Instant::now();
+        match render(
+            args,
+            progress,
+            &interrupt_render,
+            renderer,
+            background_image,
+            files,
+            None,
+            &outputs,
+        ) {
+            Ok(_) => watch_progress.info(format!(
+                "rendered in {:.02}s",
+                start.elapsed().as_secs_f32()
+            )),
+            Err(_) if interrupt_render.load(Ordering::Relaxed) => {
+                if should_interrupt.load(Ordering::Relaxed) {
+                    break;
+                }
+                watch_progress.info("a file changed, restarting");
+            }
+            Err(err) => watch_progress.fail(format!("{err:#}")),
+        }
+
+        // wait for the next change, and for the input directory to settle afterwards.
+        loop {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(()) => break,
+                Err(flume::RecvTimeoutError::Timeout) => {
+                    if should_interrupt.load(Ordering::Relaxed) {
+                        return Ok(());
+                    }
+                }
+                Err(flume::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+    }
+    Ok(())
+}
+
+/// Render each file of `dir_contents` to its own image at its path relative to the input directory below `dir`,
+/// with the theme name and the image extension appended, and return the amount of images and their total pixels.
+///
+/// Files are rendered in parallel with the threads configured in `opts`, each of them with the same aspect ratio.
+/// Files without a single line are skipped.
+#[allow(clippy::too_many_arguments)]
+fn render_per_file(
+    dir_contents: &codevis::DirContents,
+    renderer: &codevis::Renderer,
+    opts: codevis::render::Options<'_>,
+    dir: &Path,
+    theme_suffix: Option<&str>,
+    format: Option<options::Format>,
+    bit_depth: codevis::BitDepth,
+    mut progress: impl prodash::Progress,
+    should_interrupt: &AtomicBool,
+    outputs: &OutputPaths,
+) -> anyhow::Result<(usize, u64)> {
+    use prodash::Progress;
+
+    let extension = format.map_or("png", |format| {
+        ImageFormat::from(format).extensions_str()[0]
+    });
+    let threads = (opts.threads == 0)
+        .then(num_cpus::get)
+        .unwrap_or(opts.threads)
+        .clamp(1, dir_contents.children_content.len().max(1));
+    let next_file = AtomicUsize::default();
+    let written = AtomicUsize::default();
+    let pixels = AtomicU64::default();
+    std::thread::scope(|scope| -> anyhow::Result<()> {
+        let workers = (0..threads)
+            .map(|tid| {
+                let mut progress = progress.add_child(format!("Thread {tid}"));
+                progress.init(None, Some(prodash::unit::label("files")));
+                let (next_file, written, pixels) = (&next_file, &written, &pixels);
+                scope.spawn(move || -> anyhow::Result<()> {
+                    while let Some((path, content)) = dir_contents
+                        .children_content
+                        .get(next_file.fetch_add(1, Ordering::SeqCst))
+                    {
+                        let single_file = codevis::DirContents {
+                            parent_dir: dir_contents.parent_dir.clone(),
+                            children_content: vec![(path.clone(), content.clone())],
+                        };
+                        let img = match renderer.render(
+                            &single_file,
+                            codevis::render::Options { threads: 1, ..opts },
+                            prodash::progress::Discard,
+                            should_interrupt,
+                        ) {
+                            Ok(img) => img,
+                            Err(codevis::RenderError::NoLines { .. }) => {
+                                progress.inc();
+                                continue;
+                            }
+                            Err(err) => return Err(err.into()),
+                        };
+
+                        // only keep the normal components so nothing is written outside of `dir`.
+                        let relative_path = dir_contents
+                            .relative_path(path)
+                            .components()
+                            .filter(|component| {
+                                matches!(component, std::path::Component::Normal(_))
+                            })
+                            .collect::<PathBuf>();
+                        let mut file_name = relative_path.into_os_string();
+                        if let Some(theme) = theme_suffix {
+                            file_name.push(".");
+                            file_name.push(theme.replace(['(', ')'], "").replace(' ', "-"));
+                        }
+                        file_name.push(".");
+                        file_name.push(extension);
+                        let img_path = dir.join(file_name);
+                        if let Some(parent) = img_path.parent() {
+                            std::fs::create_dir_all(parent).with_context(|| {
+                                format!("Failed to create directory {parent:?}")
+                            })?;
+                        }
+                        outputs.insert(&img_path);
+                        sage_image(
+                            &img,
+                            &img_path,
+                            format,
+                            bit_depth,
+                            prodash::progress::Discard,
+                        )?;
+
+                        written.fetch_add(1, Ordering::Relaxed);
+                        pixels
+                            .fetch_add(img.width() as u64 * img.height() as u64, Ordering::Relaxed);
+                        progress.inc();
+                    }
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            worker.join().expect("no panics in workers")?;
+        }
+        Ok(())
+    })?;
+
+    let written = written.into_inner();
+    let skipped = dir_contents.children_content.len() - written;
+    if skipped != 0 {
+        progress.info(format!("Skipped {skipped} files without lines"));
+    }
+    Ok((written, pixels.into_inner()))
+}
+
+/// A seed that differs from run to run, derived from the current time.
+fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+/// The amount of lines of all columns to render at a time with `--low-memory`.
+const LOW_MEMORY_BAND_LINES: u32 = 256;
+
+/// Render like [`codevis::Renderer::render()`], but encode the image as PNG at `img_path` band by band while it's rendered,
+/// and return its dimensions.
+///
+/// If `print_hash` is set, the image is hashed band by band like [`image_hash()`] as well, and its hash is returned,
+/// so nothing needs to be written if `img_path` is `None`.
+/// The time spent encoding is added to the save phase of the `phase_timer` in `opts`.
+#[allow(clippy::too_many_arguments)]
+fn save_png_in_bands(
+    dir_contents: &codevis::DirContents,
+    progress: impl prodash::Progress,
+    should_interrupt: &AtomicBool,
+    renderer: &codevis::Renderer,
+    opts: codevis::render::Options<'_>,
+    img_path: Option<&Path>,
+    print_hash: bool,
+    mut save_progress: impl prodash::Progress,
+) -> anyhow::Result<((u32, u32), Option<blake3::Hash>)> {
+    let mut out = img_path
+        .map(|img_path| {
+            std::fs::File::create(img_path)
+                .map(std::io::BufWriter::new)
+                .with_context(|| format!("Failed to create {img_path:?}"))
+        })
+        .transpose()?;
+    let mut writer = None;
+    let mut hasher = None;
+    save_progress.init(
+        None,
         Some(prodash::unit::dynamic_and_mode(
             prodash::unit::Bytes,
             prodash::unit::display::Mode::with_throughput(),
         )),
     );
+    let dimensions = renderer.render_in_bands(
+        dir_contents,
+        opts,
+        progress,
+        should_interrupt,
+        LOW_MEMORY_BAND_LINES,
+        |(width, height), band| {
+            if print_hash {
+                hasher
+                    .get_or_insert_with(|| image_hasher((width, height)))
+                    .update(band.as_raw());
+            }
+            if img_path.is_none() {
+                return Ok(());
+            }
+            let start = Instant::now();
+            let writer = match writer.as_mut() {
+                Some(writer) => writer,
+                None => {
+                    let mut encoder =
+                        png::Encoder::new(out.take().expect("only taken once"), width, height);
+                    encoder.set_color(png::ColorType::Rgb);
+                    encoder.set_depth(png::BitDepth::Eight);
+                    writer.insert(encoder.write_header()?.into_stream_writer()?)
+                }
+            };
+            std::io::Write::write_all(writer, band.as_raw())?;
+            save_progress.inc_by(band.as_raw().len());
+            if let Some(phase_timer) = opts.phase_timer {
+                phase_timer.add(Phase::Save, start.elapsed());
+            }
+            Ok(())
+        },
+    )?;
+    if let Some(img_path) = img_path {
+        writer
+            .expect("at least one band is written")
+            .finish()
+            .with_context(|| format!("Failed to finish writing {img_path:?}"))?;
+    }
+    Ok((dimensions, hasher.map(|hasher| hasher.finalize())))
+}
+
+/// The BLAKE3 hash of the dimensions and pixels of `img`, as printed by `--print-hash`.
+fn image_hash<C>(img: &ImageBuffer<Rgb<u8>, C>) -> blake3::Hash
+where
+    C: Deref<Target = [u8]>,
+{
+    let mut hasher = image_hasher(img.dimensions());
+    hasher.update(img.as_raw());
+    hasher.finalize()
+}
 
+/// A hasher for an image of `width` x `height` pixels like [`image_hash()`], to which its pixels are yet to be added,
+/// so images with the same pixels in different shapes have different hashes.
+fn image_hasher((width, height): (u32, u32)) -> blake3::Hasher {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&width.to_le_bytes());
+    hasher.update(&height.to_le_bytes());
+    hasher
+}
+
+/// Return `path` with `theme` added before its `extension`, like `out.Solarized-dark.png` for `out.png`.
+fn theme_specific_path(path: &Path, theme: &str, extension: &str) -> PathBuf {
+    let mut theme_extension = theme.replace(['(', ')'], "").replace(' ', "-");
+    theme_extension.push('.');
+    theme_extension.push_str(extension);
+    path.with_extension(theme_extension)
+}
+
+/// Write the colors of `palette` with the amount of pixels that have them as JSON to `path`, limited to the
+/// `max_colors` most common ones if set, along with the total amount of `pixels` in the image.
+fn export_palette(
+    mut palette: Vec<(Rgb<u8>, u64)>,
+    pixels: u64,
+    path: &Path,
+    max_colors: Option<usize>,
+    mut progress: impl prodash::Progress,
+) -> anyhow::Result<()> {
+    let distinct_colors = palette.len();
+    if let Some(max_colors) = max_colors {
+        palette.truncate(max_colors);
+    }
+    let colors = palette
+        .iter()
+        .map(|(color, count)| {
+            serde_json::json!({
+                "color": format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2]),
+                "count": count,
+            })
+        })
+        .collect::<Vec<_>>();
+    let json = serde_json::json!({
+        "pixels": pixels,
+        "distinct_colors": distinct_colors,
+        "colors": colors,
+    });
+    let file = std::fs::File::create(path).with_context(|| format!("Failed to create {path:?}"))?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), &json)
+        .with_context(|| format!("Failed to write palette to {path:?}"))?;
+    progress.done(format!(
+        "Wrote {} of {distinct_colors} colors to {path:?}",
+        palette.len()
+    ));
+    Ok(())
+}
+
+/// Save `img` as `rows` x `columns` tiles next to `img_path`, with their row and column appended to its name, along
+/// with a JSON manifest named like `img_path` that tells where each tile belongs.
+///
+/// The paths of the tiles and the manifest are added to `outputs` before they are written.
+fn save_tiles<C>(
+    img: &ImageBuffer<Rgb<u8>, C>,
+    img_path: &Path,
+    (rows, columns): (u32, u32),
+    format: Option<options::Format>,
+    bit_depth: codevis::BitDepth,
+    mut progress: impl prodash::Progress,
+    outputs: &OutputPaths,
+) -> anyhow::Result<()>
+where
+    C: Deref<Target = [u8]>,
+{
+    let (width, height) = img.dimensions();
+    anyhow::ensure!(
+        rows <= height && columns <= width,
+        "Can't split the {width}x{height} image into {rows}x{columns} tiles, as some would be empty"
+    );
+    let start = Instant::now();
+    progress.init(
+        Some((rows * columns) as usize),
+        Some(prodash::unit::label("tiles")),
+    );
+    let mut tiles = Vec::new();
+    for (index, region) in (0..).zip(codevis::render::tiles(width, height, rows, columns)) {
+        let (row, column) = (index / columns, index % columns);
+        let mut file_name = img_path.file_stem().unwrap_or_default().to_owned();
+        file_name.push(format!("_{row}_{column}"));
+        if let Some(extension) = img_path.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        let tile_path = img_path.with_file_name(&file_name);
+        let tile = codevis::render::crop(img, region)?;
+        outputs.insert(&tile_path);
+        sage_image(
+            &tile,
+            &tile_path,
+            format,
+            bit_depth,
+            prodash::progress::Discard,
+        )?;
+        tiles.push(serde_json::json!({
+            "row": row,
+            "column": column,
+            "x": region.x,
+            "y": region.y,
+            "width": region.width,
+            "height": region.height,
+            "path": Path::new(&file_name).display().to_string(),
+        }));
+        progress.inc();
+    }
+    let manifest_path = img_path.with_extension("json");
+    let manifest = serde_json::json!({
+        "width": width,
+        "height": height,
+        "rows": rows,
+        "columns": columns,
+        "tiles": tiles,
+    });
+    outputs.insert(&manifest_path);
+    std::fs::write(&manifest_path, format!("{manifest:#}\n")).with_context(|| {
+        format!("Failed to write the manifest of the tiles to {manifest_path:?}")
+    })?;
+    progress.show_throughput(start);
+    Ok(())
+}
+
+fn sage_image<C>(
+    img: &ImageBuffer<Rgb<u8>, C>,
+    img_path: &Path,
+    format: Option<options::Format>,
+    bit_depth: codevis::BitDepth,
+    mut progress: impl prodash::Progress,
+) -> anyhow::Result<()>
+where
+    C: Deref<Target = [u8]>,
+{
     // There is no image format that can reasonably stream arbitrary image formats, so writing
     // isn't interactive.
     // I think the goal would be to write a TGA file (it can handle huge files in theory while being uncompressed)
     // and write directly into a memory map on disk, or any other format that can.
     // In the mean time, PNG files work as well even though some apps are buggy with these image resolutions.
-    img.save(img_path)?;
-    let bytes = img_path
-        .metadata()
-        .map_or(0, |md| md.len() as prodash::progress::Step);
-    progress.inc_by(bytes);
-    progress.show_throughput(start);
+    match format.or_else(|| options::Format::from_path(img_path)) {
+        Some(format) => {
+            if ImageFormat::from_path(img_path)
+                .map_or(false, |implied| implied != ImageFormat::from(format))
+            {
+                progress.info(format!(
+                    "Writing {format:?} even though the extension of {img_path:?} suggests otherwise"
+                ));
+            }
+            let file = std::fs::File::create(img_path)
+                .with_context(|| format!("Failed to create {img_path:?}"))?;
+            codevis::render_to_writer(
+                img,
+                format,
+                bit_depth,
+                std::io::BufWriter::new(file),
+                progress,
+            )?
+        }
+        // other formats the `image` crate knows, like GIF, can only be written to files.
+        None => {
+            anyhow::ensure!(
+                bit_depth == codevis::BitDepth::Eight,
+                "Only PNG files can be written with 16 bits per channel, not {img_path:?}"
+            );
+            let start = Instant::now();
+            img.save(img_path)?;
+            let bytes = img_path
+                .metadata()
+                .map_or(0, |md| md.len() as prodash::progress::Step);
+            progress.inc_by(bytes);
+            progress.show_throughput(start);
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_counted_once_for_all_themes() {
+        let args = <options::Args as clap::Parser>::try_parse_from([
+            "codevis",
+            "-i",
+            "tests/fixtures/line-endings",
+            "--theme",
+            "Solarized (dark),InspiredGitHub",
+            "--no-write",
+        ])
+        .unwrap();
+        let phase_timer = PhaseTimer::default();
+        let rendered = render(
+            &args,
+            &prodash::TreeOptions::default().into(),
+            &AtomicBool::new(false),
+            &codevis::Renderer::new(),
+            None,
+            None,
+            Some(&phase_timer),
+            &OutputPaths::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            rendered.lines,
+            2 * 6,
+            "both files have 3 lines, and each theme draws them"
+        );
+        assert!(phase_timer.elapsed(Phase::Scan) > Duration::ZERO);
+    }
+
+    #[test]
+    fn normalize_eol_is_a_flag_that_can_be_turned_off() {
+        let normalize_eol = |extra_args: &[&str]| {
+            <options::Args as clap::Parser>::try_parse_from(
+                ["codevis", "-i", "."].iter().chain(extra_args),
+            )
+            .unwrap()
+            .normalize_eol
+        };
+        assert!(normalize_eol(&[]));
+        assert!(normalize_eol(&["--normalize-eol"]));
+        assert!(normalize_eol(&["--normalize-eol", "--hidden"]));
+        assert!(normalize_eol(&["--normalize-eol", "true"]));
+        assert!(!normalize_eol(&["--normalize-eol", "false"]));
+        assert!(!normalize_eol(&["--normalize-eol=false"]));
+    }
+
+    #[test]
+    fn line_heights_below_the_glyph_height_are_warned_about() {
+        let message = |args: &[&str]| {
+            let matches = <options::Args as clap::CommandFactory>::command()
+                .try_get_matches_from(["codevis", "-i", "."].iter().chain(args))
+                .unwrap();
+            let args = <options::Args as clap::FromArgMatches>::from_arg_matches(&matches).unwrap();
+            line_height_message(&args, &matches)
+        };
+        assert_eq!(message(&[]), None, "the default height is chosen knowingly");
+        assert!(message(&["--line-height-pixels", "8"])
+            .unwrap()
+            .contains("Use --readable"));
+        assert!(message(&["--line-height-pixels", "2"])
+            .unwrap()
+            .contains("2 pixels"));
+        assert_eq!(message(&["--line-height-pixels", "8", "--quiet"]), None);
+        assert_eq!(message(&["--line-height-pixels", "16"]), None);
+
+        assert_eq!(message(&["--readable"]), None);
+        assert_eq!(message(&["--readable", "--line-height-pixels", "16"]), None);
+        assert!(message(&["--readable", "--line-height-pixels", "2"])
+            .unwrap()
+            .contains("Ignoring"));
+        assert_eq!(
+            message(&["--readable", "--line-height-pixels", "2", "-q"]),
+            None
+        );
+    }
+
+    #[test]
+    fn hashes_of_images_include_their_dimensions_and_match_in_bands() {
+        let pixels = vec![1, 2, 3, 4, 5, 6];
+        assert_ne!(
+            image_hash(&RgbImage::from_raw(2, 1, pixels.clone()).unwrap()),
+            image_hash(&RgbImage::from_raw(1, 2, pixels).unwrap()),
+            "the same pixels in another shape are another image"
+        );
+
+        let renderer = codevis::Renderer::new();
+        let dir_contents = codevis::DirContents {
+            parent_dir: Default::default(),
+            children_content: vec![("file.txt".into(), "some text\n".repeat(300))],
+        };
+        // a single column of more lines than fit into one band.
+        let opts = codevis::render::Options {
+            threads: 1,
+            max_columns: Some(1),
+            ..Default::default()
+        };
+        let img = renderer
+            .render(
+                &dir_contents,
+                opts,
+                prodash::progress::Discard,
+                &AtomicBool::default(),
+            )
+            .unwrap();
+        let (dimensions, hash) = save_png_in_bands(
+            &dir_contents,
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+            &renderer,
+            opts,
+            None,
+            true,
+            prodash::progress::Discard,
+        )
+        .unwrap();
+        assert_eq!(dimensions, img.dimensions());
+        assert_eq!(
+            hash,
+            Some(image_hash(&img)),
+            "nothing needs to be written to hash the image with `--low-memory`"
+        );
+    }
+
+    #[test]
+    fn literal_tabs_are_single_and_conflict_with_tab_mode() {
+        use codevis::render::TabMode;
+        let parse = |extra_args: &[&str]| {
+            <options::Args as clap::Parser>::try_parse_from(
+                ["codevis", "-i", "."].iter().chain(extra_args),
+            )
+        };
+        assert_eq!(parse(&[]).unwrap().tab_mode(), TabMode::Expand);
+        assert_eq!(
+            parse(&["--tab-mode", "glyph"]).unwrap().tab_mode(),
+            TabMode::Glyph
+        );
+        assert_eq!(
+            parse(&["--literal-tabs"]).unwrap().tab_mode(),
+            TabMode::Single
+        );
+        for tab_mode in ["expand", "glyph", "single"] {
+            assert!(
+                parse(&["--literal-tabs", "--tab-mode", tab_mode]).is_err(),
+                "{tab_mode} can't silently be replaced"
+            );
+        }
+    }
+
+    #[test]
+    fn fit_to_screen_falls_back_to_16_9_without_a_screen() {
+        assert_eq!(screen_aspect(Some((2560, 1080))).0, (2560.0, 1080.0));
+        assert_eq!(screen_aspect(None).0, (16.0, 9.0));
+        if cfg!(not(feature = "display-info")) {
+            assert_eq!(
+                screen_size(),
+                None,
+                "screens can't be found without display-info"
+            );
+        }
+    }
+
+    #[test]
+    fn only_written_files_are_own_output() {
+        let dir = std::env::temp_dir().join(format!("codevis-outputs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let outputs = OutputPaths::default();
+        let saved = save_tiles(
+            &RgbImage::new(4, 2),
+            &dir.join("main.png"),
+            (1, 2),
+            None,
+            codevis::BitDepth::Eight,
+            prodash::progress::Discard,
+            &outputs,
+        );
+        let written = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path().canonicalize().unwrap())
+            .collect::<Vec<_>>();
+        let source = dir.canonicalize().unwrap().join("main.rs");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        saved.unwrap();
+        assert_eq!(written.len(), 3, "two tiles and the manifest");
+        assert!(written.iter().all(|path| outputs.contains(path)));
+        assert!(
+            !outputs.contains(&source),
+            "files named like the output aren't ours"
+        );
+    }
+}
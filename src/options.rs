@@ -1,12 +1,30 @@
+use crate::output_template::OutputTemplate;
+pub use codevis::Format;
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, clap::Parser)]
 #[clap(version)]
 pub struct Args {
     /// The directory to read UTF-8 encoded text files from.
-    #[clap(long, short = 'i', help_heading = "INPUT")]
-    pub input_dir: PathBuf,
+    #[clap(
+        long,
+        short = 'i',
+        required_unless_present_any(["archive", "concat"]),
+        help_heading = "INPUT"
+    )]
+    pub input_dir: Option<PathBuf>,
+
+    /// A `.tar`, `.tar.gz`, `.tgz` or `.zip` file to read UTF-8 encoded text files from instead of `--input-dir`,
+    /// without unpacking it.
+    ///
+    /// Files are filtered like those of a directory, with `--ignore-file` being relative to the root of the archive.
+    #[clap(
+        long,
+        conflicts_with_all = ["input_dir", "files_from0", "watch"],
+        help_heading = "INPUT"
+    )]
+    pub archive: Option<PathBuf>,
 
     /// An extension to ignore, like `md` for markdown files.
     /// You can add multiple extensions by seperating them with commas like so `--ignore_extension rs,lock`.
@@ -23,17 +41,103 @@ pub struct Args {
     )]
     pub whitelist_extension: Vec<OsString>,
 
+    /// A file with gitignore-style patterns of files not to render, relative to `--input-dir`.
+    ///
+    /// Defaults to `.codeviz-ignore` in `--input-dir`, if it exists.
+    /// It's independent of `.gitignore` files, so it can hide files that git tracks.
+    #[clap(long, help_heading = "INPUT")]
+    pub ignore_file: Option<PathBuf>,
+
+    /// Also render hidden files and directories whose name starts with a dot, like `.github/workflows/ci.yml`.
+    ///
+    /// They are skipped by default. Files ignored by `.gitignore` files stay ignored, and `.git` is never rendered.
+    #[clap(long, help_heading = "INPUT")]
+    pub hidden: bool,
+
+    /// Turn `\r\n` line endings into `\n` while reading files, so they render the same as files with `\n` line endings.
+    ///
+    /// Use `--normalize-eol false` to keep them as they are.
+    #[clap(
+        long,
+        default_value_t = true,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        action = clap::ArgAction::Set,
+        help_heading = "INPUT"
+    )]
+    pub normalize_eol: bool,
+
+    /// Render files that aren't UTF-8 encoded, like images, as a hexdump like the one of `xxd` instead of skipping them,
+    /// so the whole directory is shown.
+    ///
+    /// Each line of a hexdump shows 16 bytes, so a binary file of 1MiB takes 65536 lines, which can make it take up
+    /// most of the image. Use `--ignore-extension` or `--max-file-lines` to keep large binary files in check.
+    #[clap(long, help_heading = "INPUT")]
+    pub binary_as_hexdump: bool,
+
+    /// Shorten runs of more than this many blank lines to this many while reading files, so sparse code takes
+    /// less space in the image.
+    ///
+    /// Lines containing only whitespace count as blank. Line numbers count the lines that are left.
+    #[clap(long, help_heading = "INPUT")]
+    pub collapse_blank_lines: Option<usize>,
+
+    /// Render only this many of all files, dropping the others entirely, for a quick preview of a huge directory.
+    #[clap(long, value_name = "N", help_heading = "INPUT")]
+    pub sample_files: Option<usize>,
+
+    /// How `--sample-files` chooses the files to render.
+    #[clap(
+        value_enum,
+        long,
+        default_value_t = SampleMode::Even,
+        requires("sample_files"),
+        help_heading = "INPUT"
+    )]
+    pub sample_mode: SampleMode,
+
+    /// Only render the first this many lines of each file, so a few huge files, like generated ones, don't take over
+    /// the image while all other files render fully.
+    #[clap(long, value_name = "N", help_heading = "INPUT")]
+    pub max_file_lines: Option<usize>,
+
+    /// Render only files modified within this long ago, like `7d` or `24h`, counting all others as ignored.
+    ///
+    /// Units are `s`, `m`, `h`, `d` and `w`. Files whose modification time can't be read are ignored as well.
+    #[clap(long, value_parser = parse_duration, help_heading = "INPUT")]
+    pub modified_since: Option<std::time::Duration>,
+
+    /// Render only the files listed in this file, or in stdin if it is `-`, instead of all files in `--input-dir`.
+    ///
+    /// Paths are relative to `--input-dir` and separated by NUL bytes, as produced by `find -print0`
+    /// or `git diff --name-only -z`, which makes paths containing newlines safe to use.
+    #[clap(long, help_heading = "INPUT")]
+    pub files_from0: Option<PathBuf>,
+
     /// If true, files that would be rendered white due to lack of syntax are skipped.
     #[clap(long, help_heading = "INPUT")]
     pub ignore_files_without_syntax: bool,
 
+    /// Don't find the syntax of files by their first line, like a `#!/usr/bin/env python` shebang, when their name
+    /// or extension has no known syntax, rendering them as plain text instead.
+    #[clap(long, help_heading = "INPUT")]
+    pub no_detect_by_content: bool,
+
+    /// Stop rendering files after this long, like `90s` or `5m`, and save the image with the lines of the remaining
+    /// files left blank, to stay within a time budget.
+    ///
+    /// Time is counted from reading the input, and saving the image takes a bit longer. Units are like the ones of
+    /// `--modified-since`.
+    #[clap(long, value_parser = parse_duration, value_name = "DURATION", help_heading = "PERFORMANCE")]
+    pub max_render_time: Option<std::time::Duration>,
+
     /// The number of threads to use for rendering.
     ///
     /// '0' is equivalent to using all logical cores, this is also the default.
     #[clap(long, short = 't', default_value_t = num_cpus::get(), help_heading = "PERFORMANCE")]
     pub threads: usize,
 
-    /// If true, highlighting will be performed on lines truncated to the `--column-width-pixels`, which is faster
+    /// If true, highlighting will be performed on lines truncated to `--column-chars`, which is faster
     /// but may lock up syntax highlighting.
     ///
     /// It may also affect the looks.
@@ -41,49 +145,419 @@ pub struct Args {
     #[clap(long, help_heading = "PERFORMANCE")]
     pub highlight_truncated_lines: bool,
 
+    /// A directory in which to keep the rendered images of individual files.
+    ///
+    /// Files that didn't change since the last run are taken from there instead of being rendered again,
+    /// as long as options affecting their appearance are unchanged as well.
+    /// It's not used with `--background-image`, which needs to know which pixels are glyphs.
+    #[clap(long, help_heading = "PERFORMANCE")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Render and write the PNG in bands of a few hundred lines of all columns, to not hold the whole image in memory.
+    ///
+    /// This makes very large images possible, but takes longer as rendering happens on a single thread,
+    /// and files that continue in the next column are rendered twice.
+    #[clap(
+        long,
+        conflicts_with_all = ["cache_dir", "file_summary", "background_image", "padding", "highlight_file", "crop", "format", "border", "shadow", "badge", "no_split_files", "glow", "exclude_empty_columns", "width_for", "max_render_time", "zebra_columns", "mark_blank_files"],
+        help_heading = "PERFORMANCE"
+    )]
+    pub low_memory: bool,
+
+    /// The most memory the image may take, like `2GiB`, with lines getting thinner until it fits.
+    ///
+    /// Rendering fails if even a line height of one pixel is too much, or if the line height can't change
+    /// with `--readable`.
+    #[clap(long, conflicts_with("low_memory"), help_heading = "PERFORMANCE")]
+    pub max_memory: Option<bytesize::ByteSize>,
+
     /// Only use plain text file syntax highlighting. It's fastest and won't lock up.
     #[clap(long, conflicts_with("theme"), help_heading = "PERFORMANCE")]
     pub force_plain_syntax: bool,
 
     /// When a file looks up, use this to see which file is about to be highlighted.
-    #[clap(long, help_heading = "MONITORING")]
+    #[clap(long, visible_alias = "display-files", help_heading = "MONITORING")]
     pub display_to_be_processed_file: bool,
 
+    /// Display at most this many files per second with `--display-files`, to not flood the progress display
+    /// with large repositories. 0 displays all files.
+    #[clap(
+        long,
+        default_value_t = 20,
+        requires("display_to_be_processed_file"),
+        help_heading = "MONITORING"
+    )]
+    pub display_files_per_second: u32,
+
+    /// Keep running and render again whenever a file in the input directory changes.
+    #[clap(long, help_heading = "MONITORING")]
+    pub watch: bool,
+
+    /// Render repeatedly and print how long scanning, dimensioning, processing and saving took.
+    #[clap(long, conflicts_with("watch"), help_heading = "MONITORING")]
+    pub bench: bool,
+
+    /// The amount of times to render with `--bench`.
+    #[clap(
+        long,
+        default_value_t = 5,
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires("bench"),
+        help_heading = "MONITORING"
+    )]
+    pub bench_iters: u32,
+
+    /// How to show progress on stderr, with `json` writing lines like `{"phase":"render","current":10,"total":20}`.
+    ///
+    /// `total` is `null` if it isn't known, and lines are only written when a phase made progress.
+    #[clap(value_enum, long, default_value_t = ProgressFormat::Human, help_heading = "MONITORING")]
+    pub progress_format: ProgressFormat,
+
+    /// Don't warn about settings that lose detail or are ignored, like a `--line-height-pixels` below the height
+    /// of glyphs.
+    #[clap(long, short = 'q', help_heading = "MONITORING")]
+    pub quiet: bool,
+
+    /// Give every file exactly this many lines, truncating longer files and padding shorter ones.
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with("file_summary"),
+        help_heading = "IMAGE"
+    )]
+    pub uniform_file_height: Option<u32>,
+
     /// Allow the last column to be partially empty, with the tradeoff
     /// of the output image being closer to desired aspect ratio.
-    #[clap(long, help_heading = "IMAGE")]
+    ///
+    /// By default, columns are no taller than needed to hold all lines, so the last column lacks fewer lines
+    /// than there are columns.
+    #[clap(long, alias = "no-force-full-columns", help_heading = "IMAGE")]
     pub dont_force_full_columns: bool,
 
-    /// The width of one column in pixels, with each character being a pixel wide.
+    /// Fill columns from right to left, so the first file ends up in the rightmost column.
+    #[clap(long, help_heading = "IMAGE")]
+    pub rtl_columns: bool,
+
+    /// The order to render files in, instead of sorting them by path.
+    ///
+    /// Files that are equal in this order stay sorted by path.
+    #[clap(value_enum, long, help_heading = "IMAGE")]
+    pub render_order: Option<codevis::render::RenderOrder>,
+
+    /// Put files in a random order for varied mosaics of the same files, which differs with every run unless `--seed` is given.
+    #[clap(long, conflicts_with("render_order"), help_heading = "IMAGE")]
+    pub shuffle_files: bool,
+
+    /// The seed for `--shuffle-files` and `--sample-mode random`, to shuffle or sample the same way again.
+    #[clap(long, help_heading = "IMAGE")]
+    pub seed: Option<u64>,
+
+    /// The width of one column in characters, which are a pixel wide, or 8 pixels with `--readable`.
     ///
     /// Lines longer than that will be truncated.
-    #[clap(long, default_value_t = 100, help_heading = "IMAGE")]
-    pub column_width_pixels: u32,
+    /// `auto` or 0 make columns as wide as the longest line instead, up to `--max-auto-width`.
+    /// `--column-width-pixels` is a deprecated alias, as the width was never in pixels with `--readable`.
+    #[clap(
+        long,
+        visible_alias = "column-width-chars",
+        alias = "column-width-pixels",
+        default_value = "100",
+        value_parser = parse_column_chars,
+        help_heading = "IMAGE"
+    )]
+    pub column_chars: u32,
 
-    /// The height of a line in pixels,
+    /// The widest columns can get with `--column-chars auto`, so a few very long lines don't make all columns wide.
+    #[clap(
+        long,
+        default_value_t = 300,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help_heading = "IMAGE"
+    )]
+    pub max_auto_width: u32,
+
+    /// Render files with an extension in columns of a different width, like `java=140` or `lisp=60`, which can
+    /// be given multiple times.
+    ///
+    /// Each width gets its own block of columns, placed to the right of the block of all other files,
+    /// with blocks sharing the aspect ratio by how many characters they show. Shorter blocks are filled up with
+    /// their background at the bottom.
+    #[clap(
+        long,
+        value_name = "EXTENSION=CHARS",
+        value_parser = parse_width_for,
+        help_heading = "IMAGE"
+    )]
+    pub width_for: Vec<(String, u32)>,
+
+    /// The height of a line in pixels, which is always 16 with `--readable` to fit its glyphs.
     #[clap(long, default_value_t = 2, help_heading = "IMAGE")]
     pub line_height_pixels: u32,
 
+    /// How many rows of blank pixels to add below each line, which makes lines easier to tell apart at larger scales.
+    #[clap(long, default_value_t = 0, help_heading = "IMAGE")]
+    pub line_spacing: u32,
+
     /// Whether the text should be rendered in a readable font.
     #[clap(long, conflicts_with("fg_pixel_color"), help_heading = "IMAGE")]
     pub readable: bool,
 
+    /// Surround the glyphs of `--readable` text with a 1px outline, so they stay legible on backgrounds of a
+    /// similar color. The outline darkens the background unless `--outline-color` is set.
+    #[clap(long, requires("readable"), help_heading = "IMAGE")]
+    pub glyph_outline: bool,
+
+    /// The color of the outline of `--glyph-outline`, like `#000000`.
+    #[clap(long, value_parser = parse_color, requires("glyph_outline"), help_heading = "COLORS")]
+    pub outline_color: Option<image::Rgb<u8>>,
+
     /// Whether the filename should be written at the top of files.
     /// only really useful when combined with `--readable`.
+    ///
+    /// Paths are written relative to the input directory and without `.` components, like `src/lib.rs`.
     #[clap(long, help_heading = "IMAGE")]
     pub show_filenames: bool,
 
+    /// The character to draw with `--readable` in place of characters the font has no glyph for,
+    /// like a space to hide them or `?`.
+    #[clap(long, requires("readable"), help_heading = "IMAGE")]
+    pub missing_glyph: Option<char>,
+
+    /// Draw a single line per file instead of its content, with a bar as long as the file has lines
+    /// relative to the longest file.
+    #[clap(long, help_heading = "IMAGE", conflicts_with_all(["readable", "show_filenames", "line_nums"]))]
+    pub file_summary: bool,
+
     /// Whether to render line numbers.
     #[clap(long, help_heading = "IMAGE")]
     pub line_nums: bool,
 
-    /// The width side of the desired image aspect.
-    #[clap(long, default_value_t = 16.0, help_heading = "IMAGE")]
-    pub aspect_width: f64,
+    /// Paint spaces and tabs at the end of lines in `--trailing-whitespace-color` to make them stand out.
+    #[clap(long, help_heading = "IMAGE")]
+    pub highlight_trailing_whitespace: bool,
+
+    /// The color `--highlight-trailing-whitespace` paints in, like `#ff0000`, defaulting to `--highlight-color` or red.
+    #[clap(long, value_parser = parse_color, help_heading = "COLORS")]
+    pub trailing_whitespace_color: Option<image::Rgb<u8>>,
+
+    /// Draw a border around files whose path relative to the input directory matches this glob, like `src/**/*.rs`.
+    ///
+    /// Can be given multiple times to highlight files matching any of the globs.
+    #[clap(long, help_heading = "IMAGE")]
+    pub highlight_file: Vec<String>,
+
+    /// Tint lines matching this regular expression, like `TODO|FIXME`, to see where they are.
+    #[clap(long, visible_alias = "highlight-search", help_heading = "IMAGE")]
+    pub grep: Option<regex::Regex>,
+
+    /// Render only the lines matching this regular expression, like `^\s*(pub )?fn `, to see all of them at once,
+    /// counting files without a single match as ignored.
+    ///
+    /// Lines are matched without their line ending. Line numbers count the lines that are left.
+    #[clap(long, value_name = "REGEX", help_heading = "INPUT")]
+    pub grep_only: Option<regex::Regex>,
+
+    /// The color that lines matching `--grep` are tinted towards, like `#ffd700`, defaulting to `--highlight-color` or gold.
+    #[clap(long, value_parser = parse_color, help_heading = "COLORS")]
+    pub grep_color: Option<image::Rgb<u8>>,
+
+    /// How strongly lines matching `--grep` are tinted, from 0.0 for not at all to 1.0 for only showing `--grep-color`.
+    ///
+    /// Defaults to `--highlight-strength` or 0.5.
+    #[clap(long, value_parser = parse_opacity, help_heading = "COLORS")]
+    pub grep_strength: Option<f32>,
+
+    /// The color of all highlights, like `#ff00ff`, unless they have a color of their own.
+    ///
+    /// Without it, each highlight uses its own default color.
+    #[clap(long, value_parser = parse_color, help_heading = "COLORS")]
+    pub highlight_color: Option<image::Rgb<u8>>,
+
+    /// How strongly all highlights that tint lines are mixed in, from 0.0 to 1.0, unless they have a strength of their own.
+    #[clap(long, value_parser = parse_opacity, help_heading = "COLORS")]
+    pub highlight_strength: Option<f32>,
+
+    /// The color of the border drawn by `--highlight-file`, like `#ff0000`, defaulting to `--highlight-color` or red.
+    #[clap(long, value_parser = parse_color, help_heading = "COLORS")]
+    pub highlight_file_color: Option<image::Rgb<u8>>,
+
+    /// How many pixels wide the border drawn by `--highlight-file` is.
+    #[clap(
+        long,
+        default_value_t = 2,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help_heading = "IMAGE"
+    )]
+    pub highlight_border_width: u32,
+
+    /// Shade the characters of lines longer than `--gradient-threshold` increasingly towards `--gradient-color`.
+    #[clap(long, help_heading = "IMAGE")]
+    pub length_gradient: bool,
+
+    /// The color `--length-gradient` shades towards, like `#ff8c00`, defaulting to `--highlight-color` or orange.
+    #[clap(long, value_parser = parse_color, help_heading = "COLORS")]
+    pub gradient_color: Option<image::Rgb<u8>>,
+
+    /// How much of `--gradient-color` is mixed in at the end of a column, from 0.0 to 1.0.
+    ///
+    /// Defaults to `--highlight-strength` or 0.7.
+    #[clap(long, value_parser = parse_opacity, help_heading = "COLORS")]
+    pub gradient_strength: Option<f32>,
+
+    /// The amount of characters after which `--length-gradient` starts shading a line.
+    #[clap(
+        long,
+        default_value_t = 80,
+        requires("length_gradient"),
+        help_heading = "IMAGE"
+    )]
+    pub gradient_threshold: u32,
+
+    /// Indent files by their directory depth, to make the directory structure visible as a left margin.
+    #[clap(long, help_heading = "IMAGE")]
+    pub indent_by_depth: bool,
+
+    /// The amount of cells `--indent-by-depth` indents files by per directory, up to half the column width.
+    #[clap(
+        long,
+        default_value_t = 2,
+        requires("indent_by_depth"),
+        help_heading = "IMAGE"
+    )]
+    pub indent_cells: u32,
+
+    /// The desired width of the image in pixels, which is used instead of the aspect ratio.
+    ///
+    /// It's rounded down to a multiple of the column width, and the height follows from the amount of lines.
+    #[clap(long, help_heading = "IMAGE")]
+    pub target_width: Option<u32>,
+
+    /// Never use more than this amount of columns, making the image taller than the aspect ratio asks for.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..), help_heading = "IMAGE")]
+    pub max_columns: Option<u32>,
+
+    /// Make columns at least this many lines tall, using fewer columns than the aspect ratio asks for,
+    /// so small inputs don't produce stubby images.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..), help_heading = "IMAGE")]
+    pub min_column_lines: Option<u32>,
+
+    /// Start files at the top of the next column if they don't fit into the rest of the current one, leaving it empty,
+    /// so each file can be read in one piece unless it's taller than a column.
+    #[clap(long, conflicts_with("file_summary"), help_heading = "IMAGE")]
+    pub no_split_files: bool,
+
+    /// The desired image aspect as `WIDTH:HEIGHT`, like `16:9` or `4:3`, or `a4` for the portrait aspect of paper.
+    #[clap(long, value_parser = parse_aspect, default_value = "16:9", help_heading = "IMAGE")]
+    pub aspect: (f64, f64),
+
+    /// Use the aspect of the primary display instead of `--aspect`, so the image fits the screen it's shown on,
+    /// like a wallpaper.
+    ///
+    /// The display is found through X11 on Linux, CoreGraphics on macOS and GDI on Windows. Without one, like in an
+    /// SSH session, or if built without the `display-info` feature, the aspect falls back to 16:9.
+    #[clap(
+        long,
+        conflicts_with_all = ["aspect", "aspect_width", "aspect_height"],
+        help_heading = "IMAGE"
+    )]
+    pub fit_to_screen: bool,
+
+    /// The width side of the desired image aspect, overriding the one of `--aspect`.
+    #[clap(long, help_heading = "IMAGE")]
+    pub aspect_width: Option<f64>,
+
+    /// The height side of the desired image aspect, overriding the one of `--aspect`.
+    #[clap(long, help_heading = "IMAGE")]
+    pub aspect_height: Option<f64>,
+
+    /// Pad the image with the background color to exactly match the desired aspect, like for fitting a 1920x1080 frame.
+    #[clap(
+        long,
+        conflicts_with("target_width"),
+        group = "padding",
+        help_heading = "IMAGE"
+    )]
+    pub exact_aspect: bool,
+
+    /// Like `--exact-aspect`, but add bands of this color like `#000000` instead, letterboxing the finished image.
+    ///
+    /// Unlike `--exact-aspect`, bands are only added if the aspect of the image is more than 5% off the desired one.
+    #[clap(
+        long,
+        value_parser = parse_color,
+        conflicts_with("target_width"),
+        group = "padding",
+        help_heading = "IMAGE"
+    )]
+    pub letterbox_color: Option<image::Rgb<u8>>,
+
+    /// Cut off the empty background at the right of the image, like that of a last column that isn't full,
+    /// which makes the image narrower than the desired aspect.
+    #[clap(long, conflicts_with = "exact_aspect", help_heading = "IMAGE")]
+    pub exclude_empty_columns: bool,
+
+    /// Where to put the padding added by `--exact-aspect` or `--letterbox-color`.
+    #[clap(value_enum, long, default_value_t = codevis::render::Pad::Center, requires("padding"), help_heading = "IMAGE")]
+    pub pad: codevis::render::Pad,
+
+    /// Show the amount of files and lines that were rendered in this corner of the image.
+    #[clap(value_enum, long, default_value_t = codevis::render::Badge::None, help_heading = "IMAGE")]
+    pub badge: codevis::render::Badge,
+
+    /// Surround the finished image with a border this many pixels wide, which makes it larger.
+    #[clap(long, default_value_t = 0, help_heading = "IMAGE")]
+    pub border: u32,
+
+    /// The color of the border of `--border`, like `#ffffff`.
+    #[clap(long, value_parser = parse_color, default_value = "#ffffff", help_heading = "IMAGE")]
+    pub border_color: image::Rgb<u8>,
+
+    /// Place the finished image on `--shadow-backdrop` with a soft shadow this many pixels wide falling to its
+    /// bottom right, which makes it larger.
+    #[clap(long, default_value_t = 0, help_heading = "IMAGE")]
+    pub shadow: u32,
+
+    /// The color the shadow of `--shadow` is drawn on, like `#ffffff`, as images have no transparency.
+    #[clap(long, value_parser = parse_color, default_value = "#ffffff", help_heading = "IMAGE")]
+    pub shadow_backdrop: image::Rgb<u8>,
 
-    /// The height side of the desired image aspect.
-    #[clap(long, default_value_t = 9.0, help_heading = "IMAGE")]
-    pub aspect_height: f64,
+    /// Let bright colors glow into their surroundings, which makes syntax tokens stand out against dark backgrounds.
+    ///
+    /// This blurs a copy of the whole image, so it takes a while and twice the memory for large images.
+    #[clap(long, help_heading = "COLORS")]
+    pub glow: bool,
+
+    /// How many pixels far bright colors glow with `--glow`.
+    #[clap(long, default_value_t = 8, requires("glow"), help_heading = "COLORS")]
+    pub glow_radius: u32,
+
+    /// How strongly bright colors glow with `--glow`, with 1.0 adding their blurred colors in full.
+    #[clap(long, default_value_t = 0.8, requires("glow"), help_heading = "COLORS")]
+    pub glow_intensity: f32,
+
+    /// Shift the brightness of the background in every other column a little, to tell columns apart without gaps
+    /// between them.
+    ///
+    /// Dark backgrounds get brighter and bright ones get darker, while glyphs keep their colors.
+    #[clap(long, help_heading = "COLORS")]
+    pub zebra_columns: bool,
+
+    /// How much the background of every other column is shifted with `--zebra-columns`, from 0.0 for not at all to
+    /// 1.0 for white or black.
+    #[clap(
+        long,
+        default_value_t = 0.08,
+        value_parser = parse_opacity,
+        requires("zebra_columns"),
+        help_heading = "COLORS"
+    )]
+    pub zebra_strength: f32,
+
+    /// Draw a thin line along the left edge of files with nothing but whitespace, which otherwise look just like the
+    /// empty end of the last column.
+    #[clap(long, help_heading = "IMAGE")]
+    pub mark_blank_files: bool,
 
     /// The themes to use for rendering. Use `foo` to see a list of possible values.
     ///
@@ -92,6 +566,51 @@ pub struct Args {
     #[clap(long, default_values = &["Solarized (dark)"], help_heading = "COLORS", value_delimiter = ',')]
     pub theme: Vec<String>,
 
+    /// Use a different theme for files with the given extension, like `md="Solarized (light)"`.
+    ///
+    /// Can be specified multiple times, and files without a matching extension use `--theme`.
+    #[clap(
+        long,
+        help_heading = "COLORS",
+        value_parser = parse_theme_for,
+        conflicts_with("force_plain_syntax")
+    )]
+    pub theme_for: Vec<(String, String)>,
+
+    /// Highlight with a theme built from the 16 ANSI colors of the terminal instead of `--theme`, which is used if
+    /// they can't be found.
+    ///
+    /// The colors are read from `$CODEVIS_TERMINAL_COLORS` as 16 colors like `#RRGGBB` separated by whitespace,
+    /// commas or colons, or else from the colors `pywal` last applied. Only broad groups like comments, strings and
+    /// keywords get colors of their own, so this is less detailed than a full theme.
+    #[clap(long, conflicts_with_all = ["all_themes", "compare_themes"], help_heading = "COLORS")]
+    pub theme_from_terminal: bool,
+
+    /// Arrange these existing images in a grid in `--output-path` instead of rendering anything, to assemble
+    /// a gallery of earlier renders. Separate paths with commas.
+    #[clap(
+        long,
+        value_name = "IMAGE",
+        value_delimiter = ',',
+        conflicts_with_all = ["input_dir", "archive", "files_from0", "watch", "bench", "compare_themes", "per_file_output", "low_memory"],
+        help_heading = "OUTPUT"
+    )]
+    pub concat: Vec<PathBuf>,
+
+    /// How many images of `--concat` to place in each row, instead of about as many as there are rows.
+    #[clap(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires("concat"),
+        help_heading = "OUTPUT"
+    )]
+    pub concat_cols: Option<u32>,
+
+    /// Label each image of `--concat` with its file name.
+    #[clap(long, requires("concat"), help_heading = "OUTPUT")]
+    pub concat_labels: bool,
+
     /// Render the input with all available themes, one after another.
     #[clap(
         long,
@@ -105,14 +624,124 @@ pub struct Args {
     #[clap(value_enum, long, default_value_t = codevis::render::FgColor::StyleAsciiBrightness, help_heading = "COLORS")]
     pub fg_pixel_color: codevis::render::FgColor,
 
+    /// Draw all glyphs in this color, like `#ffffff`, regardless of syntax, while backgrounds keep the colors
+    /// of the theme. Also applies to `--readable` glyphs.
+    #[clap(long, value_parser = parse_color, conflicts_with_all(["fg_pixel_color", "mono"]), help_heading = "COLORS")]
+    pub fg_hex: Option<image::Rgb<u8>>,
+
+    /// How much brighter than the theme colors glyphs get with `--fg-pixel-color style-ascii-brightness`.
+    ///
+    /// Lower it if bright themes look blown out.
+    #[clap(
+        long,
+        alias = "foreground-boost",
+        default_value_t = 2.4,
+        help_heading = "COLORS"
+    )]
+    pub fg_boost: f32,
+
     /// The way background pixels are colored.
     #[clap(value_enum, long, default_value_t = codevis::render::BgColor::Style, help_heading = "COLORS")]
     pub bg_pixel_color: codevis::render::BgColor,
 
+    /// Tint lines that were added since this git revision green, and lines that were modified yellow.
+    ///
+    /// The files of the input directory are rendered as they are, and compared to `--to-git-rev` if set.
+    #[clap(
+        long,
+        value_name = "REV",
+        conflicts_with_all = ["archive", "collapse_blank_lines", "grep_only", "file_summary"],
+        help_heading = "COLORS"
+    )]
+    pub since_git_rev: Option<String>,
+
+    /// Compare `--since-git-rev` to this revision instead of the files of the input directory, which should then
+    /// have this revision checked out.
+    #[clap(
+        long,
+        value_name = "REV",
+        requires("since_git_rev"),
+        help_heading = "COLORS"
+    )]
+    pub to_git_rev: Option<String>,
+
+    /// Tint each line in a color derived from the name of its author as `git blame` sees it, to show who wrote what.
+    ///
+    /// Every file is blamed on its own, which takes a while in large repositories. Files that aren't known to git
+    /// and lines that aren't committed yet aren't tinted.
+    #[clap(
+        long,
+        conflicts_with_all = ["archive", "collapse_blank_lines", "grep_only", "file_summary", "since_git_rev"],
+        help_heading = "COLORS"
+    )]
+    pub blame_tint: bool,
+
+    /// Show the color of each author with `--blame-tint`, along with how many lines they wrote.
+    #[clap(long, requires("blame_tint"), help_heading = "COLORS")]
+    pub blame_legend: bool,
+
+    /// Give all files the background of the first file in the theme, so only their foreground varies
+    /// instead of backgrounds making a patchwork of files with different themes or `--bg-pixel-color` modulation.
+    #[clap(long, conflicts_with = "mono", help_heading = "COLORS")]
+    pub uniform_background: bool,
+
+    /// Draw all code in `--mono-fg` on `--mono-bg` instead of the colors of the theme, for a retro terminal look.
+    #[clap(
+        long,
+        conflicts_with_all(["fg_pixel_color", "bg_pixel_color", "canvas_color"]),
+        help_heading = "COLORS"
+    )]
+    pub mono: bool,
+
+    /// The color of glyphs with `--mono`, like `#33ff33`.
+    #[clap(long, value_parser = parse_color, default_value = "#33ff33", requires("mono"), help_heading = "COLORS")]
+    pub mono_fg: image::Rgb<u8>,
+
+    /// The color behind glyphs with `--mono`, like `#000000`.
+    #[clap(long, value_parser = parse_color, default_value = "#000000", requires("mono"), help_heading = "COLORS")]
+    pub mono_bg: image::Rgb<u8>,
+
+    /// A fixed background color like `#1e1e1e` for the canvas behind glyphs, used with `--fg-pixel-color style-ascii-brightness`.
+    #[clap(long, value_parser = parse_color, help_heading = "COLORS")]
+    pub canvas_color: Option<image::Rgb<u8>>,
+
+    /// An image to show behind the code, in place of all pixels drawn with a background color.
+    #[clap(long, help_heading = "COLORS")]
+    pub background_image: Option<PathBuf>,
+
+    /// How the `--background-image` is fitted to the output image.
+    #[clap(value_enum, long, default_value_t = codevis::render::BgFit::Cover, help_heading = "COLORS")]
+    pub bg_fit: codevis::render::BgFit,
+
+    /// Convert the output to grayscale, keeping the brightness differences of the syntax highlighting.
+    ///
+    /// Unlike `--force-plain-syntax`, highlighting is still performed.
+    #[clap(long, help_heading = "COLORS")]
+    pub grayscale: bool,
+
     /// The difference in brightness that certain background color styles may have at most.
     #[clap(long, default_value_t = 0.3, help_heading = "COLORS")]
     pub color_modulation: f32,
 
+    /// How visible comments are, from 0.0 for blending them into the background to 1.0 for leaving them unchanged.
+    ///
+    /// It has no effect with `--force-plain-syntax`, which doesn't know about comments.
+    #[clap(long, default_value_t = 1.0, value_parser = parse_opacity, help_heading = "COLORS")]
+    pub comment_opacity: f32,
+
+    /// Only show `code` or only `comments`, blending everything else into the background, to see where the logic
+    /// or the documentation is.
+    ///
+    /// Comments are found by the `comment` scope of the syntax, so it has no effect with `--force-plain-syntax`.
+    #[clap(value_enum, long, help_heading = "COLORS")]
+    pub only: Option<codevis::render::Only>,
+
+    /// The color space in which to blend colors when fading comments, tinting lines matching `--grep` or shading long lines.
+    ///
+    /// `linear` mixes colors like light does, while `srgb` makes mixed colors look darker than they should.
+    #[clap(value_enum, long, default_value_t = codevis::render::BlendSpace::Linear, help_heading = "COLORS")]
+    pub blend_space: codevis::render::BlendSpace,
+
     /// Open the output image with the standard image viewer.
     #[clap(long, help_heading = "OUTPUT")]
     pub open: bool,
@@ -126,7 +755,359 @@ pub struct Args {
     )]
     pub output_path: PathBuf,
 
+    /// Name the output file after this template instead of `--output-path`, with placeholders being filled in
+    /// once the image is rendered, like `viz-{theme}-{date}.png`.
+    ///
+    /// Placeholders are `{theme}`, `{date}` as in `2022-10-05`, `{width}` and `{height}` of the image in pixels,
+    /// and the amount of `{lines}` and `{files}` rendered. With multiple themes, the template must contain `{theme}`.
+    #[clap(
+        long,
+        value_parser = OutputTemplate::parse,
+        conflicts_with_all = ["output_path", "per_file_output", "compare_themes", "low_memory", "watch"],
+        help_heading = "OUTPUT"
+    )]
+    pub output_template: Option<OutputTemplate>,
+
+    /// Render every file to its own image in this directory instead, at its path relative to `--input-dir`
+    /// with `.png` appended, like `DIR/src/main.rs.png`.
+    ///
+    /// Each image is sized to match the aspect ratio on its own, and files are rendered in parallel.
+    #[clap(
+        long,
+        conflicts_with_all = ["output_path", "crop", "open", "low_memory"],
+        help_heading = "OUTPUT"
+    )]
+    pub per_file_output: Option<PathBuf>,
+
+    /// Render the input once with each of these themes and arrange the results in a labeled grid in one image,
+    /// to compare them side by side. Separate themes with commas like `--theme`.
+    #[clap(
+        long,
+        value_delimiter = ',',
+        conflicts_with_all = ["theme", "all_themes", "per_file_output", "low_memory"],
+        help_heading = "OUTPUT"
+    )]
+    pub compare_themes: Vec<String>,
+
+    /// The largest width in pixels of each image in the grid of `--compare-themes`, to which larger images
+    /// are scaled down to keep the grid small.
+    #[clap(
+        long,
+        default_value_t = 800,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help_heading = "OUTPUT"
+    )]
+    pub compare_width: u32,
+
+    /// The filter used to scale down the images in the grid of `--compare-themes`, from the fastest to the one that
+    /// keeps the most detail.
+    #[clap(value_enum, long, default_value_t = codevis::render::DownscaleFilter::Lanczos, help_heading = "OUTPUT")]
+    pub downscale_filter: codevis::render::DownscaleFilter,
+
+    /// The format of the output file, which is otherwise derived from the extension of `--output-path`.
+    #[clap(value_enum, long, help_heading = "OUTPUT")]
+    pub format: Option<Format>,
+
+    /// The amount of bits per channel of PNG files, at the cost of files roughly twice as large with 16 bits.
+    ///
+    /// The image is still rendered with 8 bits per channel, which are widened so white stays white. This serves
+    /// pipelines that post-process the image with 16 bits of precision.
+    #[clap(
+        value_enum,
+        long,
+        default_value = "8",
+        conflicts_with("low_memory"),
+        help_heading = "OUTPUT"
+    )]
+    pub bit_depth: codevis::BitDepth,
+
+    /// Write the colors of the saved image to this JSON file, with their hex code and how many pixels have them,
+    /// the most common first.
+    ///
+    /// With multiple themes, the name of the theme is added to the file name like for `--output-path`.
+    #[clap(
+        long,
+        conflicts_with_all = ["per_file_output", "compare_themes", "low_memory"],
+        help_heading = "OUTPUT"
+    )]
+    pub export_palette: Option<PathBuf>,
+
+    /// Only list this many of the most common colors with `--export-palette`.
+    #[clap(long, requires("export_palette"), help_heading = "OUTPUT")]
+    pub palette_size: Option<usize>,
+
+    /// Write a JSON report to this file with the amount of files, lines, bytes and pixels that were rendered,
+    /// along with the path, lines, bytes and syntax of each file.
+    #[clap(long, value_name = "PATH.json", help_heading = "OUTPUT")]
+    pub report: Option<PathBuf>,
+
+    /// Print the BLAKE3 hash of the pixels of each image along with its path, to detect when a visualization
+    /// changed without comparing images.
+    ///
+    /// The hash is independent of `--format`, as only the dimensions and the RGB values of the pixels are hashed.
+    #[clap(long, conflicts_with("per_file_output"), help_heading = "OUTPUT")]
+    pub print_hash: bool,
+
+    /// Don't write the image, which is useful along with `--print-hash` to only compute its hash.
+    #[clap(
+        long,
+        conflicts_with_all = ["per_file_output", "open", "export_palette"],
+        help_heading = "OUTPUT"
+    )]
+    pub no_write: bool,
+
+    /// Only save the region `X,Y,WIDTH,HEIGHT` of the rendered image, in pixels.
+    #[clap(long, value_parser = parse_crop, help_heading = "OUTPUT")]
+    pub crop: Option<codevis::render::Rect>,
+
+    /// Save the image as a grid of `ROWSxCOLS` tiles, like `4x8`, for viewers that can't open huge images.
+    ///
+    /// Tiles are named after `--output-path` with their row and column appended, like `output_0_0.png`, and
+    /// `output.json` describes where each tile belongs for reassembling them. Tiles differ in size by at most a pixel.
+    #[clap(
+        long,
+        value_name = "ROWSxCOLS",
+        value_parser = parse_split_output,
+        conflicts_with_all = ["low_memory", "per_file_output", "no_write", "open"],
+        help_heading = "OUTPUT"
+    )]
+    pub split_output: Option<(u32, u32)>,
+
+    /// Drop control characters like form feeds or escapes instead of rendering them as a placeholder glyph.
+    #[clap(long, help_heading = "IMAGE")]
+    pub strip_control: bool,
+
+    /// Remove the leading whitespace that all non-blank lines of a file have in common, keeping their relative
+    /// indentation, so more of each line fits into a column.
+    #[clap(long, help_heading = "IMAGE")]
+    pub strip_leading_indentation: bool,
+
     /// The number of spaces that a tab character is equivalent to.
     #[clap(long, default_value_t = 4, help_heading = "IMAGE")]
     pub tab_spaces: u32,
+
+    /// How tabs are drawn: `expand` fills up to the next tab stop with blank cells, `glyph` draws an arrow
+    /// into a single cell, and `single` takes a single blank cell like a space.
+    ///
+    /// With `--highlight-trailing-whitespace`, tabs at the end of a line are highlighted in every mode, with
+    /// the arrow of `glyph` drawn on top of the highlight in readable mode.
+    #[clap(value_enum, long, default_value_t = codevis::render::TabMode::Expand, help_heading = "IMAGE")]
+    pub tab_mode: codevis::render::TabMode,
+
+    /// Draw each tab into exactly one blank cell, so renders of the same code stay comparable pixel by pixel
+    /// when its indentation changes.
+    ///
+    /// This is the same as `--tab-mode single`, so text after tabs doesn't line up at tab stops anymore.
+    #[clap(long, conflicts_with = "tab_mode", help_heading = "IMAGE")]
+    pub literal_tabs: bool,
+}
+
+/// The ways `--sample-files` can choose files.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SampleMode {
+    /// Choose files spread evenly across all of them in the order of their paths, so all parts of the directory remain.
+    Even,
+    /// Choose files at random, differently with every run unless `--seed` is given.
+    Random,
+}
+
+/// The ways progress can be shown.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProgressFormat {
+    /// Continuously redrawn lines for humans to read.
+    Human,
+    /// One JSON object per line for other programs to read.
+    Json,
+}
+
+impl Args {
+    /// The directory to read files from, which is required unless files are read from `--archive`.
+    pub fn input_dir(&self) -> &Path {
+        self.input_dir
+            .as_deref()
+            .expect("clap requires `--input-dir` unless `--archive` is given")
+    }
+
+    /// The desired aspect ratio of the image, from `--aspect` along with `--aspect-width` and `--aspect-height`.
+    pub fn aspect_ratio(&self) -> f64 {
+        let (width, height) = self.aspect;
+        self.aspect_width.unwrap_or(width) / self.aspect_height.unwrap_or(height)
+    }
+
+    /// How to draw tabs, which is always `single` with `--literal-tabs`.
+    pub fn tab_mode(&self) -> codevis::render::TabMode {
+        if self.literal_tabs {
+            codevis::render::TabMode::Single
+        } else {
+            self.tab_mode
+        }
+    }
+
+    /// The color of a highlight, which is its own `color` if given, or else `--highlight-color`, or else `default`.
+    pub fn highlight_color_or(
+        &self,
+        color: Option<image::Rgb<u8>>,
+        default: image::Rgb<u8>,
+    ) -> image::Rgb<u8> {
+        color.or(self.highlight_color).unwrap_or(default)
+    }
+
+    /// The strength of a highlight, which is its own `strength` if given, or else `--highlight-strength`, or else `default`.
+    pub fn highlight_strength_or(&self, strength: Option<f32>, default: f32) -> f32 {
+        strength.or(self.highlight_strength).unwrap_or(default)
+    }
+}
+
+/// Parse a `ROWSxCOLS` grid with at least one row and column.
+fn parse_split_output(value: &str) -> Result<(u32, u32), String> {
+    let (rows, columns) = value
+        .split_once('x')
+        .ok_or_else(|| format!("expected `ROWSxCOLS` like `4x8`, got {value:?}"))?;
+    let count = |count: &str| match count.trim().parse::<u32>() {
+        Ok(0) => Err(format!("{value:?} needs at least one row and column")),
+        Ok(count) => Ok(count),
+        Err(err) => Err(format!("{count:?}: {err}")),
+    };
+    Ok((count(rows)?, count(columns)?))
+}
+
+/// Parse a `WIDTH:HEIGHT` aspect with positive sides, or the name of a preset.
+fn parse_aspect(value: &str) -> Result<(f64, f64), String> {
+    if value.eq_ignore_ascii_case("a4") {
+        return Ok((210.0, 297.0));
+    }
+    let (width, height) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected `WIDTH:HEIGHT` like `16:9`, or `a4`, got {value:?}"))?;
+    let side = |side: &str| match side.trim().parse::<f64>() {
+        Ok(side) if side > 0.0 && side.is_finite() => Ok(side),
+        Ok(_) => Err(format!("the sides of {value:?} must be positive")),
+        Err(err) => Err(format!("{side:?}: {err}")),
+    };
+    Ok((side(width)?, side(height)?))
+}
+
+/// Parse a duration like `90s`, `30m`, `24h`, `7d` or `2w`.
+fn parse_duration(value: &str) -> Result<std::time::Duration, String> {
+    let unit_at = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("expected a unit like `7d` or `24h`, got {value:?}"))?;
+    let (amount, unit) = value.split_at(unit_at);
+    let seconds_per_unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "unknown unit {unit:?}, must be one of s, m, h, d or w"
+            ))
+        }
+    };
+    let amount: u64 = amount.parse().map_err(|err| format!("{value:?}: {err}"))?;
+    amount
+        .checked_mul(seconds_per_unit)
+        .map(std::time::Duration::from_secs)
+        .ok_or_else(|| format!("{value:?} is too long"))
+}
+
+/// Parse an `extension=theme` pair.
+fn parse_theme_for(value: &str) -> Result<(String, String), String> {
+    let (extension, theme) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected `extension=theme`, got {value:?}"))?;
+    Ok((
+        extension.trim_start_matches('.').to_owned(),
+        theme.to_owned(),
+    ))
+}
+
+/// Parse an `extension=chars` pair.
+fn parse_width_for(value: &str) -> Result<(String, u32), String> {
+    let (extension, chars) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected `extension=chars`, got {value:?}"))?;
+    let chars = chars
+        .parse()
+        .ok()
+        .filter(|chars| *chars > 0)
+        .ok_or_else(|| format!("{chars:?} must be a positive amount of characters"))?;
+    Ok((extension.trim_start_matches('.').to_owned(), chars))
+}
+
+/// Parse a column width in characters, with `auto` and `0` both meaning to fit the longest line as `0`.
+fn parse_column_chars(value: &str) -> Result<u32, String> {
+    if value == "auto" {
+        return Ok(0);
+    }
+    value.parse().map_err(|err| format!("{value:?}: {err}"))
+}
+
+/// Parse an opacity between 0.0 and 1.0.
+fn parse_opacity(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(opacity) if (0.0..=1.0).contains(&opacity) => Ok(opacity),
+        Ok(_) => Err(format!("expected a value between 0.0 and 1.0, got {value}")),
+        Err(err) => Err(format!("{value:?}: {err}")),
+    }
+}
+
+/// Parse a `#RRGGBB` color, with the `#` being optional.
+fn parse_color(value: &str) -> Result<image::Rgb<u8>, String> {
+    let hex = value.trim_start_matches('#');
+    let channel = |range: std::ops::Range<usize>| {
+        hex.get(range)
+            .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+    };
+    match (hex.len(), channel(0..2), channel(2..4), channel(4..6)) {
+        (6, Some(r), Some(g), Some(b)) => Ok(image::Rgb([r, g, b])),
+        _ => Err(format!("expected a color like `#RRGGBB`, got {value:?}")),
+    }
+}
+
+/// Parse a `X,Y,WIDTH,HEIGHT` region.
+fn parse_crop(value: &str) -> Result<codevis::render::Rect, String> {
+    let numbers = value
+        .split(',')
+        .map(|n| {
+            n.trim()
+                .parse::<u32>()
+                .map_err(|err| format!("{n:?}: {err}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match numbers[..] {
+        [x, y, width, height] => Ok(codevis::render::Rect {
+            x,
+            y,
+            width,
+            height,
+        }),
+        _ => Err(format!("expected `X,Y,WIDTH,HEIGHT`, got {value:?}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aspects_are_positive_sides_or_presets() {
+        assert_eq!(parse_aspect("16:9"), Ok((16.0, 9.0)));
+        assert_eq!(parse_aspect(" 1.5 : 1 "), Ok((1.5, 1.0)));
+        assert_eq!(parse_aspect("a4"), Ok((210.0, 297.0)));
+        assert_eq!(parse_aspect("A4"), Ok((210.0, 297.0)));
+
+        for value in ["0:1", "1:0", "-16:9", "16:-9", "NaN:1", "1:nan", "inf:1"] {
+            assert!(
+                parse_aspect(value)
+                    .unwrap_err()
+                    .contains("must be positive"),
+                "{value:?}"
+            );
+        }
+        for value in ["", "16", "16x9", "a5", "16:9:1", "a:b", "16:"] {
+            assert!(parse_aspect(value).is_err(), "{value:?}");
+        }
+    }
 }
@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+/// The placeholders that can be used in an output template.
+const PLACEHOLDERS: &[&str] = &["theme", "date", "width", "height", "lines", "files"];
+
+/// A file name like `viz-{theme}-{date}.png` with placeholders that are filled in once the image is rendered.
+#[derive(Debug, Clone)]
+pub struct OutputTemplate(String);
+
+/// The values of all placeholders of an [`OutputTemplate`].
+pub struct Values<'a> {
+    pub theme: &'a str,
+    pub width: u32,
+    pub height: u32,
+    pub lines: usize,
+    pub files: usize,
+}
+
+impl OutputTemplate {
+    /// Parse `template`, failing on unknown or unterminated placeholders.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        for part in template.split('{').skip(1) {
+            let name = part
+                .split_once('}')
+                .map(|(name, _)| name)
+                .ok_or_else(|| format!("unterminated placeholder in {template:?}"))?;
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "unknown placeholder {{{name}}}, must be one of {}",
+                    PLACEHOLDERS
+                        .iter()
+                        .map(|name| format!("{{{name}}}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+        Ok(OutputTemplate(template.to_owned()))
+    }
+
+    /// Return `true` if the template contains `{theme}`, so each theme gets its own file.
+    pub fn has_theme(&self) -> bool {
+        self.0.contains("{theme}")
+    }
+
+    /// Fill in all placeholders with `values`, with the date being today's in UTC like `2022-10-05`.
+    pub fn expand(&self, values: &Values<'_>) -> PathBuf {
+        self.0
+            .replace(
+                "{theme}",
+                &values.theme.replace(['(', ')'], "").replace(' ', "-"),
+            )
+            .replace("{date}", &today())
+            .replace("{width}", &values.width.to_string())
+            .replace("{height}", &values.height.to_string())
+            .replace("{lines}", &values.lines.to_string())
+            .replace("{files}", &values.files.to_string())
+            .into()
+    }
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`.
+fn today() -> String {
+    let days = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() / 86400) as i64;
+    date(days)
+}
+
+/// The date `days` after 1970-01-01 as `YYYY-MM-DD`.
+fn date(days: i64) -> String {
+    // Convert days since 1970-01-01 to a civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_and_unterminated_placeholders_are_rejected() {
+        assert!(
+            OutputTemplate::parse("viz-{theme}-{date}-{width}x{height}-{lines}-{files}.png")
+                .is_ok()
+        );
+        assert!(OutputTemplate::parse("viz.png").is_ok());
+        let err = OutputTemplate::parse("viz-{colors}.png").unwrap_err();
+        assert!(err.contains("{colors}"), "{err}");
+        for template in ["viz-{theme.png", "viz-{", "viz-{date}-{theme"] {
+            assert!(
+                OutputTemplate::parse(template)
+                    .unwrap_err()
+                    .contains("unterminated"),
+                "{template}"
+            );
+        }
+    }
+
+    #[test]
+    fn themes_are_sanitized_for_file_names() {
+        let template = OutputTemplate::parse("viz-{theme}-{width}x{height}.png").unwrap();
+        assert!(template.has_theme());
+        let path = template.expand(&Values {
+            theme: "Solarized (dark)",
+            width: 1920,
+            height: 1080,
+            lines: 0,
+            files: 0,
+        });
+        assert_eq!(path, PathBuf::from("viz-Solarized-dark-1920x1080.png"));
+    }
+
+    #[test]
+    fn days_since_the_epoch_are_dates() {
+        for (days, expected) in [
+            (0, "1970-01-01"),
+            (-1, "1969-12-31"),
+            (59, "1970-03-01"),
+            (11016, "2000-02-29"),
+            (11017, "2000-03-01"),
+            (19417, "2023-03-01"),
+            (19782, "2024-02-29"),
+            (47540, "2100-02-28"),
+            (47541, "2100-03-01"),
+        ] {
+            assert_eq!(date(days), expected, "{days}");
+        }
+    }
+}
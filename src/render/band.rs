@@ -0,0 +1,414 @@
+use crate::render::chunk::calc_offsets;
+use crate::render::function::{
+    auto_column_width_for, collect_content, indent_for, theme_by_name, CollectOptions, Collected,
+};
+use crate::render::{chunk, composite, dimension, Cache, Dimension, Options, RateLimit};
+use crate::{DirContents, Phase, RenderError, FILENAME_LINE_COUNT};
+use image::{GenericImage, GenericImageView, Pixel, Rgb, RgbImage};
+use prodash::Progress;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+
+/// Render the given files like [`render()`](crate::render()), but only keep `band_lines` lines of all columns
+/// in memory at a time, and pass them to `write_band` from the top of the image to the bottom so they can be
+/// encoded incrementally.
+///
+/// `write_band` receives the dimensions of the whole image along with each band, and the dimensions are returned
+/// once all bands are written.
+///
+/// Besides the current band, only the image of the last file rendered into each column is kept, so files reaching
+/// into the next band don't have to be rendered again. Files that continue in the next column are rendered twice
+/// though, and all rendering happens on a single thread, so this takes longer than rendering the whole image at once.
+///
+/// `threads`, `cache_dir`, `file_summary`, `background_image`, `exact_aspect`, `letterbox_color`,
+/// `highlight_files`, `max_memory`, `border`, `shadow`, `badge`, `no_split_files`, `glow_radius`,
+/// `exclude_empty_columns`, `width_for_extension`, `deadline`, `zebra_columns` and `mark_blank_files` are
+/// ignored.
+#[allow(clippy::too_many_arguments)]
+pub fn render_in_bands(
+    dir_content: &DirContents,
+    mut progress: impl Progress,
+    should_interrupt: &AtomicBool,
+    ss: &SyntaxSet,
+    ts: &ThemeSet,
+    Options {
+        column_width,
+        line_height,
+        readable,
+        show_filenames,
+        target_aspect_ratio,
+        fg_color,
+        bg_color,
+        highlight_truncated_lines,
+        display_to_be_processed_file,
+        display_rate_limit,
+        theme,
+        theme_for_extension,
+        force_full_columns,
+        plain,
+        ignore_files_without_syntax,
+        color_modulation,
+        tab_spaces,
+        tab_mode,
+        line_nums,
+        strip_control,
+        grayscale,
+        rtl_columns,
+        target_width,
+        max_columns,
+        highlight_trailing_whitespace,
+        length_gradient,
+        phase_timer,
+        canvas_color,
+        uniform_background,
+        uniform_file_height,
+        indent_by_depth,
+        render_order,
+        shuffle_seed,
+        line_spacing,
+        comment_opacity,
+        only,
+        missing_glyph,
+        mono,
+        auto_column_width,
+        grep,
+        grep_color,
+        grep_strength,
+        min_column_lines,
+        fg_boost,
+        strip_leading_indentation,
+        blend_space,
+        line_changes,
+        line_authors,
+        detect_by_content,
+        trailing_whitespace_color,
+        length_gradient_color,
+        length_gradient_strength,
+        glyph_outline,
+        glyph_outline_color,
+        ..
+    }: Options,
+    band_lines: u32,
+    mut write_band: impl FnMut((u32, u32), &RgbImage) -> std::io::Result<()>,
+) -> Result<(u32, u32), RenderError> {
+    let start = Instant::now();
+    let (line_height, char_width) = if readable { (16, 8) } else { (line_height, 1) };
+    // lines are `line_spacing` further apart, with the spacing left blank when drawing them.
+    let line_height = line_height + line_spacing;
+
+    let theme = theme_by_name(ts, theme)?;
+    let themes_by_extension = theme_for_extension
+        .iter()
+        .map(|(extension, theme)| Ok((extension.to_owned(), theme_by_name(ts, theme)?)))
+        .collect::<Result<Vec<_>, RenderError>>()?;
+
+    let Collected {
+        content,
+        total_line_count,
+        num_ignored,
+        num_truncated,
+        num_empty,
+    } = collect_content(
+        dir_content,
+        ss,
+        CollectOptions {
+            ignore_files_without_syntax,
+            detect_by_content,
+            uniform_file_height,
+            show_filenames,
+            render_order,
+            shuffle_seed,
+        },
+    )?;
+    if total_line_count == 0 {
+        return Err(RenderError::NoLines {
+            num_files: content.len() + num_empty,
+        });
+    }
+    if num_empty != 0 {
+        progress.info(format!("Skipped {num_empty} empty files"));
+    }
+    if let (Some(height), true) = (uniform_file_height, num_truncated != 0) {
+        progress.info(format!("Truncated {num_truncated} files to {height} lines"));
+    }
+    let column_width = match auto_column_width {
+        Some(max_width) => {
+            let column_width = auto_column_width_for(
+                dir_content,
+                &content,
+                max_width,
+                tab_spaces,
+                tab_mode,
+                line_nums,
+                show_filenames,
+                indent_by_depth,
+                readable,
+                strip_leading_indentation,
+            );
+            progress.info(format!("Column width: {column_width} characters"));
+            column_width
+        }
+        None => column_width,
+    };
+    let header_lines = if show_filenames {
+        FILENAME_LINE_COUNT
+    } else {
+        0
+    };
+    let total_line_count = total_line_count + content.len() as u32 * header_lines;
+    // the lines a file takes in the layout, including its filename.
+    let lines_of = |num_content_lines: usize| {
+        uniform_file_height.unwrap_or(num_content_lines as u32) + header_lines
+    };
+
+    let Dimension {
+        imgx,
+        imgy,
+        lines_per_column,
+        required_columns,
+    } = dimension::compute(
+        target_aspect_ratio,
+        target_width,
+        max_columns,
+        min_column_lines,
+        column_width * char_width,
+        total_line_count,
+        line_height,
+        force_full_columns,
+        progress.add_child("determine dimensions"),
+    )?;
+    let process_start = Instant::now();
+    if let Some(phase_timer) = phase_timer {
+        phase_timer.add(Phase::Dimension, process_start - start);
+    }
+
+    let band_lines = band_lines.clamp(1, lines_per_column);
+    progress.info(format!(
+        "Image dimensions: {imgx} x {imgy} x {channel_count} [x * y * channels], rendered in bands of {band_lines} lines ({} in memory)",
+        bytesize::ByteSize(imgx as u64 * (band_lines * line_height) as u64 * Rgb::<u8>::CHANNEL_COUNT as u64),
+        channel_count = Rgb::<u8>::CHANNEL_COUNT,
+    ));
+
+    progress.set_name("process");
+    progress.init(
+        Some(total_line_count as usize),
+        prodash::unit::label_and_mode("lines", prodash::unit::display::Mode::with_throughput())
+            .into(),
+    );
+    let cache = Cache::new(
+        ss,
+        theme,
+        themes_by_extension,
+        comment_opacity,
+        only,
+        detect_by_content,
+    );
+    let display_limit = RateLimit::new(display_rate_limit);
+    let uniform_background = uniform_background
+        .then(|| chunk::uniform_background(&cache, ss, bg_color, color_modulation))
+        .transpose()?;
+    let column_pixels = column_width * char_width;
+
+    // The last file rendered into each column as `(file_index, image, background)`.
+    let mut last_rendered =
+        vec![None::<(usize, RgbImage, Option<Rgb<u8>>)>; required_columns as usize];
+    let mut is_rendered = vec![false; content.len()];
+    // The background of the file with the highest index so far, as the corner is filled with the last one.
+    let mut last_background = None::<(usize, Option<Rgb<u8>>)>;
+    let mut longest_line_chars = 0;
+    let mut writing = Duration::ZERO;
+
+    let mut band = RgbImage::new(imgx, band_lines * line_height);
+    for first_line in (0..lines_per_column).step_by(band_lines as usize) {
+        let num_lines = band_lines.min(lines_per_column - first_line);
+        if band.height() != num_lines * line_height {
+            band = RgbImage::new(imgx, num_lines * line_height);
+        }
+
+        for column in 0..required_columns {
+            let band_start = column * lines_per_column + first_line;
+            let band_end = band_start + num_lines;
+            let first_file = content.partition_point(|&(_, num_content_lines, lines_so_far)| {
+                lines_so_far + lines_of(num_content_lines) <= band_start
+            });
+
+            let mut line = band_start;
+            for (file_index, &((path, content), num_content_lines, lines_so_far)) in
+                content.iter().enumerate().skip(first_file)
+            {
+                if lines_so_far >= band_end {
+                    break;
+                }
+                if should_interrupt.load(Ordering::Relaxed) {
+                    return Err(RenderError::Interrupted);
+                }
+
+                let last = &mut last_rendered[column as usize];
+                if last
+                    .as_ref()
+                    .map_or(true, |(index, ..)| *index != file_index)
+                {
+                    let mut highlighter = if plain {
+                        cache.new_plain_highlighter()
+                    } else {
+                        cache.highlighter_for_file(path, content)
+                    };
+                    let relative_path = &dir_content.relative_path(path);
+                    if display_to_be_processed_file
+                        && !is_rendered[file_index]
+                        && display_limit.allow()
+                    {
+                        progress.info(relative_path.display().to_string())
+                    }
+                    // padding for the uniform file height is added when copying lines into the band.
+                    let mut img = RgbImage::new(
+                        column_pixels,
+                        (num_content_lines as u32 + header_lines) * line_height,
+                    );
+                    let out = chunk::process(
+                        relative_path,
+                        content,
+                        &mut img,
+                        None,
+                        |line| highlighter.highlight_line(line, ss),
+                        chunk::Context {
+                            column_width,
+                            line_height,
+                            char_width,
+                            total_line_count,
+                            highlight_truncated_lines,
+                            line_num: 0,
+                            lines_per_column: total_line_count,
+                            fg_color,
+                            bg_color,
+                            file_index,
+                            color_modulation,
+                            tab_spaces,
+                            tab_mode,
+                            readable,
+                            show_filenames,
+                            line_nums,
+                            strip_control,
+                            required_columns: 1,
+                            rtl_columns: false,
+                            highlight_trailing_whitespace,
+                            length_gradient,
+                            canvas_color,
+                            uniform_background,
+                            line_changes: line_changes
+                                .and_then(|changes| changes.of(relative_path)),
+                            line_authors: line_authors
+                                .and_then(|authors| authors.of(relative_path)),
+                            indent: indent_for(relative_path, indent_by_depth, column_width),
+                            missing_glyph,
+                            line_spacing,
+                            mono,
+                            grep,
+                            grep_color,
+                            grep_strength,
+                            fg_boost,
+                            strip_leading_indentation,
+                            blend_space,
+                            trailing_whitespace_color,
+                            length_gradient_color,
+                            length_gradient_strength,
+                            glyph_outline,
+                            glyph_outline_color,
+                        },
+                    )?;
+                    longest_line_chars = out.longest_line_in_chars.max(longest_line_chars);
+                    if last_background.map_or(true, |(last_index, _)| file_index > last_index) {
+                        last_background = Some((file_index, out.background));
+                    }
+                    if !is_rendered[file_index] {
+                        is_rendered[file_index] = true;
+                        progress.inc_by(num_content_lines);
+                    }
+                    *last = Some((file_index, img, out.background));
+                }
+                let (_, img, background) = last.as_ref().expect("rendered above");
+
+                let file_end = (lines_so_far + lines_of(num_content_lines)).min(band_end);
+                for line in line..file_end {
+                    let line_in_file = line - lines_so_far;
+                    let (x, _) = calc_offsets(
+                        line,
+                        lines_per_column,
+                        column_width,
+                        line_height,
+                        required_columns,
+                        rtl_columns,
+                    );
+                    let (x, y) = (x * char_width, (line - band_start) * line_height);
+                    if (line_in_file + 1) * line_height <= img.height() {
+                        band.copy_from(
+                            &*img.view(0, line_in_file * line_height, column_pixels, line_height),
+                            x,
+                            y,
+                        )
+                        .expect("line fits into the band");
+                    } else {
+                        chunk::fill_rect(
+                            &mut band,
+                            x,
+                            y,
+                            column_pixels,
+                            line_height,
+                            background.unwrap_or(Rgb([0, 0, 0])),
+                        );
+                    }
+                }
+                line = line.max(file_end);
+            }
+
+            // fill in any empty bottom right corner, with background color
+            let background = last_background.and_then(|(_, background)| background);
+            for line in line..band_end {
+                let (x, _) = calc_offsets(
+                    line,
+                    lines_per_column,
+                    column_width,
+                    line_height,
+                    required_columns,
+                    rtl_columns,
+                );
+                chunk::fill_rect(
+                    &mut band,
+                    x * char_width,
+                    (line - band_start) * line_height,
+                    column_pixels,
+                    line_height,
+                    background.unwrap_or(Rgb([0, 0, 0])),
+                );
+            }
+        }
+
+        if grayscale {
+            composite::grayscale(&mut band);
+        }
+        let write_start = Instant::now();
+        write_band((imgx, imgy), &band)?;
+        writing += write_start.elapsed();
+    }
+
+    if let Some(phase_timer) = phase_timer {
+        phase_timer.add(Phase::Process, process_start.elapsed() - writing);
+    }
+    progress.show_throughput(start);
+    progress.info(format!(
+        "Longest encountered line in chars: {longest_line_chars}"
+    ));
+    if num_ignored != 0 {
+        progress.info(format!("Ignored {num_ignored} files due to missing syntax",))
+    }
+    if display_limit.skipped() != 0 {
+        progress.info(format!(
+            "Skipped displaying {} files to stay within the rate limit",
+            display_limit.skipped()
+        ))
+    }
+
+    Ok((imgx, imgy))
+}
@@ -1,11 +1,18 @@
-use crate::render::{BgColor, FgColor};
+use crate::render::{BgColor, BlendSpace, Cache, FgColor, Rect, TabMode};
+use crate::LineChange;
 use bstr::ByteSlice;
-use image::{ImageBuffer, Rgb};
-use std::ops::{Deref, DerefMut};
+use image::{GrayImage, ImageBuffer, Luma, Pixel, Rgb};
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut, Range};
 use std::path::Path;
 use syntect::highlighting::{Color, Style};
+use syntect::parsing::SyntaxSet;
+use unicode_width::UnicodeWidthChar;
 use unifont_bitmap::Unifont;
 
+/// The value of pixels in a coverage image that a glyph was drawn on.
+pub(crate) const COVERED: Luma<u8> = Luma([u8::MAX]);
+
 /// The result of processing a chunk.
 pub struct Outcome {
     /// The longest line we encountered in unicode codepoints.
@@ -14,7 +21,7 @@ pub struct Outcome {
     pub background: Option<Rgb<u8>>,
 }
 
-pub struct Context {
+pub struct Context<'a> {
     pub column_width: u32,
     pub line_height: u32,
     pub char_width: u32,
@@ -32,22 +39,245 @@ pub struct Context {
     pub readable: bool,
     pub show_filenames: bool,
     pub line_nums: bool,
+    pub strip_control: bool,
+    pub required_columns: u32,
+    pub rtl_columns: bool,
+    pub highlight_trailing_whitespace: bool,
+    pub length_gradient: Option<u32>,
+    pub canvas_color: Option<Rgb<u8>>,
+    /// If set, the background to use for all lines instead of the one derived from their style.
+    pub uniform_background: Option<Rgb<u8>>,
+    /// The amount of cells to leave empty at the start of every line.
+    pub indent: u32,
+    /// The character to draw in place of characters without a glyph.
+    pub missing_glyph: Option<char>,
+    /// The amount of pixel rows at the bottom of each line that are left blank, which are part of `line_height`.
+    pub line_spacing: u32,
+    /// If set, the `(foreground, background)` colors to use for all styles.
+    pub mono: Option<(Rgb<u8>, Rgb<u8>)>,
+    /// If set, lines matching this expression are tinted towards `grep_color`.
+    pub grep: Option<&'a regex::Regex>,
+    /// The color that lines matching `grep` are tinted towards.
+    pub grep_color: Rgb<u8>,
+    /// How much of `grep_color` is mixed into lines matching `grep`, from 0.0 to 1.0.
+    pub grep_strength: f32,
+    /// How much brighter than the style color glyphs get with `FgColor::StyleAsciiBrightness`.
+    pub fg_boost: f32,
+    /// Whether to remove the leading whitespace that all non-blank lines have in common.
+    pub strip_leading_indentation: bool,
+    /// The color space in which to blend colors.
+    pub blend_space: BlendSpace,
+    /// How tabs are drawn.
+    pub tab_mode: TabMode,
+    /// If set, the changes of each line of the file, with lines tinted in their color.
+    pub line_changes: Option<&'a [LineChange]>,
+    /// If set, the color of the author of each line of the file, with lines tinted in it.
+    pub line_authors: Option<&'a [Option<Rgb<u8>>]>,
+    /// The color of trailing whitespace with `highlight_trailing_whitespace`.
+    pub trailing_whitespace_color: Rgb<u8>,
+    /// The color that characters of long lines are shaded towards with `length_gradient`.
+    pub length_gradient_color: Rgb<u8>,
+    /// How much of `length_gradient_color` is mixed in at the end of a column.
+    pub length_gradient_strength: f32,
+    /// Whether to outline readable glyphs.
+    pub glyph_outline: bool,
+    /// The color of the outline of glyphs, or `None` to darken their background.
+    pub glyph_outline_color: Option<Rgb<u8>>,
+}
+
+impl Context<'_> {
+    /// Hash everything that affects the appearance of a file rendered into its own column image with this context.
+    ///
+    /// Fields that only affect where lines are placed in the final image are ignored.
+    pub fn hash_appearance(&self, state: &mut impl Hasher) {
+        let Context {
+            column_width,
+            line_height,
+            char_width,
+            total_line_count: _,
+            line_num: _,
+            lines_per_column: _,
+            fg_color,
+            bg_color,
+            highlight_truncated_lines,
+            file_index,
+            color_modulation,
+            tab_spaces,
+            readable,
+            show_filenames,
+            line_nums,
+            strip_control,
+            required_columns: _,
+            rtl_columns: _,
+            highlight_trailing_whitespace,
+            length_gradient,
+            canvas_color,
+            uniform_background,
+            indent,
+            missing_glyph,
+            line_spacing,
+            mono,
+            grep,
+            grep_color,
+            grep_strength,
+            fg_boost,
+            strip_leading_indentation,
+            blend_space,
+            tab_mode,
+            line_changes,
+            line_authors,
+            trailing_whitespace_color,
+            length_gradient_color,
+            length_gradient_strength,
+            glyph_outline,
+            glyph_outline_color,
+        } = self;
+        column_width.hash(state);
+        line_height.hash(state);
+        char_width.hash(state);
+        fg_color.hash(state);
+        bg_color.hash(state);
+        highlight_truncated_lines.hash(state);
+        // Only even and odd files can look different.
+        (file_index % 2).hash(state);
+        color_modulation.to_bits().hash(state);
+        tab_spaces.hash(state);
+        tab_mode.hash(state);
+        readable.hash(state);
+        show_filenames.hash(state);
+        line_nums.hash(state);
+        strip_control.hash(state);
+        highlight_trailing_whitespace.hash(state);
+        trailing_whitespace_color.hash(state);
+        length_gradient.hash(state);
+        length_gradient_color.hash(state);
+        length_gradient_strength.to_bits().hash(state);
+        canvas_color.hash(state);
+        uniform_background.hash(state);
+        indent.hash(state);
+        missing_glyph.hash(state);
+        line_spacing.hash(state);
+        mono.hash(state);
+        grep.map(regex::Regex::as_str).hash(state);
+        line_changes.hash(state);
+        line_authors.hash(state);
+        grep_color.hash(state);
+        grep_strength.to_bits().hash(state);
+        fg_boost.to_bits().hash(state);
+        strip_leading_indentation.hash(state);
+        blend_space.hash(state);
+        glyph_outline.hash(state);
+        glyph_outline_color.hash(state);
+    }
+}
+
+/// The leading spaces and tabs that all non-blank lines of `content` have in common, like with `textwrap::dedent`.
+pub(crate) fn common_indent(content: &str) -> &str {
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    let Some(first) = lines.next() else {
+        return "";
+    };
+    let mut indent = &first[..first.len() - first.trim_start_matches([' ', '\t']).len()];
+    for line in lines {
+        let common = indent
+            .bytes()
+            .zip(line.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        indent = &indent[..common];
+    }
+    indent
+}
+
+/// The amount of leading bytes of `line` to remove to strip a common indent of `dedent` bytes.
+///
+/// Blank lines may be shorter than the common indent, or be indented differently.
+pub(crate) fn indent_to_strip(line: &[u8], dedent: usize) -> usize {
+    line.iter()
+        .take(dedent)
+        .take_while(|b| matches!(b, b' ' | b'\t'))
+        .count()
+}
+
+/// How much of the color of a [`LineChange`] is mixed into lines that changed.
+const LINE_CHANGE_STRENGTH: f32 = 0.4;
+
+/// How much of the color of their author is mixed into lines.
+const LINE_AUTHOR_STRENGTH: f32 = 0.35;
+
+/// How much the background around glyphs is darkened for their outline, if it has no color of its own.
+const GLYPH_OUTLINE_DARKENING: f32 = 0.75;
+
+/// The glyph to draw in place of tabs with [`TabMode::Glyph`].
+const TAB_GLYPH: char = '\u{2192}';
+
+/// The glyph to draw in place of control characters, like form feeds or escapes.
+const CONTROL_CHAR_PLACEHOLDER: char = '\u{25AF}';
+
+/// Return `true` for control characters that aren't handled as whitespace.
+pub(crate) fn is_stray_control_char(chr: char) -> bool {
+    chr.is_control() && !matches!(chr, '\t' | '\n' | '\r')
 }
 
 /// Return the `(x, y)` offsets to apply to the given line, to wrap columns of lines into the
 /// target image.
+///
+/// With `rtl_columns`, columns are filled from right to left in an image that is `required_columns` wide.
 pub fn calc_offsets(
     line_num: u32,
     lines_per_column: u32,
     column_width: u32,
     line_height: u32,
+    required_columns: u32,
+    rtl_columns: bool,
 ) -> (u32, u32) {
+    let column = line_num / lines_per_column;
+    let column = if rtl_columns {
+        required_columns - 1 - column
+    } else {
+        column
+    };
     (
-        (line_num / lines_per_column) * column_width,
+        column * column_width,
         (line_num % lines_per_column) * line_height,
     )
 }
 
+/// Return the regions of the image covered by the `num_lines` lines starting at `first_line`, one for each column
+/// they are placed in.
+pub(crate) fn line_rects(
+    first_line: u32,
+    num_lines: u32,
+    lines_per_column: u32,
+    column_width: u32,
+    line_height: u32,
+    required_columns: u32,
+    rtl_columns: bool,
+) -> Vec<Rect> {
+    let mut rects = Vec::new();
+    let mut line = first_line;
+    let end = first_line + num_lines;
+    while line < end {
+        let lines_in_column = (lines_per_column - line % lines_per_column).min(end - line);
+        let (x, y) = calc_offsets(
+            line,
+            lines_per_column,
+            column_width,
+            line_height,
+            required_columns,
+            rtl_columns,
+        );
+        rects.push(Rect {
+            x,
+            y,
+            width: column_width,
+            height: lines_in_column * line_height,
+        });
+        line += lines_in_column;
+    }
+    rects
+}
+
 /// Ensures a string has a minimum number of characters in it by
 /// appending spaces to the beginging of the string if its characters
 /// are too few. This is used for formatting the spacing of line_nums.
@@ -66,10 +296,14 @@ fn ensure_width(str: String, width: u32) -> String {
 /// This function can be used to render one file/string of text, to a single image,
 /// or called repeatedly on different files/strings, and passed the same image, to
 /// render different bodies of text in different areas of the same image.
+///
+/// If `coverage` is set, the pixels of all glyphs are marked in it, so they can be told apart from the background
+/// even if they have its color.
 pub fn process<C>(
     filepath: &Path,
     content: &str,
     img: &mut ImageBuffer<Rgb<u8>, C>,
+    mut coverage: Option<&mut GrayImage>,
     mut highlight: impl FnMut(&str) -> Result<Vec<(Style, &str)>, syntect::Error>,
     Context {
         column_width,
@@ -87,13 +321,46 @@ pub fn process<C>(
         readable,
         show_filenames,
         line_nums,
-    }: Context,
-) -> anyhow::Result<Outcome>
+        strip_control,
+        required_columns,
+        rtl_columns,
+        highlight_trailing_whitespace,
+        length_gradient,
+        canvas_color,
+        uniform_background,
+        indent,
+        missing_glyph,
+        line_spacing,
+        mono,
+        grep,
+        grep_color,
+        grep_strength,
+        fg_boost,
+        strip_leading_indentation,
+        blend_space,
+        tab_mode,
+        line_changes,
+        line_authors,
+        trailing_whitespace_color,
+        length_gradient_color,
+        length_gradient_strength,
+        glyph_outline,
+        glyph_outline_color,
+    }: Context<'_>,
+) -> Result<Outcome, syntect::Error>
 where
     C: Deref<Target = [u8]>,
     C: DerefMut,
 {
     let mut unifont = Unifont::open();
+    // lines are placed `line_height` apart, but glyphs only take the rows above the spacing.
+    let (line_height, line_stride) = (line_height - line_spacing, line_height);
+
+    let dedent = if strip_leading_indentation {
+        common_indent(content).len()
+    } else {
+        0
+    };
 
     let largest_line_num_width = if line_nums {
         format!("{}", content.lines().count()).len() + 1
@@ -103,8 +370,32 @@ where
         0
     };
 
-    let style = highlight(" ")?[0].0;
-    let initial_forground_color = Rgb([style.foreground.r, style.foreground.g, style.foreground.b]);
+    // In mono mode all styles have the same colors, which are used as they are.
+    let (fg_color, bg_color, canvas_color, uniform_background) = if mono.is_some() {
+        (FgColor::Style, BgColor::Style, None, None)
+    } else {
+        (fg_color, bg_color, canvas_color, uniform_background)
+    };
+    // The canvas color replaces the background behind brightness-encoded glyphs.
+    let to_background = |style: Style| match (canvas_color, uniform_background) {
+        (Some(canvas_color), _) if fg_color == FgColor::StyleAsciiBrightness => canvas_color,
+        (_, Some(background)) => background,
+        _ => bg_color.to_rgb(style, file_index, color_modulation),
+    };
+    // without a color of their own, outlines are a darker version of the background they are drawn on.
+    let outline_for = |background: &Rgb<u8>| {
+        glyph_outline.then(|| {
+            glyph_outline_color.unwrap_or_else(|| {
+                blend_space.mix(*background, Rgb([0, 0, 0]), GLYPH_OUTLINE_DARKENING)
+            })
+        })
+    };
+
+    let style = restyle(highlight(" ")?[0].0, mono);
+    let initial_forground_color = match fg_color {
+        FgColor::Fixed(color) => color,
+        _ => Rgb([style.foreground.r, style.foreground.g, style.foreground.b]),
+    };
 
     // write the filename
     if show_filenames {
@@ -112,8 +403,7 @@ where
         // let style = highlight(" ")?[0].0;
         // println!("style: {:#?}", style);
         let mut background = None::<Rgb<u8>>;
-        let background =
-            background.get_or_insert_with(|| bg_color.to_rgb(style, file_index, color_modulation));
+        let background = background.get_or_insert_with(|| to_background(style));
 
         // figure out where in the image to write
         let actual_line = line_num % total_line_count;
@@ -121,22 +411,36 @@ where
             actual_line,
             lines_per_column,
             column_width * char_width,
-            line_height,
+            line_stride,
+            required_columns,
+            rtl_columns,
         );
+        let area = LineArea {
+            x: cur_column_x_offset,
+            y: cur_y,
+            char_width,
+            line_height,
+        };
 
         // write filename on image
         // let char_color = Rgb([255, 255, 255]);
-        let mut cur_line_x = 0;
+        let mut cur_line_x = put_indent(img, area, indent, readable, *background);
         for chr in filepath.to_str().unwrap().chars() {
+            if !fits_in_column(chr, cur_line_x, column_width, readable) {
+                break;
+            }
             if readable {
                 put_readable_char_in_image(
                     chr,
                     &mut unifont,
+                    missing_glyph,
                     cur_column_x_offset + cur_line_x * char_width,
                     cur_y,
                     img,
+                    coverage.as_deref_mut(),
                     &background,
                     &initial_forground_color,
+                    outline_for(&background),
                     &mut cur_line_x,
                 );
             } else {
@@ -146,6 +450,7 @@ where
                     img_x,
                     cur_y,
                     img,
+                    coverage.as_deref_mut(),
                     initial_forground_color,
                     line_height,
                     char_width,
@@ -155,34 +460,15 @@ where
         }
 
         // Fill the rest of the line with the background color.
-        if readable {
-            while cur_line_x < column_width {
-                put_readable_char_in_image(
-                    ' ',
-                    &mut unifont,
-                    cur_column_x_offset + cur_line_x * char_width,
-                    cur_y,
-                    img,
-                    background,
-                    background,
-                    &mut cur_line_x,
-                );
-            }
-        } else {
-            while cur_line_x < column_width * char_width {
-                // Fill the char space with a solid color.
-                let img_x = cur_column_x_offset + cur_line_x;
-                put_solid_char_in_image(
-                    img_x,
-                    cur_y,
-                    img,
-                    *background,
-                    line_height,
-                    char_width,
-                    &mut cur_line_x,
-                );
-            }
-        }
+        fill_rest_of_line(img, area, cur_line_x, column_width, readable, *background);
+        fill_rect(
+            img,
+            cur_column_x_offset,
+            cur_y + line_height,
+            column_width * char_width,
+            line_spacing,
+            *background,
+        );
 
         line_num += 1;
     }
@@ -193,14 +479,34 @@ where
     for (file_line_num, line) in content.as_bytes().lines_with_terminator().enumerate() {
         // make file_line_num that of the file.
         let file_line_num = file_line_num + 1;
+        let line = &line[indent_to_strip(line, dedent)..];
 
+        // match the whole line, even if only part of it is drawn, but without its line ending so `$` matches at its end.
+        let is_grep_match = grep.map_or(false, |grep| {
+            grep.is_match(
+                line.to_str()
+                    .expect("UTF-8 was source")
+                    .trim_end_matches(['\r', '\n']),
+            )
+        });
+        // the byte range of spaces and tabs before the line terminator, if they are to be highlighted.
+        // It's found in the whole line so spaces are only highlighted if nothing follows them, even if it isn't drawn.
+        let trailing_whitespace = if highlight_trailing_whitespace {
+            let content = line
+                .to_str()
+                .expect("UTF-8 was source")
+                .trim_end_matches(['\n', '\r']);
+            content.trim_end_matches([' ', '\t']).len()..content.len()
+        } else {
+            0..0
+        };
         let (line, truncated_line) = {
             let line = line.to_str().expect("UTF-8 was source");
             let mut num_chars = 0;
             let mut chars = line.chars();
             let bytes_till_char_limit: usize = chars
                 .by_ref()
-                .take(column_width as usize)
+                .take((column_width - indent) as usize)
                 .map(|c| {
                     num_chars += 1;
                     c.len_utf8()
@@ -208,7 +514,7 @@ where
                 .sum();
             num_chars += chars.count();
             longest_line_in_chars = longest_line_in_chars.max(num_chars);
-            let possibly_truncated_line = (num_chars >= column_width as usize)
+            let possibly_truncated_line = (num_chars >= (column_width - indent) as usize)
                 .then(|| &line[..bytes_till_char_limit])
                 .unwrap_or(line);
             (
@@ -226,21 +532,33 @@ where
             actual_line,
             lines_per_column,
             column_width * char_width,
-            line_height,
+            line_stride,
+            required_columns,
+            rtl_columns,
         );
+        let area = LineArea {
+            x: cur_column_x_offset,
+            y: cur_y,
+            char_width,
+            line_height,
+        };
         let storage;
         let array_storage;
 
         let regions: &[_] = if line.len() > 1024 * 16 {
-            array_storage = [(default_bg_color(background), truncated_line)];
+            array_storage = [(restyle(default_bg_color(background), mono), truncated_line)];
             &array_storage
         } else {
-            storage = highlight(line)?;
+            storage = highlight(line)?
+                .into_iter()
+                .map(|(style, region)| (restyle(style, mono), region))
+                .collect::<Vec<_>>();
             &storage
         };
-        let background = background
-            .get_or_insert_with(|| bg_color.to_rgb(regions[0].0, file_index, color_modulation));
-        let mut cur_line_x = 0;
+        let background = background.get_or_insert_with(|| to_background(regions[0].0));
+        let mut cur_line_x = put_indent(img, area, indent, readable, *background);
+        // lines are only ever cut off at the end, so offsets into the drawn part are offsets into the whole line.
+        let mut byte_offset = 0;
 
         // draw file_line_num for this line
         if line_nums {
@@ -250,15 +568,21 @@ where
             let file_line_num_char_color = initial_forground_color;
             // let file_line_num_char_color = Rgb([255, 255, 255]);
             for chr in line_num_string.chars() {
+                if !fits_in_column(chr, cur_line_x, column_width, readable) {
+                    break;
+                }
                 if readable {
                     put_readable_char_in_image(
                         chr,
                         &mut unifont,
+                        missing_glyph,
                         cur_column_x_offset + cur_line_x * char_width,
                         cur_y,
                         img,
+                        coverage.as_deref_mut(),
                         background,
                         &file_line_num_char_color,
+                        outline_for(background),
                         &mut cur_line_x,
                     );
                 } else {
@@ -273,6 +597,7 @@ where
                         img_x,
                         cur_y,
                         img,
+                        coverage.as_deref_mut().filter(|_| chr != ' '),
                         color,
                         line_height,
                         char_width,
@@ -283,8 +608,10 @@ where
         }
 
         // Draw the line on the image.
+        let content_start_x = cur_line_x;
+        let mut content_end_x = cur_line_x;
         for (style, region) in regions {
-            if cur_line_x >= column_width * char_width {
+            if cur_line_x >= column_width {
                 break;
             }
             if region.is_empty() {
@@ -292,7 +619,24 @@ where
             }
 
             for chr in region.chars() {
-                if cur_line_x >= column_width * char_width {
+                let is_trailing_whitespace = trailing_whitespace.contains(&byte_offset);
+                let whitespace_color = if is_trailing_whitespace {
+                    &trailing_whitespace_color
+                } else {
+                    &*background
+                };
+                byte_offset += chr.len_utf8();
+
+                // Control characters have no reasonable glyph, so they are all drawn the same way.
+                let chr = if is_stray_control_char(chr) {
+                    if strip_control {
+                        continue;
+                    }
+                    CONTROL_CHAR_PLACEHOLDER
+                } else {
+                    chr
+                };
+                if !fits_in_column(chr, cur_line_x, column_width, readable) {
                     break;
                 }
 
@@ -302,19 +646,28 @@ where
                     }
                     FgColor::StyleAsciiBrightness => {
                         let fg_byte = (chr as usize) & 0xff;
-                        let boost = 2.4;
+                        let channel = |style_channel: u8| {
+                            (((fg_byte * style_channel as usize) as f32 / u16::MAX as f32)
+                                * fg_boost
+                                * 256.0)
+                                .clamp(0.0, 255.0) as u8
+                        };
                         Rgb([
-                            (((fg_byte * style.foreground.r as usize) as f32 / u16::MAX as f32)
-                                * boost
-                                * 256.0) as u8,
-                            (((fg_byte * style.foreground.g as usize) as f32 / u16::MAX as f32)
-                                * boost
-                                * 256.0) as u8,
-                            (((fg_byte * style.foreground.b as usize) as f32 / u16::MAX as f32)
-                                * boost
-                                * 256.0) as u8,
+                            channel(style.foreground.r),
+                            channel(style.foreground.g),
+                            channel(style.foreground.b),
                         ])
                     }
+                    FgColor::ThemeDefault | FgColor::Fixed(_) => initial_forground_color,
+                };
+
+                // Only faded foregrounds are translucent, as the highlighter ignores the alpha of themes,
+                // and they are blended into the background unless all glyphs have the same color.
+                let char_color = match fg_color {
+                    FgColor::ThemeDefault | FgColor::Fixed(_) => char_color,
+                    FgColor::Style | FgColor::StyleAsciiBrightness => {
+                        blend(char_color, *background, style.foreground.a, blend_space)
+                    }
                 };
 
                 if chr == ' ' || chr == '\n' || chr == '\r' {
@@ -322,11 +675,14 @@ where
                         put_readable_char_in_image(
                             ' ',
                             &mut unifont,
+                            missing_glyph,
                             cur_column_x_offset + cur_line_x * char_width,
                             cur_y,
                             img,
-                            background,
+                            None,
+                            whitespace_color,
                             &char_color,
+                            outline_for(whitespace_color),
                             &mut cur_line_x,
                         );
                     } else {
@@ -336,17 +692,51 @@ where
                             img_x,
                             cur_y,
                             img,
-                            *background,
+                            None,
+                            *whitespace_color,
+                            line_height,
+                            char_width,
+                            &mut cur_line_x,
+                        );
+                    }
+                } else if chr == '\t' && tab_mode == TabMode::Glyph {
+                    if readable {
+                        put_readable_char_in_image(
+                            TAB_GLYPH,
+                            &mut unifont,
+                            missing_glyph,
+                            cur_column_x_offset + cur_line_x * char_width,
+                            cur_y,
+                            img,
+                            coverage.as_deref_mut(),
+                            whitespace_color,
+                            &char_color,
+                            outline_for(whitespace_color),
+                            &mut cur_line_x,
+                        );
+                    } else {
+                        // trailing tabs are highlighted rather than drawn.
+                        let img_x = cur_column_x_offset + cur_line_x;
+                        put_solid_char_in_image(
+                            img_x,
+                            cur_y,
+                            img,
+                            coverage.as_deref_mut().filter(|_| !is_trailing_whitespace),
+                            if is_trailing_whitespace {
+                                *whitespace_color
+                            } else {
+                                char_color
+                            },
                             line_height,
                             char_width,
                             &mut cur_line_x,
                         );
                     }
                 } else if chr == '\t' {
-                    let spaces_to_add = tab_spaces - (cur_line_x % tab_spaces);
+                    let spaces_to_add = tab_mode.cells(cur_line_x, tab_spaces);
 
                     for _ in 0..spaces_to_add {
-                        if cur_line_x >= column_width * char_width {
+                        if cur_line_x >= column_width {
                             break;
                         }
 
@@ -354,11 +744,14 @@ where
                             put_readable_char_in_image(
                                 ' ',
                                 &mut unifont,
+                                missing_glyph,
                                 cur_column_x_offset + cur_line_x * char_width,
                                 cur_y,
                                 img,
-                                background,
+                                None,
+                                whitespace_color,
                                 &char_color,
+                                outline_for(whitespace_color),
                                 &mut cur_line_x,
                             );
                         } else {
@@ -368,7 +761,8 @@ where
                                 img_x,
                                 cur_y,
                                 img,
-                                *background,
+                                None,
+                                *whitespace_color,
                                 line_height,
                                 char_width,
                                 &mut cur_line_x,
@@ -379,11 +773,14 @@ where
                     put_readable_char_in_image(
                         chr,
                         &mut unifont,
+                        missing_glyph,
                         cur_column_x_offset + cur_line_x * char_width,
                         cur_y,
                         img,
+                        coverage.as_deref_mut(),
                         background,
                         &char_color,
+                        outline_for(background),
                         &mut cur_line_x,
                     );
                 } else {
@@ -393,44 +790,92 @@ where
                         img_x,
                         cur_y,
                         img,
+                        coverage.as_deref_mut(),
                         char_color,
                         line_height,
                         char_width,
                         &mut cur_line_x,
                     );
                 }
+                if chr != '\n' && chr != '\r' {
+                    content_end_x = cur_line_x;
+                }
             }
         }
 
+        if let Some(threshold) = length_gradient {
+            shade_long_line(
+                img,
+                area,
+                content_start_x + threshold..content_end_x,
+                column_width,
+                Tint {
+                    color: length_gradient_color,
+                    strength: length_gradient_strength,
+                },
+                blend_space,
+            );
+        }
+
         // Fill the rest of the line with the background color.
-        if readable {
-            while cur_line_x < column_width {
-                put_readable_char_in_image(
-                    ' ',
-                    &mut unifont,
-                    cur_column_x_offset + cur_line_x * char_width,
-                    cur_y,
-                    img,
-                    background,
-                    background,
-                    &mut cur_line_x,
-                );
-            }
-        } else {
-            while cur_line_x < column_width * char_width {
-                // Fill the char space with a solid color.
-                let img_x = cur_column_x_offset + cur_line_x;
-                put_solid_char_in_image(
-                    img_x,
-                    cur_y,
-                    img,
-                    *background,
-                    line_height,
-                    char_width,
-                    &mut cur_line_x,
-                );
-            }
+        fill_rest_of_line(img, area, cur_line_x, column_width, readable, *background);
+        if is_grep_match {
+            tint_rect(
+                img,
+                cur_column_x_offset,
+                cur_y,
+                column_width * char_width,
+                line_height,
+                Tint {
+                    color: grep_color,
+                    strength: grep_strength,
+                },
+                blend_space,
+            );
+        }
+        if let Some(color) = line_changes
+            .and_then(|changes| changes.get(file_line_num - 1))
+            .and_then(|change| change.color())
+        {
+            tint_rect(
+                img,
+                cur_column_x_offset,
+                cur_y,
+                column_width * char_width,
+                line_height,
+                Tint {
+                    color,
+                    strength: LINE_CHANGE_STRENGTH,
+                },
+                blend_space,
+            );
         }
+        if let Some(color) = line_authors
+            .and_then(|authors| authors.get(file_line_num - 1))
+            .copied()
+            .flatten()
+        {
+            tint_rect(
+                img,
+                cur_column_x_offset,
+                cur_y,
+                column_width * char_width,
+                line_height,
+                Tint {
+                    color,
+                    strength: LINE_AUTHOR_STRENGTH,
+                },
+                blend_space,
+            );
+        }
+        fill_rect(
+            img,
+            cur_column_x_offset,
+            cur_y + line_height,
+            column_width * char_width,
+            line_spacing,
+            *background,
+        );
 
         line_num += 1;
     }
@@ -441,38 +886,84 @@ where
     })
 }
 
+/// Return `true` if the font has a glyph of its own for `chr`.
+///
+/// Characters without one are looked up as `U+FFFD REPLACEMENT CHARACTER` once its page is loaded, so that's what
+/// they are told apart by.
+fn has_glyph(unifont: &mut Unifont, chr: char) -> bool {
+    const REPLACEMENT: u32 = char::REPLACEMENT_CHARACTER as u32;
+    if chr == char::REPLACEMENT_CHARACTER {
+        return true;
+    }
+    unifont.load_page(u32::from(chr) >> 8);
+    unifont.load_page(REPLACEMENT >> 8);
+    unifont.get_bitmap(chr.into()).map_or(false, |bitmap| {
+        Some(bitmap) != unifont.get_bitmap(REPLACEMENT)
+    })
+}
+
+/// Draw the glyph of `chr` in `text_color` on `background_color`, and if `outline_color` is set, paint the
+/// background pixels next to the glyph in it, as far as they are within the cells of `chr`.
+///
+/// The pixels of the glyph are marked in `coverage`, if set.
+#[allow(clippy::too_many_arguments)]
 fn put_readable_char_in_image<C>(
     chr: char,
     unifont: &mut Unifont,
+    missing_glyph: Option<char>,
     img_x: u32,
     img_y: u32,
     img: &mut ImageBuffer<Rgb<u8>, C>,
+    mut coverage: Option<&mut GrayImage>,
     background_color: &Rgb<u8>,
     text_color: &Rgb<u8>,
+    outline_color: Option<Rgb<u8>>,
     cur_line_x: &mut u32,
 ) where
     C: Deref<Target = [u8]>,
     C: DerefMut,
 {
+    // the glyph is fit into the cells of the original character, so a replacement doesn't shift the rest of the line.
+    let cells = glyph_cells(chr);
+    if cells == 0 {
+        return;
+    }
+    let chr = match missing_glyph {
+        Some(replacement) if !has_glyph(unifont, chr) => replacement,
+        _ => chr,
+    };
     let bitmap = unifont.load_bitmap(chr.into());
 
     // get bitmap dimensions
     let char_height = 16;
     // let standard_char_width = 8;
-    let char_width = if bitmap.is_wide() { 16 } else { 8 };
+    let bitmap_width = if bitmap.is_wide() { 16 } else { 8 };
+    // glyphs are cut off or padded with the background to the width of their cells.
+    let char_width = cells * 8;
+
+    // get pixel from bitmap
+    let is_set = |x: u32, y: usize| {
+        if x >= bitmap_width || y >= char_height {
+            false
+        } else if bitmap.is_wide() {
+            bitmap.get_bytes()[y * 2 + x as usize / 8] & (1 << (7 - x % 8)) != 0
+        } else {
+            bitmap.get_bytes()[y] & (1 << (7 - x)) != 0
+        }
+    };
+    // background pixels are part of the outline if one of their 8 neighbors is set.
+    let is_outline = |x: u32, y: usize| {
+        (x.saturating_sub(1)..=x + 1)
+            .flat_map(|nx| (y.saturating_sub(1)..=y + 1).map(move |ny| (nx, ny)))
+            .any(|(nx, ny)| is_set(nx, ny))
+    };
 
     // add bitmap to image
-    for y in 0..char_height as usize {
+    for y in 0..char_height {
         for x in 0..char_width {
             let pixel_x = img_x + x;
             let pixel_y = img_y + y as u32;
-
-            // get pixel from bitmap
-            let should_pixel = if bitmap.is_wide() {
-                bitmap.get_bytes()[y * 2 + x as usize / 8] & (1 << (7 - x % 8)) != 0
-            } else {
-                bitmap.get_bytes()[y] & (1 << (7 - x)) != 0
-            };
+            let should_pixel = is_set(x, y);
 
             // if not in image bounds
             if pixel_x >= img.width() || pixel_y >= img.height() {
@@ -486,25 +977,79 @@ fn put_readable_char_in_image<C>(
                 // set pixel in image
                 if should_pixel {
                     img.put_pixel(pixel_x, pixel_y, *text_color);
+                    if let Some(coverage) = coverage.as_deref_mut() {
+                        coverage.put_pixel(pixel_x, pixel_y, COVERED);
+                    }
                 } else {
-                    img.put_pixel(pixel_x, pixel_y, *background_color);
+                    match outline_color.filter(|_| is_outline(x, y)) {
+                        Some(outline_color) => img.put_pixel(pixel_x, pixel_y, outline_color),
+                        None => img.put_pixel(pixel_x, pixel_y, *background_color),
+                    }
                 }
             }
         }
     }
 
-    if bitmap.is_wide() {
-        *cur_line_x += 2;
-    } else {
-        *cur_line_x += 1;
+    *cur_line_x += cells;
+}
+
+/// The amount of cells `chr` takes in `readable` mode, which is its display width like in a terminal, no matter
+/// how wide its glyph is. Combining marks, joiners and other zero-width characters take no cells and aren't drawn.
+pub(crate) fn glyph_cells(chr: char) -> u32 {
+    chr.width().unwrap_or(1) as u32
+}
+
+/// Return `true` if `chr` can be drawn at `cur_line_x` in a column that is `column_width` cells wide.
+///
+/// Glyphs that don't fit into the column entirely would be cut off, or reach into the next one. Wide glyphs
+/// take two cells when `readable`, but never fit into a column of a single cell.
+fn fits_in_column(chr: char, cur_line_x: u32, column_width: u32, readable: bool) -> bool {
+    let cells = if readable { glyph_cells(chr) } else { 1 };
+    cur_line_x + cells <= column_width
+}
+
+/// Draw `text` in readable glyphs of `color` on `background` with its top-left corner at `(x, y)`,
+/// leaving out all glyphs that don't fit into `max_width` pixels.
+pub(crate) fn draw_text<C>(
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+    text: &str,
+    (x, y): (u32, u32),
+    max_width: u32,
+    color: Rgb<u8>,
+    background: Rgb<u8>,
+) where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    let mut unifont = Unifont::open();
+    let mut cell = 0;
+    for chr in text.chars() {
+        if (cell + glyph_cells(chr)) * 8 > max_width {
+            break;
+        }
+        put_readable_char_in_image(
+            chr,
+            &mut unifont,
+            None,
+            x + cell * 8,
+            y,
+            img,
+            None,
+            &background,
+            &color,
+            None,
+            &mut cell,
+        );
     }
 }
 
-/// Fill the char space with a solid color.
+/// Fill the char space with a solid color, and mark it in `coverage` if set.
+#[allow(clippy::too_many_arguments)]
 fn put_solid_char_in_image<C>(
     img_x: u32,
     img_y: u32,
     img: &mut ImageBuffer<Rgb<u8>, C>,
+    mut coverage: Option<&mut GrayImage>,
     color: Rgb<u8>,
     line_height: u32,
     char_width: u32,
@@ -520,11 +1065,219 @@ fn put_solid_char_in_image<C>(
         for x_pos in img_x..img_x + char_width {
             // println!("placing x");
             img.put_pixel(x_pos, y_pos, color);
+            if let Some(coverage) = coverage.as_deref_mut() {
+                coverage.put_pixel(x_pos, y_pos, COVERED);
+            }
         }
     }
     *cur_line_x += char_width;
 }
 
+/// A color to mix into pixels, and how much of it, with a `strength` of 1.0 replacing them.
+#[derive(Clone, Copy)]
+struct Tint {
+    color: Rgb<u8>,
+    strength: f32,
+}
+
+/// Shade the `cells` of the line at `area` increasingly towards the color of `tint`, reaching its strength at the
+/// end of the column.
+fn shade_long_line<C>(
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+    area: LineArea,
+    cells: Range<u32>,
+    column_width: u32,
+    tint: Tint,
+    blend_space: BlendSpace,
+) where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    let first_cell = cells.start;
+    let ramp = column_width.saturating_sub(first_cell).max(1) as f32;
+    for cell in cells {
+        let strength = tint.strength * ((cell - first_cell + 1) as f32 / ramp).min(1.0);
+        tint_rect(
+            img,
+            area.x + cell * area.char_width,
+            area.y,
+            area.char_width,
+            area.line_height,
+            Tint { strength, ..tint },
+            blend_space,
+        );
+    }
+}
+
+/// Mix `tint` into all pixels of a rectangle of the image, rounding to the closest color.
+///
+/// Parts of the rectangle that are outside of the image are ignored.
+fn tint_rect<C>(
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+    img_x: u32,
+    img_y: u32,
+    width: u32,
+    height: u32,
+    tint: Tint,
+    blend_space: BlendSpace,
+) where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    for y in img_y..img_y.saturating_add(height).min(img.height()) {
+        for x in img_x..img_x.saturating_add(width).min(img.width()) {
+            let pixel = img.get_pixel_mut(x, y);
+            *pixel = blend_space.mix(*pixel, tint.color, tint.strength);
+        }
+    }
+}
+
+/// Blend `fg` over `bg` in `blend_space`, with an `alpha` of 255 keeping `fg` as is.
+fn blend(fg: Rgb<u8>, bg: Rgb<u8>, alpha: u8, blend_space: BlendSpace) -> Rgb<u8> {
+    if alpha == u8::MAX {
+        return fg;
+    }
+    blend_space.mix(bg, fg, alpha as f32 / u8::MAX as f32)
+}
+
+/// Fill the first `indent` cells of the line at `area` with `color` and return the position at which to continue
+/// drawing.
+fn put_indent<C>(
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+    area: LineArea,
+    indent: u32,
+    readable: bool,
+    color: Rgb<u8>,
+) -> u32
+where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    fill_rect(
+        img,
+        area.x,
+        area.y,
+        indent * area.char_width,
+        area.line_height,
+        color,
+    );
+    if readable {
+        indent
+    } else {
+        indent * area.char_width
+    }
+}
+
+/// Where a line is drawn in the image, and the size of its cells, all in pixels.
+#[derive(Clone, Copy)]
+struct LineArea {
+    /// The left edge of the column the line is in.
+    x: u32,
+    /// The top edge of the line.
+    y: u32,
+    char_width: u32,
+    line_height: u32,
+}
+
+/// Fill the remainder of the line at `area`, starting at `cur_line_x`, with `color`.
+///
+/// In `readable` mode `cur_line_x` counts glyph cells, otherwise it counts pixels.
+fn fill_rest_of_line<C>(
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+    area: LineArea,
+    cur_line_x: u32,
+    column_width: u32,
+    readable: bool,
+    color: Rgb<u8>,
+) where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    let LineArea {
+        x,
+        y,
+        char_width,
+        line_height,
+    } = area;
+    let (img_x, width) = if readable {
+        (
+            x + cur_line_x * char_width,
+            column_width.saturating_sub(cur_line_x) * char_width,
+        )
+    } else {
+        (
+            x + cur_line_x,
+            (column_width * char_width).saturating_sub(cur_line_x),
+        )
+    };
+    fill_rect(img, img_x, y, width, line_height, color);
+}
+
+/// Fill a rectangle of the image with a solid color, writing whole rows of the raw buffer at once.
+///
+/// Parts of the rectangle that are outside of the image are ignored.
+pub(crate) fn fill_rect<C>(
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+    img_x: u32,
+    img_y: u32,
+    width: u32,
+    height: u32,
+    color: Rgb<u8>,
+) where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    let x_end = img_x.saturating_add(width).min(img.width());
+    let y_end = img_y.saturating_add(height).min(img.height());
+    if img_x >= x_end || img_y >= y_end {
+        return;
+    }
+
+    let channel_count = Rgb::<u8>::CHANNEL_COUNT as usize;
+    let row_len = img.width() as usize * channel_count;
+    let row_range = img_x as usize * channel_count..x_end as usize * channel_count;
+    let buf: &mut [u8] = img;
+    for row in buf
+        .chunks_exact_mut(row_len)
+        .skip(img_y as usize)
+        .take((y_end - img_y) as usize)
+    {
+        for pixel in row[row_range.clone()].chunks_exact_mut(channel_count) {
+            pixel.copy_from_slice(&color.0);
+        }
+    }
+}
+
+/// Return `style` with the `(foreground, background)` colors of `mono` if set, or `style` as is.
+pub(crate) fn restyle(style: Style, mono: Option<(Rgb<u8>, Rgb<u8>)>) -> Style {
+    let to_color = |Rgb([r, g, b]): Rgb<u8>| Color {
+        r,
+        g,
+        b,
+        a: u8::MAX,
+    };
+    match mono {
+        Some((foreground, background)) => Style {
+            foreground: to_color(foreground),
+            background: to_color(background),
+            ..style
+        },
+        None => style,
+    }
+}
+
+/// Return the background `bg_color` derives from unhighlighted text of the first file in the default theme of `cache`,
+/// for use as background of all files.
+pub(crate) fn uniform_background(
+    cache: &Cache<'_, '_>,
+    ss: &SyntaxSet,
+    bg_color: BgColor,
+    color_modulation: f32,
+) -> Result<Rgb<u8>, syntect::Error> {
+    let style = cache.new_plain_highlighter().highlight_line(" ", ss)?[0].0;
+    Ok(bg_color.to_rgb(style, 0, color_modulation))
+}
+
 fn default_bg_color(background: Option<Rgb<u8>>) -> Style {
     Style {
         foreground: Color {
@@ -544,3 +1297,90 @@ fn default_bg_color(background: Option<Rgb<u8>>) -> Style {
         font_style: Default::default(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tint_rect_rounds_to_the_closest_color() {
+        let mut img = ImageBuffer::from_pixel(4, 4, Rgb([0, 10, 255]));
+        let tint = Tint {
+            color: Rgb([255, 11, 0]),
+            strength: 0.5,
+        };
+        tint_rect(&mut img, 1, 1, 2, 8, tint, BlendSpace::Srgb);
+        assert_eq!(*img.get_pixel(0, 0), Rgb([0, 10, 255]), "outside");
+        assert_eq!(
+            *img.get_pixel(1, 3),
+            Rgb([128, 11, 128]),
+            "127.5 and 10.5 round up instead of being truncated"
+        );
+    }
+
+    #[test]
+    fn fill_rest_of_line_matches_filling_cell_by_cell() {
+        let background = Rgb([10, 20, 30]);
+        let color = Rgb([200, 100, 50]);
+        let mut unifont = Unifont::open();
+        for (readable, char_width, line_height) in [(false, 1, 2), (false, 3, 1), (true, 8, 16)] {
+            let column_width = 6;
+            let area = LineArea {
+                x: 5,
+                y: 3,
+                char_width,
+                line_height,
+            };
+            let new_image = || {
+                ImageBuffer::from_pixel(
+                    2 * area.x + column_width * char_width,
+                    2 * area.y + line_height,
+                    background,
+                )
+            };
+            // `cur_line_x` counts pixels unless `readable`, but always starts at a cell.
+            let cell_width = if readable { 1 } else { char_width };
+            let line_end = column_width * cell_width;
+            for start in [0, 1, column_width - 1, column_width].map(|cell| cell * cell_width) {
+                let mut filled = new_image();
+                fill_rest_of_line(&mut filled, area, start, column_width, readable, color);
+
+                // the way lines were filled before, one cell at a time
+                let mut expected = new_image();
+                let mut cur_line_x = start;
+                while cur_line_x < line_end {
+                    if readable {
+                        put_readable_char_in_image(
+                            ' ',
+                            &mut unifont,
+                            None,
+                            area.x + cur_line_x * char_width,
+                            area.y,
+                            &mut expected,
+                            None,
+                            &color,
+                            &color,
+                            None,
+                            &mut cur_line_x,
+                        );
+                    } else {
+                        put_solid_char_in_image(
+                            area.x + cur_line_x,
+                            area.y,
+                            &mut expected,
+                            None,
+                            color,
+                            line_height,
+                            char_width,
+                            &mut cur_line_x,
+                        );
+                    }
+                }
+                assert!(
+                    filled == expected,
+                    "readable: {readable}, char width: {char_width}, start: {start}"
+                );
+            }
+        }
+    }
+}
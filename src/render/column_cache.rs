@@ -0,0 +1,171 @@
+use crate::render::chunk::Outcome;
+use image::{Rgb, RgbImage};
+use std::hash::Hasher;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A directory of column images of individual files, keyed by a hash of their content and everything
+/// else that affects their appearance.
+///
+/// Entries are stored as their key and the `Outcome` of rendering followed by the raw pixels, whose dimensions are
+/// known to the caller.
+pub(crate) struct ColumnCache<'a> {
+    dir: &'a Path,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+/// The key of an entry, which is the BLAKE3 hash of everything that affects its appearance.
+pub(crate) type Key = [u8; 32];
+
+/// The amount of bytes in front of the pixels of each entry.
+const HEADER_LEN: usize = 32 + 8 + 1 + 3;
+
+/// Fed into every key first, to be changed whenever the inputs of keys or their serialization change.
+const KEY_VERSION: &[u8] = b"codevis column cache 1";
+
+/// A [`Hasher`] that computes a [`Key`] from everything fed into it, with integers serialized in little endian at
+/// a fixed width, so the same inputs give the same key on every platform and with every Rust release, unlike
+/// the hashers of the standard library.
+pub(crate) struct KeyHasher(blake3::Hasher);
+
+impl KeyHasher {
+    pub fn new() -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(KEY_VERSION);
+        KeyHasher(hasher)
+    }
+
+    /// Return the key of everything written so far.
+    pub fn key(&self) -> Key {
+        *self.0.finalize().as_bytes()
+    }
+}
+
+impl Hasher for KeyHasher {
+    fn finish(&self) -> u64 {
+        let key = self.key();
+        u64::from_le_bytes(key[..8].try_into().expect("keys are longer"))
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.write(&[i]);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i8(&mut self, i: i8) {
+        self.write_u8(i as u8);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_i64(i as i64);
+    }
+}
+
+impl<'a> ColumnCache<'a> {
+    pub fn new(dir: &'a Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(ColumnCache {
+            dir,
+            hits: AtomicUsize::default(),
+            misses: AtomicUsize::default(),
+        })
+    }
+
+    /// Return the image and outcome stored under `key`, if it exists, was stored under the very same key
+    /// and has the given dimensions.
+    pub fn load(&self, key: &Key, width: u32, height: u32) -> Option<(RgbImage, Outcome)> {
+        let entry = std::fs::read(self.path(key))
+            .ok()
+            .filter(|data| data.len() == HEADER_LEN + width as usize * height as usize * 3)
+            .filter(|data| data[..32] == key[..])
+            .and_then(|mut data| {
+                let header = &data[32..HEADER_LEN];
+                let outcome = Outcome {
+                    longest_line_in_chars: u64::from_le_bytes(header[..8].try_into().ok()?)
+                        as usize,
+                    background: (header[8] != 0).then(|| Rgb([header[9], header[10], header[11]])),
+                };
+                data.drain(..HEADER_LEN);
+                Some((RgbImage::from_raw(width, height, data)?, outcome))
+            });
+        match entry {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        entry
+    }
+
+    /// Store `img` and `outcome` under `key`, replacing any previous entry atomically.
+    pub fn store(&self, key: &Key, img: &RgbImage, outcome: &Outcome) -> std::io::Result<()> {
+        let mut header = [0u8; HEADER_LEN];
+        header[..32].copy_from_slice(key);
+        header[32..40].copy_from_slice(&(outcome.longest_line_in_chars as u64).to_le_bytes());
+        if let Some(background) = outcome.background {
+            header[40] = 1;
+            header[41..].copy_from_slice(&background.0);
+        }
+
+        let path = self.path(key);
+        let tmp_path = path.with_extension(format!("tmp-{:?}", std::thread::current().id()));
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(&header)?;
+        file.write_all(img.as_raw())?;
+        drop(file);
+        std::fs::rename(tmp_path, path)
+    }
+
+    /// Return `(hits, lookups)`.
+    pub fn stats(&self) -> (usize, usize) {
+        let hits = self.hits.load(Ordering::Relaxed);
+        (hits, hits + self.misses.load(Ordering::Relaxed))
+    }
+
+    fn path(&self, key: &Key) -> std::path::PathBuf {
+        let name = key[..16]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        self.dir.join(format!("{name}.column"))
+    }
+}
@@ -0,0 +1,471 @@
+use crate::render::chunk::{draw_text, fill_rect, COVERED};
+use crate::render::{Badge, BgFit, BlendSpace, Pad, Rect};
+use image::{GrayImage, ImageBuffer, Pixel, Rgb, RgbImage};
+use memmap2::MmapMut;
+use std::ops::{Deref, DerefMut};
+
+/// Replace all pixels of `img` that have one of the `transparent` colors with the respective pixel
+/// of `background`, which is fitted to `img` according to `fit`.
+///
+/// Pixels marked in `coverage`, whose top-left corner is at the given position in `img`, are glyphs and
+/// kept even if they have one of the `transparent` colors.
+///
+/// Pixels are sampled with nearest-neighbor filtering so no scaled copy of `background` needs to be held in memory.
+pub(crate) fn background_image<C>(
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+    background: &RgbImage,
+    fit: BgFit,
+    transparent: &[Rgb<u8>],
+    coverage: Option<(&GrayImage, (u32, u32))>,
+) where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    let (width, height) = (img.width() as f64, img.height() as f64);
+    let (bg_width, bg_height) = (background.width() as f64, background.height() as f64);
+    if bg_width == 0.0 || bg_height == 0.0 {
+        return;
+    }
+
+    // The scale of the background in both directions, and the position of its top-left corner in `img`.
+    let (scale_x, scale_y, left, top) = match fit {
+        BgFit::Stretch => (width / bg_width, height / bg_height, 0.0, 0.0),
+        BgFit::Cover | BgFit::Contain => {
+            let scale = if fit == BgFit::Cover {
+                (width / bg_width).max(height / bg_height)
+            } else {
+                (width / bg_width).min(height / bg_height)
+            };
+            (
+                scale,
+                scale,
+                (width - bg_width * scale) / 2.0,
+                (height - bg_height * scale) / 2.0,
+            )
+        }
+        BgFit::Tile => (1.0, 1.0, 0.0, 0.0),
+    };
+
+    let is_covered = |x: u32, y: u32| {
+        coverage.is_some_and(|(coverage, (left, top))| {
+            x.checked_sub(left)
+                .zip(y.checked_sub(top))
+                .and_then(|(x, y)| coverage.get_pixel_checked(x, y))
+                .is_some_and(|pixel| *pixel == COVERED)
+        })
+    };
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        if !transparent.contains(pixel) || is_covered(x, y) {
+            continue;
+        }
+        let (bg_x, bg_y) = if fit == BgFit::Tile {
+            (x % background.width(), y % background.height())
+        } else {
+            let bg_x = (x as f64 - left) / scale_x;
+            let bg_y = (y as f64 - top) / scale_y;
+            if bg_x < 0.0 || bg_y < 0.0 || bg_x >= bg_width || bg_y >= bg_height {
+                // Only possible with `Contain`, which leaves the uncovered parts as they are.
+                continue;
+            }
+            (bg_x as u32, bg_y as u32)
+        };
+        *pixel = *background.get_pixel(bg_x, bg_y);
+    }
+}
+
+/// Convert every pixel of `img` to its luminance, so highlighting remains visible as differences in brightness.
+pub(crate) fn grayscale<C>(img: &mut ImageBuffer<Rgb<u8>, C>)
+where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    let buf: &mut [u8] = img;
+    for pixel in buf.chunks_exact_mut(Rgb::<u8>::CHANNEL_COUNT as usize) {
+        let luma = (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32)
+            .round()
+            .min(255.0) as u8;
+        pixel.fill(luma);
+    }
+}
+
+/// Shift the pixels of `img` that have one of the `backgrounds` colors in every other column of `column_width` pixels,
+/// starting with the second, towards white if they are dark or towards black if they are bright, by `strength`.
+///
+/// Glyphs are left as they are, so syntax colors read the same in all columns.
+pub(crate) fn zebra_columns<C>(
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+    column_width: u32,
+    backgrounds: &[Rgb<u8>],
+    strength: f32,
+    blend_space: BlendSpace,
+) where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    let shifted = backgrounds
+        .iter()
+        .map(|&background| {
+            (
+                background,
+                shift_brightness(background, strength, blend_space),
+            )
+        })
+        .collect::<Vec<_>>();
+    for (x, _, pixel) in img.enumerate_pixels_mut() {
+        if (x / column_width) % 2 == 0 {
+            continue;
+        }
+        if let Some((_, shifted)) = shifted.iter().find(|(background, _)| background == pixel) {
+            *pixel = *shifted;
+        }
+    }
+}
+
+/// Draw a thin line in a slightly brighter or darker version of the background along the left edge of `rect`,
+/// which is the part of a column taken by a file with nothing but whitespace, so it can be told apart from the
+/// empty end of the last column.
+pub(crate) fn mark_blank_file<C>(
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+    rect: Rect,
+    blend_space: BlendSpace,
+) where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    const WIDTH: u32 = 2;
+    const STRENGTH: f32 = 0.25;
+    if rect.width == 0 || rect.height == 0 {
+        return;
+    }
+    let background = *img.get_pixel(rect.x, rect.y);
+    fill_rect(
+        img,
+        rect.x,
+        rect.y,
+        WIDTH.min(rect.width),
+        rect.height,
+        shift_brightness(background, STRENGTH, blend_space),
+    );
+}
+
+/// Shift `color` towards white if it's dark or towards black if it's bright, by `strength`.
+fn shift_brightness(color: Rgb<u8>, strength: f32, blend_space: BlendSpace) -> Rgb<u8> {
+    let luma = 0.299 * color[0] as f32 + 0.587 * color[1] as f32 + 0.114 * color[2] as f32;
+    let target = if luma < 128.0 {
+        Rgb([255, 255, 255])
+    } else {
+        Rgb([0, 0, 0])
+    };
+    blend_space.mix(color, target, strength)
+}
+
+/// Let the bright pixels of `img` glow into their surroundings by blurring them over `radius` pixels and adding
+/// `intensity` of the result back onto `img`.
+///
+/// The bright pixels are held in a copy of `img`, which is blurred in place one row and column at a time.
+pub(crate) fn glow<C>(img: &mut ImageBuffer<Rgb<u8>, C>, radius: u32, intensity: f32)
+where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    /// The luminance above which pixels start to glow, fully glowing at 255.
+    const THRESHOLD: f32 = 128.0;
+    if radius == 0 || intensity <= 0.0 {
+        return;
+    }
+    let (width, height) = img.dimensions();
+    let mut bright = RgbImage::new(width, height);
+    for (bright, pixel) in bright.pixels_mut().zip(img.pixels()) {
+        let luma = 0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32;
+        let amount = ((luma - THRESHOLD) / (255.0 - THRESHOLD)).clamp(0.0, 1.0);
+        *bright = pixel.map(|c| (c as f32 * amount) as u8);
+    }
+    // two box blurs are a cheap approximation of a gaussian blur, which makes for a softer glow.
+    for _ in 0..2 {
+        box_blur(&mut bright, radius);
+    }
+    for (pixel, bright) in img.pixels_mut().zip(bright.pixels()) {
+        *pixel = Rgb([0, 1, 2].map(|c| {
+            (pixel[c] as f32 + bright[c] as f32 * intensity)
+                .round()
+                .min(255.0) as u8
+        }));
+    }
+}
+
+/// Blur `img` with a box of `2 * radius + 1` pixels on each side, with pixels outside of `img` being black.
+fn box_blur(img: &mut RgbImage, radius: u32) {
+    let (width, height) = img.dimensions();
+    let mut line = Vec::new();
+    for y in 0..height {
+        line.clear();
+        line.extend((0..width).map(|x| *img.get_pixel(x, y)));
+        for (x, blurred) in blur_line(&line, radius).enumerate() {
+            img.put_pixel(x as u32, y, blurred);
+        }
+    }
+    for x in 0..width {
+        line.clear();
+        line.extend((0..height).map(|y| *img.get_pixel(x, y)));
+        for (y, blurred) in blur_line(&line, radius).enumerate() {
+            img.put_pixel(x, y as u32, blurred);
+        }
+    }
+}
+
+/// Return the average of the `2 * radius + 1` pixels around each pixel of `line`, keeping a running sum
+/// so the radius doesn't affect how long it takes.
+fn blur_line(line: &[Rgb<u8>], radius: u32) -> impl Iterator<Item = Rgb<u8>> + '_ {
+    let radius = radius as usize;
+    let window = (2 * radius + 1) as u32;
+    let channel_sum = |range: std::ops::Range<usize>, c: usize| {
+        line[range].iter().map(|pixel| pixel[c] as u32).sum::<u32>()
+    };
+    let mut sum = [0, 1, 2].map(|c| channel_sum(0..radius.min(line.len()), c));
+    (0..line.len()).map(move |x| {
+        for (c, sum) in sum.iter_mut().enumerate() {
+            if let Some(entering) = line.get(x + radius) {
+                *sum += entering[c] as u32;
+            }
+            if x > radius {
+                *sum -= line[x - radius - 1][c] as u32;
+            }
+        }
+        Rgb(sum.map(|sum| (sum / window) as u8))
+    })
+}
+
+/// Draw a border of `width` pixels and `color` along the inside of `rect`.
+pub(crate) fn draw_border<C>(
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+    rect: Rect,
+    width: u32,
+    color: Rgb<u8>,
+) where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    let width = width.min(rect.width).min(rect.height);
+    let Rect {
+        x,
+        y,
+        width: rect_width,
+        height: rect_height,
+    } = rect;
+    fill_rect(img, x, y, rect_width, width, color);
+    fill_rect(img, x, y + rect_height - width, rect_width, width, color);
+    fill_rect(img, x, y, width, rect_height, color);
+    fill_rect(img, x + rect_width - width, y, width, rect_height, color);
+}
+
+/// Draw `text` in white on a translucent black box in the `corner` of `img`, scaled with the size of the image
+/// so it remains readable without covering much of it.
+///
+/// Parts of the badge that don't fit into the image are left out.
+pub(crate) fn draw_badge<C>(img: &mut ImageBuffer<Rgb<u8>, C>, text: &str, corner: Badge)
+where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    /// How much of the image behind the box of the badge remains visible.
+    const SHOW_THROUGH: f32 = 0.4;
+    const TEXT_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+    if corner == Badge::None {
+        return;
+    }
+    let (width, height) = img.dimensions();
+    // glyphs are 16 pixels high, which makes the text about a twentieth of the shorter side of the image high.
+    let scale = (width.min(height) / 320).max(1);
+    // the text with a padding of 4 pixels, at its original size.
+    let text_width = text.chars().count() as u32 * 8;
+    let mut label = RgbImage::new(text_width + 8, 16 + 8);
+    draw_text(
+        &mut label,
+        text,
+        (4, 4),
+        text_width,
+        TEXT_COLOR,
+        Rgb([0, 0, 0]),
+    );
+
+    let (badge_width, badge_height) = (label.width() * scale, label.height() * scale);
+    let margin = 8 * scale;
+    let left = match corner {
+        Badge::TopLeft | Badge::BottomLeft => margin,
+        _ => width.saturating_sub(badge_width + margin),
+    };
+    let top = match corner {
+        Badge::TopLeft | Badge::TopRight => margin,
+        _ => height.saturating_sub(badge_height + margin),
+    };
+    for y in top..(top + badge_height).min(height) {
+        for x in left..(left + badge_width).min(width) {
+            let pixel = img.get_pixel_mut(x, y);
+            if *label.get_pixel((x - left) / scale, (y - top) / scale) == TEXT_COLOR {
+                *pixel = TEXT_COLOR;
+            } else {
+                *pixel = pixel.map(|c| (c as f32 * SHOW_THROUGH) as u8);
+            }
+        }
+    }
+}
+
+/// Pad `img` with `color` so its width divided by its height is `aspect_ratio`, as closely as whole pixels allow,
+/// placing `img` according to `pad`.
+///
+/// `img` is returned as is if it has the right aspect ratio already, or if `aspect_ratio` isn't a positive number.
+pub(crate) fn pad_to_aspect_ratio(
+    img: ImageBuffer<Rgb<u8>, MmapMut>,
+    aspect_ratio: f64,
+    pad: Pad,
+    color: Rgb<u8>,
+) -> std::io::Result<ImageBuffer<Rgb<u8>, MmapMut>> {
+    let (width, height) = img.dimensions();
+    if !(aspect_ratio.is_finite() && aspect_ratio > 0.0) || width == 0 || height == 0 {
+        return Ok(img);
+    }
+    let (padded_width, padded_height) = if (width as f64 / height as f64) < aspect_ratio {
+        ((height as f64 * aspect_ratio).round() as u32, height)
+    } else {
+        (width, (width as f64 / aspect_ratio).round() as u32)
+    };
+    let (padded_width, padded_height) = (padded_width.max(width), padded_height.max(height));
+    if (padded_width, padded_height) == (width, height) {
+        return Ok(img);
+    }
+
+    let (left, top) = pad_offset((width, height), (padded_width, padded_height), pad);
+    expand(&img, (padded_width, padded_height), (left, top), |padded| {
+        fill_rect(padded, 0, 0, padded_width, padded_height, color)
+    })
+}
+
+/// The position of the top-left corner of an image of `size` after it was padded to `padded_size` by `pad`.
+pub(crate) fn pad_offset(
+    (width, height): (u32, u32),
+    (padded_width, padded_height): (u32, u32),
+    pad: Pad,
+) -> (u32, u32) {
+    match pad {
+        Pad::Center => ((padded_width - width) / 2, (padded_height - height) / 2),
+        Pad::BottomRight => (0, 0),
+    }
+}
+
+/// Cut off the pixel columns at the right of `img` that are entirely `background`, keeping at least one,
+/// and return the trimmed image along with how many columns were cut off.
+pub(crate) fn trim_empty_columns(
+    img: ImageBuffer<Rgb<u8>, MmapMut>,
+    background: Rgb<u8>,
+) -> std::io::Result<(ImageBuffer<Rgb<u8>, MmapMut>, u32)> {
+    let (width, height) = img.dimensions();
+    let mut kept = 1;
+    for y in 0..height {
+        if kept == width {
+            break;
+        }
+        if let Some(x) = (kept..width)
+            .rev()
+            .find(|&x| *img.get_pixel(x, y) != background)
+        {
+            kept = x + 1;
+        }
+    }
+    if kept >= width {
+        return Ok((img, 0));
+    }
+
+    let channel_count = Rgb::<u8>::CHANNEL_COUNT as usize;
+    let mut trimmed = ImageBuffer::<Rgb<u8>, _>::from_raw(
+        kept,
+        height,
+        MmapMut::map_anon(kept as usize * height as usize * channel_count)?,
+    )
+    .expect("correct size computation above");
+    let (row_len, trimmed_row_len) = (
+        width as usize * channel_count,
+        kept as usize * channel_count,
+    );
+    let trimmed_buf: &mut [u8] = &mut trimmed;
+    for (row, trimmed_row) in img
+        .chunks_exact(row_len)
+        .zip(trimmed_buf.chunks_exact_mut(trimmed_row_len))
+    {
+        trimmed_row.copy_from_slice(&row[..trimmed_row_len]);
+    }
+    Ok((trimmed, width - kept))
+}
+
+/// Surround `img` with a border of `width` pixels and `color` on every side.
+pub(crate) fn add_border(
+    img: ImageBuffer<Rgb<u8>, MmapMut>,
+    width: u32,
+    color: Rgb<u8>,
+) -> std::io::Result<ImageBuffer<Rgb<u8>, MmapMut>> {
+    if width == 0 {
+        return Ok(img);
+    }
+    let size = (img.width() + 2 * width, img.height() + 2 * width);
+    expand(&img, size, (width, width), |framed| {
+        fill_rect(framed, 0, 0, size.0, size.1, color)
+    })
+}
+
+/// Place `img` on a `backdrop` with a soft shadow falling to its bottom right, which is `radius` pixels wide.
+///
+/// The shadow is a box-blurred rectangle, so it can be computed for each pixel directly instead of blurring
+/// the whole image.
+pub(crate) fn add_shadow(
+    img: ImageBuffer<Rgb<u8>, MmapMut>,
+    radius: u32,
+    backdrop: Rgb<u8>,
+) -> std::io::Result<ImageBuffer<Rgb<u8>, MmapMut>> {
+    /// How much the backdrop is darkened where the shadow is strongest.
+    const DARKEN: f32 = 0.5;
+    if radius == 0 {
+        return Ok(img);
+    }
+    let (width, height) = img.dimensions();
+    let offset = radius / 2;
+    let size = (width + 2 * radius + offset, height + 2 * radius + offset);
+    // the fraction of the blur window of `x` that covers the shadow, which spans `start..start + len`.
+    let coverage = |x: u32, start: u32, len: u32| {
+        let (window_start, window_end) = (x.saturating_sub(radius), x + radius + 1);
+        let covered = (window_end.min(start + len)).saturating_sub(window_start.max(start));
+        covered as f32 / (2 * radius + 1) as f32
+    };
+    let shadow_start = radius + offset;
+    expand(&img, size, (radius, radius), |shadowed| {
+        for (x, y, pixel) in shadowed.enumerate_pixels_mut() {
+            let strength = coverage(x, shadow_start, width) * coverage(y, shadow_start, height);
+            *pixel = backdrop.map(|c| (c as f32 * (1.0 - DARKEN * strength)) as u8);
+        }
+    })
+}
+
+/// Create an image of `(width, height)`, let `fill` draw its background, and copy `img` into it
+/// with its top-left corner at `(left, top)`.
+fn expand(
+    img: &ImageBuffer<Rgb<u8>, MmapMut>,
+    (width, height): (u32, u32),
+    (left, top): (u32, u32),
+    fill: impl FnOnce(&mut ImageBuffer<Rgb<u8>, MmapMut>),
+) -> std::io::Result<ImageBuffer<Rgb<u8>, MmapMut>> {
+    let channel_count = Rgb::<u8>::CHANNEL_COUNT as usize;
+    let mut expanded = ImageBuffer::<Rgb<u8>, _>::from_raw(
+        width,
+        height,
+        MmapMut::map_anon(width as usize * height as usize * channel_count)?,
+    )
+    .expect("correct size computation above");
+    fill(&mut expanded);
+
+    let row_len = img.width() as usize * channel_count;
+    let expanded_row_len = width as usize * channel_count;
+    let expanded_buf: &mut [u8] = &mut expanded;
+    for (y, row) in img.chunks_exact(row_len).enumerate() {
+        let start = (top as usize + y) * expanded_row_len + left as usize * channel_count;
+        expanded_buf[start..][..row_len].copy_from_slice(row);
+    }
+    Ok(expanded)
+}
@@ -0,0 +1,147 @@
+use crate::render::Rect;
+use crate::RenderError;
+use image::{ImageBuffer, Pixel, Rgb, RgbImage};
+use std::ops::Deref;
+
+/// Copy `region` out of `img` into a new, smaller image.
+///
+/// The copy is performed row by row, which keeps it cheap even for huge memory-mapped images.
+pub fn crop<C>(img: &ImageBuffer<Rgb<u8>, C>, region: Rect) -> Result<RgbImage, RenderError>
+where
+    C: Deref<Target = [u8]>,
+{
+    let Rect {
+        x,
+        y,
+        width,
+        height,
+    } = region;
+    if width == 0 || height == 0 {
+        return Err(RenderError::EmptyCrop { region });
+    }
+    let fits = x
+        .checked_add(width)
+        .map_or(false, |right| right <= img.width())
+        && y.checked_add(height)
+            .map_or(false, |bottom| bottom <= img.height());
+    if !fits {
+        return Err(RenderError::CropOutOfBounds {
+            region,
+            width: img.width(),
+            height: img.height(),
+        });
+    }
+
+    let channel_count = Rgb::<u8>::CHANNEL_COUNT as usize;
+    let row_len = img.width() as usize * channel_count;
+    let row_range = x as usize * channel_count..(x + width) as usize * channel_count;
+    let buf: &[u8] = img;
+    let mut out = Vec::with_capacity(width as usize * height as usize * channel_count);
+    for row in buf
+        .chunks_exact(row_len)
+        .skip(y as usize)
+        .take(height as usize)
+    {
+        out.extend_from_slice(&row[row_range.clone()]);
+    }
+    Ok(RgbImage::from_raw(width, height, out).expect("correct size computation above"))
+}
+
+/// Split an image of `width` x `height` pixels into `rows` x `columns` regions that cover it without overlapping,
+/// row by row from the top-left.
+///
+/// Regions differ in size by at most a pixel, so the remainder of dividing the image is spread across them. They are
+/// empty if there are more rows than pixels in height, or more columns than pixels in width.
+pub fn tiles(width: u32, height: u32, rows: u32, columns: u32) -> Vec<Rect> {
+    let edge =
+        |size: u32, count: u32, index: u32| (size as u64 * index as u64 / count as u64) as u32;
+    (0..rows)
+        .flat_map(|row| {
+            let (y, bottom) = (edge(height, rows, row), edge(height, rows, row + 1));
+            (0..columns).map(move |column| {
+                let (x, right) = (
+                    edge(width, columns, column),
+                    edge(width, columns, column + 1),
+                );
+                Rect {
+                    x,
+                    y,
+                    width: right - x,
+                    height: bottom - y,
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_copies_the_region_and_rejects_out_of_bounds_regions() {
+        let img = image::RgbImage::from_fn(10, 8, |x, y| image::Rgb([x as u8, y as u8, 0]));
+        let region = Rect {
+            x: 2,
+            y: 3,
+            width: 4,
+            height: 5,
+        };
+        let cropped = crop(&img, region).unwrap();
+        assert_eq!(cropped.dimensions(), (4, 5));
+        assert_eq!(cropped.get_pixel(0, 0), &image::Rgb([2, 3, 0]));
+        assert_eq!(cropped.get_pixel(3, 4), &image::Rgb([5, 7, 0]));
+
+        assert!(matches!(
+            crop(
+                &img,
+                Rect {
+                    height: 6,
+                    ..region
+                }
+            ),
+            Err(RenderError::CropOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn tiles_cover_the_image_with_remainders_spread() {
+        let regions = tiles(10, 7, 2, 3);
+        let sizes = regions
+            .iter()
+            .map(|tile| (tile.x, tile.y, tile.width, tile.height))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sizes,
+            [
+                (0, 0, 3, 3),
+                (3, 0, 3, 3),
+                (6, 0, 4, 3),
+                (0, 3, 3, 4),
+                (3, 3, 3, 4),
+                (6, 3, 4, 4),
+            ],
+            "row by row, with tiles differing in size by at most a pixel"
+        );
+        let widths = |columns| {
+            tiles(10, 1, 1, columns)
+                .iter()
+                .map(|tile| tile.width)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            widths(4),
+            [2, 3, 2, 3],
+            "the remainder is spread across tiles instead of going to the edge"
+        );
+        assert_eq!(widths(6), [1, 2, 2, 1, 2, 2]);
+
+        let img = image::RgbImage::from_fn(10, 7, |x, y| image::Rgb([x as u8, y as u8, 0]));
+        let mut reassembled = image::RgbImage::new(10, 7);
+        for tile in regions {
+            let cropped = crop(&img, tile).unwrap();
+            image::GenericImage::copy_from(&mut reassembled, &cropped, tile.x, tile.y).unwrap();
+        }
+        assert_eq!(reassembled, img);
+    }
+}
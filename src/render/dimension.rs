@@ -1,3 +1,5 @@
+use crate::RenderError;
+
 pub struct Dimension {
     pub imgx: u32,
     pub imgy: u32,
@@ -5,15 +7,97 @@ pub struct Dimension {
     pub required_columns: u32,
 }
 
-/// determine number and height of columns closest to desired aspect ratio
+/// determine number and height of columns closest to desired aspect ratio, or to fill `target_width`,
+/// using no more than `max_columns`, and columns that are at least `min_column_lines` tall.
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn compute(
     target_aspect_ratio: f64,
-    column_width: u32,
+    target_width: Option<u32>,
+    max_columns: Option<u32>,
+    min_column_lines: Option<u32>,
+    column_width_pixels: u32,
     total_line_count: u32,
     line_height: u32,
     force_full_columns: bool,
     mut progress: impl prodash::Progress,
-) -> anyhow::Result<Dimension> {
+) -> Result<Dimension, RenderError> {
+    let dimension = match target_width {
+        Some(target_width) => compute_for_width(
+            target_width,
+            column_width_pixels,
+            total_line_count,
+            line_height,
+        )?,
+        None => {
+            let (dimension, aspect_ratio_error) = compute_for_aspect_ratio(
+                target_aspect_ratio,
+                column_width_pixels,
+                total_line_count,
+                line_height,
+                force_full_columns,
+            );
+            progress.info(format!(
+                "Aspect ratio is {:.3} for a target of {target_aspect_ratio:.3}, {aspect_ratio_error:.3} off",
+                dimension.imgx as f64 / dimension.imgy as f64
+            ));
+            dimension
+        }
+    };
+
+    let dimension = match max_columns {
+        Some(max_columns) if dimension.required_columns > max_columns => {
+            progress.info(format!(
+                "Using {max_columns} instead of {} columns, making the image taller than requested",
+                dimension.required_columns
+            ));
+            compute_for_columns(
+                max_columns,
+                column_width_pixels,
+                total_line_count,
+                line_height,
+            )
+        }
+        _ => dimension,
+    };
+
+    Ok(match min_column_lines {
+        Some(min_column_lines) if dimension.lines_per_column < min_column_lines => {
+            let required_columns = total_line_count.div_ceil(min_column_lines).max(1);
+            progress.info(format!(
+                "Using {required_columns} columns of at least {min_column_lines} lines instead of {} columns of {} lines, sacrificing the requested proportions",
+                dimension.required_columns, dimension.lines_per_column
+            ));
+            let dimension = compute_for_columns(
+                required_columns,
+                column_width_pixels,
+                total_line_count,
+                line_height,
+            );
+            // short inputs leave the bottom of the column empty.
+            let lines_per_column = dimension.lines_per_column.max(min_column_lines);
+            Dimension {
+                imgy: lines_per_column * line_height,
+                lines_per_column,
+                ..dimension
+            }
+        }
+        _ => dimension,
+    })
+}
+
+/// determine number and height of columns closest to desired aspect ratio, along with how far off it is.
+///
+/// The candidates are all column heights from a single line up to all lines, each with as many columns as needed.
+/// With `force_full_columns`, only the smallest column height for each number of columns is a candidate, so columns
+/// are never taller than needed and the last one lacks fewer lines than there are columns.
+/// Of equally close candidates, the one with the most columns is chosen.
+fn compute_for_aspect_ratio(
+    target_aspect_ratio: f64,
+    column_width_pixels: u32,
+    total_line_count: u32,
+    line_height: u32,
+    force_full_columns: bool,
+) -> (Dimension, f64) {
     // determine image dimensions based on num of lines and constraints
     let mut lines_per_column = 1;
     let mut last_checked_aspect_ratio: f64 = f64::MAX;
@@ -22,9 +106,11 @@ pub(crate) fn compute(
 
     // determine maximum aspect ratios
     // the width of one column, divided by the combined height of all the lines.
-    let tallest_aspect_ratio = column_width as f64 / (total_line_count as f64 * line_height as f64);
+    let tallest_aspect_ratio =
+        column_width_pixels as f64 / (total_line_count as f64 * line_height as f64);
     // the combined width of all the columns, divided by the height of one line.
-    let widest_aspect_ratio = (column_width as f64 * total_line_count as f64) / line_height as f64;
+    let widest_aspect_ratio =
+        (column_width_pixels as f64 * total_line_count as f64) / line_height as f64;
 
     // start at widest possible aspect ratio.
     // This will later be made taller until the closest aspect ratio to the target is found.
@@ -47,12 +133,11 @@ pub(crate) fn compute(
         while (last_checked_aspect_ratio - target_aspect_ratio).abs()
             > (cur_aspect_ratio - target_aspect_ratio).abs()
         {
-            // remember current aspect ratio
+            // remember current aspect ratio, along with the column height it is for
             last_checked_aspect_ratio = cur_aspect_ratio;
+            last_column_line_limit = lines_per_column;
 
             if force_full_columns {
-                last_column_line_limit = lines_per_column;
-
                 // determine required number of columns
                 required_columns = total_line_count / lines_per_column;
                 if total_line_count % lines_per_column != 0 {
@@ -61,8 +146,12 @@ pub(crate) fn compute(
 
                 let last_required_columns = required_columns;
 
-                // find next full column aspect ratio
-                while required_columns == last_required_columns {
+                // find next full column aspect ratio, which is a single column at most.
+                // Once all lines are in a single column, there is no next one, and the loop above stops as the
+                // aspect ratio doesn't change anymore.
+                while required_columns == last_required_columns
+                    && lines_per_column < total_line_count
+                {
                     lines_per_column += 1;
 
                     // determine required number of columns
@@ -83,21 +172,15 @@ pub(crate) fn compute(
                 }
             }
 
-            cur_aspect_ratio = required_columns as f64 * column_width as f64
+            cur_aspect_ratio = required_columns as f64 * column_width_pixels as f64
                 / (lines_per_column as f64 * line_height as f64);
         }
 
         //> re-determine best aspect ratio
 
-        // (Should never not happen, but)
-        // previous while loop would never have been entered if (column_line_limit == 1)
-        // so (column_line_limit -= 1;) would be unnecessary
-        if lines_per_column != 1 && !force_full_columns {
-            // revert to last aspect ratio
-            lines_per_column -= 1;
-        } else if force_full_columns {
-            lines_per_column = last_column_line_limit;
-        }
+        // Aspect ratios get taller with every step, so the first one that is further off than the one before it
+        // means that the one before is the closest, and the loop stopped right after it.
+        lines_per_column = last_column_line_limit;
 
         // determine required number of columns
         required_columns = total_line_count / lines_per_column;
@@ -106,18 +189,77 @@ pub(crate) fn compute(
         }
     }
 
-    let imgx: u32 = required_columns * column_width;
+    let imgx: u32 = required_columns * column_width_pixels;
     let imgy: u32 = total_line_count.min(lines_per_column) * line_height;
 
-    progress.info(format!(
-        "Aspect ratio is {} off from target",
-        (last_checked_aspect_ratio - target_aspect_ratio).abs(),
-    ));
+    (
+        Dimension {
+            imgx,
+            imgy,
+            lines_per_column,
+            required_columns,
+        },
+        // the widest and tallest aspect ratios are used without checking them in the loop above.
+        (imgx as f64 / imgy as f64 - target_aspect_ratio).abs(),
+    )
+}
 
-    Ok(Dimension {
-        imgx,
-        imgy,
+/// determine the number of columns that fit into `target_width` and make them just tall enough to hold all lines.
+fn compute_for_width(
+    target_width: u32,
+    column_width_pixels: u32,
+    total_line_count: u32,
+    line_height: u32,
+) -> Result<Dimension, RenderError> {
+    let required_columns = target_width / column_width_pixels;
+    if required_columns == 0 {
+        return Err(RenderError::TargetWidthTooSmall {
+            target_width,
+            column_width: column_width_pixels,
+        });
+    }
+
+    // Trailing columns may stay empty, but are kept to fill the target width.
+    Ok(compute_for_columns(
+        required_columns,
+        column_width_pixels,
+        total_line_count,
+        line_height,
+    ))
+}
+
+/// make `required_columns` columns just tall enough to hold all lines.
+fn compute_for_columns(
+    required_columns: u32,
+    column_width_pixels: u32,
+    total_line_count: u32,
+    line_height: u32,
+) -> Dimension {
+    let required_columns = required_columns.max(1);
+    let mut lines_per_column = total_line_count / required_columns;
+    if total_line_count % required_columns != 0 {
+        lines_per_column += 1;
+    }
+    Dimension {
+        imgx: required_columns * column_width_pixels,
+        imgy: lines_per_column * line_height,
         lines_per_column,
         required_columns,
-    })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_columns_stop_at_a_single_column() {
+        // 3 lines in 10 pixel wide columns go from 3 columns to 2 and 1, whose aspect ratio of 3.33 is the closest.
+        let (dimension, _) = compute_for_aspect_ratio(3.4, 10, 3, 1, true);
+        assert_eq!(
+            (dimension.lines_per_column, dimension.required_columns),
+            (3, 1)
+        );
+        assert_eq!((dimension.imgx, dimension.imgy), (10, 3));
+    }
 }
@@ -0,0 +1,40 @@
+use image::imageops::FilterType;
+use image::{ImageBuffer, Rgb, RgbImage};
+use std::ops::Deref;
+
+/// The filter to scale images down with, from the fastest to the one that looks best.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum DownscaleFilter {
+    /// Pick the nearest pixel, which keeps colors as they are but lets fine patterns alias.
+    Nearest,
+    /// Average neighboring pixels linearly.
+    Triangle,
+    /// Use a cubic Catmull-Rom spline, which is sharper than `triangle`.
+    Catmull,
+    /// Use a Lanczos window with 3 lobes, which keeps the most detail.
+    Lanczos,
+}
+
+impl From<DownscaleFilter> for FilterType {
+    fn from(filter: DownscaleFilter) -> Self {
+        match filter {
+            DownscaleFilter::Nearest => FilterType::Nearest,
+            DownscaleFilter::Triangle => FilterType::Triangle,
+            DownscaleFilter::Catmull => FilterType::CatmullRom,
+            DownscaleFilter::Lanczos => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Scale `img` to `width` and `height` with `filter`, ignoring its aspect ratio.
+pub fn downscale<C>(
+    img: &ImageBuffer<Rgb<u8>, C>,
+    width: u32,
+    height: u32,
+    filter: DownscaleFilter,
+) -> RgbImage
+where
+    C: Deref<Target = [u8]>,
+{
+    image::imageops::resize(img, width, height, filter.into())
+}
@@ -1,17 +1,34 @@
 use crate::render::chunk::calc_offsets;
+use crate::render::column_cache::{ColumnCache, KeyHasher};
+use crate::render::composite;
+use crate::render::highlight::find_syntax;
+use crate::render::shuffle;
+use crate::render::stitch::SharedImage;
+use crate::render::summary;
 use crate::render::Cache;
 use crate::render::Dimension;
-use crate::render::{chunk, Options};
+use crate::render::RateLimit;
+use crate::render::{chunk, Options, RenderOrder, TabMode};
 use crate::DirContents;
+use crate::Phase;
+use crate::RenderError;
 use crate::FILENAME_LINE_COUNT;
-use anyhow::{bail, Context};
-use image::{ImageBuffer, Pixel, Rgb, RgbImage};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use image::{GenericImage, GrayImage, ImageBuffer, Pixel, Rgb, RgbImage};
 use memmap2::MmapMut;
 use prodash::Progress;
+use std::hash::Hash;
+use std::ops::{Deref, DerefMut, Range};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use syntect::highlighting::ThemeSet;
+use std::time::Instant;
+use syntect::highlighting::{Theme, ThemeSet};
 use syntect::parsing::SyntaxSet;
 
+/// How much the aspect ratio of the image may be off the target, relative to the smaller of both, before it's
+/// letterboxed.
+const LETTERBOX_TOLERANCE: f64 = 0.05;
+
 /// Render the given files to an image. Using the given syntax, theme and render options.
 pub fn render(
     dir_content: &DirContents,
@@ -19,30 +36,524 @@ pub fn render(
     should_interrupt: &AtomicBool,
     ss: &SyntaxSet,
     ts: &ThemeSet,
+    opts: Options,
+) -> Result<ImageBuffer<Rgb<u8>, MmapMut>, RenderError> {
+    let Canvas {
+        mut img,
+        drawn:
+            Drawn {
+                background,
+                backgrounds,
+                coverage,
+            },
+        num_files,
+        total_line_count,
+        process_start,
+    } = if opts.width_for_extension.is_empty() {
+        draw_canvas(dir_content, &mut progress, should_interrupt, ss, ts, opts)?
+    } else {
+        draw_canvas_by_width(dir_content, &mut progress, should_interrupt, ss, ts, opts)?
+    };
+
+    if opts.exclude_empty_columns && !opts.exact_aspect {
+        let trimmed;
+        (img, trimmed) = composite::trim_empty_columns(img, background.unwrap_or(Rgb([0, 0, 0])))?;
+        if trimmed != 0 {
+            progress.info(format!(
+                "Trimmed {trimmed} empty pixel columns from the right, leaving {} x {}",
+                img.width(),
+                img.height()
+            ));
+        }
+    }
+
+    // trimming only removes columns at the right, so only padding moves the drawn pixels.
+    let drawn_size = img.dimensions();
+    if opts.exact_aspect {
+        img = composite::pad_to_aspect_ratio(
+            img,
+            opts.target_aspect_ratio,
+            opts.pad,
+            background.unwrap_or(Rgb([0, 0, 0])),
+        )?;
+    }
+
+    if let Some(background_image) = opts.background_image {
+        // Everything that was drawn with a background color is seen through, except for glyphs of that color.
+        let mut backgrounds = backgrounds;
+        remember_background(&mut backgrounds, Some(background.unwrap_or(Rgb([0, 0, 0]))));
+        let offset = composite::pad_offset(drawn_size, img.dimensions(), opts.pad);
+        composite::background_image(
+            &mut img,
+            background_image,
+            opts.bg_fit,
+            &backgrounds,
+            coverage.as_ref().map(|coverage| (coverage, offset)),
+        );
+    }
+
+    if opts.grayscale {
+        composite::grayscale(&mut img);
+    }
+    composite::glow(&mut img, opts.glow_radius, opts.glow_intensity);
+    // letterbox last, so the bands have exactly the requested color.
+    if let Some(letterbox_color) = opts.letterbox_color {
+        let aspect_ratio = img.width() as f64 / img.height() as f64;
+        let off = (aspect_ratio / opts.target_aspect_ratio)
+            .max(opts.target_aspect_ratio / aspect_ratio)
+            - 1.0;
+        if off > LETTERBOX_TOLERANCE {
+            progress.info(format!(
+                "Letterboxing as the aspect ratio is {aspect_ratio:.3} for a target of {:.3}, {:.0}% off",
+                opts.target_aspect_ratio,
+                off * 100.0
+            ));
+            img = composite::pad_to_aspect_ratio(
+                img,
+                opts.target_aspect_ratio,
+                opts.pad,
+                letterbox_color,
+            )?;
+        } else {
+            progress.info(format!(
+                "Not letterboxing as the aspect ratio is {aspect_ratio:.3} for a target of {:.3}, only {:.0}% off",
+                opts.target_aspect_ratio,
+                off * 100.0
+            ));
+        }
+    }
+    composite::draw_badge(
+        &mut img,
+        &badge_text(num_files, total_line_count),
+        opts.badge,
+    );
+    // the frame goes around everything, including the letterbox.
+    img = composite::add_border(img, opts.border, opts.border_color)?;
+    img = composite::add_shadow(img, opts.shadow, opts.shadow_backdrop)?;
+    if let Some(phase_timer) = opts.phase_timer {
+        phase_timer.add(Phase::Process, process_start.elapsed());
+    }
+
+    Ok(img)
+}
+
+/// Render the given files like [`render()`], but into `buffer` instead of a newly allocated image, so the memory
+/// of a buffer can be reused across renders.
+///
+/// `buffer` must have the dimensions returned by [`compute_layout()`] for the same files and options, or
+/// [`RenderError::BufferSize`] is returned before anything is drawn into it.
+///
+/// As the image can't change its size, `exclude_empty_columns`, `exact_aspect`, `letterbox_color`, `border` and
+/// `shadow` are ignored.
+///
+/// With `width_for_extension`, the groups of files are drawn into an image of their own first, which is copied into
+/// `buffer` once it's known to fit, so no memory is saved then.
+pub fn render_into<C>(
+    buffer: &mut ImageBuffer<Rgb<u8>, C>,
+    dir_content: &DirContents,
+    mut progress: impl Progress,
+    should_interrupt: &AtomicBool,
+    ss: &SyntaxSet,
+    ts: &ThemeSet,
+    opts: Options,
+) -> Result<(), RenderError>
+where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    let (
+        Drawn {
+            background,
+            backgrounds,
+            coverage,
+        },
+        badge_text,
+        process_start,
+    ) = if opts.width_for_extension.is_empty() {
+        let layout = layout(dir_content, &mut progress, ss, ts, opts)?;
+        let Layout {
+            imgx,
+            imgy,
+            process_start,
+            ..
+        } = layout;
+        if buffer.dimensions() != (imgx, imgy) {
+            return Err(RenderError::BufferSize {
+                expected: (imgx, imgy),
+                actual: buffer.dimensions(),
+            });
+        }
+        progress.info(format!(
+            "Image dimensions: {imgx} x {imgy} x {} [x * y * channels] (rendered into the given buffer)",
+            Rgb::<u8>::CHANNEL_COUNT,
+        ));
+        let badge_text = badge_text(layout.content.len(), layout.total_line_count);
+        let drawn = draw(
+            dir_content,
+            &mut progress,
+            should_interrupt,
+            ss,
+            opts,
+            layout,
+            buffer,
+        )?;
+        (drawn, badge_text, process_start)
+    } else {
+        let Canvas {
+            img,
+            drawn,
+            num_files,
+            total_line_count,
+            process_start,
+        } = draw_canvas_by_width(dir_content, &mut progress, should_interrupt, ss, ts, opts)?;
+        if buffer.dimensions() != img.dimensions() {
+            return Err(RenderError::BufferSize {
+                expected: img.dimensions(),
+                actual: buffer.dimensions(),
+            });
+        }
+        buffer.copy_from_slice(&img);
+        (
+            drawn,
+            badge_text(num_files, total_line_count),
+            process_start,
+        )
+    };
+
+    if let Some(background_image) = opts.background_image {
+        let mut backgrounds = backgrounds;
+        remember_background(&mut backgrounds, Some(background.unwrap_or(Rgb([0, 0, 0]))));
+        composite::background_image(
+            buffer,
+            background_image,
+            opts.bg_fit,
+            &backgrounds,
+            coverage.as_ref().map(|coverage| (coverage, (0, 0))),
+        );
+    }
+    if opts.grayscale {
+        composite::grayscale(buffer);
+    }
+    composite::glow(buffer, opts.glow_radius, opts.glow_intensity);
+    composite::draw_badge(buffer, &badge_text, opts.badge);
+    if let Some(phase_timer) = opts.phase_timer {
+        phase_timer.add(Phase::Process, process_start.elapsed());
+    }
+    Ok(())
+}
+
+/// An image with all files drawn into it, before it is processed any further.
+struct Canvas {
+    img: ImageBuffer<Rgb<u8>, MmapMut>,
+    drawn: Drawn,
+    /// The amount of files that were drawn.
+    num_files: usize,
+    /// The amount of lines that were drawn, including those showing file names.
+    total_line_count: u32,
+    process_start: Instant,
+}
+
+/// Draw all files into a new image of the size their layout demands.
+fn draw_canvas(
+    dir_content: &DirContents,
+    progress: &mut impl Progress,
+    should_interrupt: &AtomicBool,
+    ss: &SyntaxSet,
+    ts: &ThemeSet,
+    opts: Options,
+) -> Result<Canvas, RenderError> {
+    let layout = layout(dir_content, progress, ss, ts, opts)?;
+    let Layout {
+        imgx,
+        imgy,
+        process_start,
+        total_line_count,
+        ..
+    } = layout;
+    let num_files = layout.content.len();
+
+    let num_pixels = {
+        let channel_count = Rgb::<u8>::CHANNEL_COUNT;
+        let num_pixels = imgx as usize * imgy as usize * channel_count as usize;
+        progress.info(format!(
+            "Image dimensions: {imgx} x {imgy} x {channel_count} [x * y * channels] ({} in virtual memory)",
+            bytesize::ByteSize(num_pixels as u64 ),
+        ));
+        num_pixels
+    };
+
+    let mut img = ImageBuffer::<Rgb<u8>, _>::from_raw(imgx, imgy, MmapMut::map_anon(num_pixels)?)
+        .expect("correct size computation above");
+    let drawn = draw(
+        dir_content,
+        progress,
+        should_interrupt,
+        ss,
+        opts,
+        layout,
+        &mut img,
+    )?;
+    Ok(Canvas {
+        img,
+        drawn,
+        num_files,
+        total_line_count,
+        process_start,
+    })
+}
+
+/// Split the files of `dir_content` into groups of the extensions in `width_for_extension`, and one of all other
+/// files, each along with the options to lay it out with its column width as its own image.
+///
+/// Empty groups are left out.
+fn groups_by_width<'a>(
+    dir_content: &DirContents,
+    opts: Options<'a>,
+) -> Vec<(DirContents, Options<'a>)> {
+    // the column width of each group, with files of other extensions first.
+    let mut groups = vec![(None, Vec::new())];
+    groups.extend(
+        opts.width_for_extension
+            .iter()
+            .map(|(_, width)| (Some(*width), Vec::new())),
+    );
+    for file in &dir_content.children_content {
+        let group = file
+            .0
+            .extension()
+            .and_then(|ext| {
+                opts.width_for_extension
+                    .iter()
+                    .position(|(extension, _)| ext == extension.as_str())
+            })
+            .map_or(0, |index| index + 1);
+        groups[group].1.push(file.clone());
+    }
+    groups.retain(|(_, files)| !files.is_empty());
+
+    // each group gets a share of the aspect ratio or width as large as the share of characters it shows.
+    let filename_lines = if opts.show_filenames {
+        FILENAME_LINE_COUNT as usize
+    } else {
+        0
+    };
+    let weights = groups
+        .iter()
+        .map(|(width, files)| {
+            let lines = files
+                .iter()
+                .map(|(_, content)| content.lines().count() + filename_lines)
+                .sum::<usize>();
+            lines as f64 * width.unwrap_or(opts.column_width) as f64
+        })
+        .collect::<Vec<_>>();
+    let total_weight = weights.iter().sum::<f64>().max(1.0);
+
+    groups
+        .into_iter()
+        .zip(weights)
+        .map(|((width, files), weight)| {
+            let share = weight / total_weight;
+            let group = DirContents {
+                parent_dir: dir_content.parent_dir.clone(),
+                children_content: files,
+            };
+            let opts = Options {
+                column_width: width.unwrap_or(opts.column_width),
+                auto_column_width: width.map_or(opts.auto_column_width, |_| None),
+                target_aspect_ratio: opts.target_aspect_ratio * share,
+                target_width: opts
+                    .target_width
+                    .map(|target_width| ((target_width as f64 * share) as u32).max(1)),
+                ..opts
+            };
+            (group, opts)
+        })
+        .collect()
+}
+
+/// Draw the files of each extension in `width_for_extension` with their column width into their own image,
+/// and all other files with `column_width`, and place these images next to each other.
+fn draw_canvas_by_width(
+    dir_content: &DirContents,
+    progress: &mut impl Progress,
+    should_interrupt: &AtomicBool,
+    ss: &SyntaxSet,
+    ts: &ThemeSet,
+    opts: Options,
+) -> Result<Canvas, RenderError> {
+    let groups = groups_by_width(dir_content, opts);
+    let mut canvases = Vec::with_capacity(groups.len());
+    for (group, opts) in groups {
+        match draw_canvas(&group, progress, should_interrupt, ss, ts, opts) {
+            Ok(canvas) => canvases.push(canvas),
+            // groups of only empty files are left out.
+            Err(RenderError::NoLines { .. }) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    if canvases.is_empty() {
+        return Err(RenderError::NoLines {
+            num_files: dir_content.children_content.len(),
+        });
+    }
+
+    let imgx = canvases
+        .iter()
+        .map(|canvas| canvas.img.width())
+        .sum::<u32>();
+    let imgy = canvases
+        .iter()
+        .map(|canvas| canvas.img.height())
+        .max()
+        .unwrap_or(0);
+    let channel_count = Rgb::<u8>::CHANNEL_COUNT as usize;
+    let mut img = ImageBuffer::<Rgb<u8>, _>::from_raw(
+        imgx,
+        imgy,
+        MmapMut::map_anon(imgx as usize * imgy as usize * channel_count)?,
+    )
+    .expect("correct size computation above");
+    // all groups are drawn with the same options, so either all or none of them track their coverage.
+    let mut coverage = canvases[0]
+        .drawn
+        .coverage
+        .as_ref()
+        .map(|_| GrayImage::new(imgx, imgy));
+    let mut x = 0;
+    let mut backgrounds = Vec::new();
+    for canvas in &canvases {
+        let (width, height) = canvas.img.dimensions();
+        let background = canvas.drawn.background.unwrap_or(Rgb([0, 0, 0]));
+        chunk::fill_rect(&mut img, x, height, width, imgy - height, background);
+        img.copy_from(&canvas.img, x, 0)
+            .expect("canvases fit next to each other");
+        if let Some((coverage, canvas_coverage)) =
+            coverage.as_mut().zip(canvas.drawn.coverage.as_ref())
+        {
+            coverage
+                .copy_from(canvas_coverage, x, 0)
+                .expect("canvases fit next to each other");
+        }
+        for background in &canvas.drawn.backgrounds {
+            remember_background(&mut backgrounds, Some(*background));
+        }
+        x += width;
+    }
+    Ok(Canvas {
+        img,
+        drawn: Drawn {
+            background: canvases.last().and_then(|canvas| canvas.drawn.background),
+            backgrounds,
+            coverage,
+        },
+        num_files: canvases.iter().map(|canvas| canvas.num_files).sum(),
+        total_line_count: canvases.iter().map(|canvas| canvas.total_line_count).sum(),
+        process_start: canvases[0].process_start,
+    })
+}
+
+/// The text of the badge for `num_files` with `total_line_count` lines.
+fn badge_text(num_files: usize, total_line_count: u32) -> String {
+    format!("{num_files} files, {total_line_count} lines")
+}
+
+/// Return the `(width, height)` of the image the given files are rendered to before it is padded, which is the size
+/// of the buffer to pass to [`render_into()`].
+///
+/// This reads all files and determines the layout like [`render()`], without drawing anything.
+pub fn compute_layout(
+    dir_content: &DirContents,
+    mut progress: impl Progress,
+    ss: &SyntaxSet,
+    ts: &ThemeSet,
+    opts: Options,
+) -> Result<(u32, u32), RenderError> {
+    if opts.width_for_extension.is_empty() {
+        let Layout { imgx, imgy, .. } = layout(dir_content, &mut progress, ss, ts, opts)?;
+        return Ok((imgx, imgy));
+    }
+
+    // like `draw_canvas_by_width()`, groups are placed next to each other.
+    let mut size = None;
+    for (group, opts) in groups_by_width(dir_content, opts) {
+        match layout(&group, &mut progress, ss, ts, opts) {
+            Ok(Layout { imgx, imgy, .. }) => {
+                let (width, height) = size.unwrap_or((0, 0));
+                size = Some((width + imgx, imgy.max(height)));
+            }
+            Err(RenderError::NoLines { .. }) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    size.ok_or(RenderError::NoLines {
+        num_files: dir_content.children_content.len(),
+    })
+}
+
+/// Where the files go in the image, and everything else that is known before the first pixel is drawn.
+struct Layout<'a> {
+    theme: &'a Theme,
+    themes_by_extension: Vec<(String, &'a Theme)>,
+    highlight_files: GlobSet,
+    content: Vec<FileEntry<'a>>,
+    total_line_count: u32,
+    num_ignored: usize,
+    column_width: u32,
+    char_width: u32,
+    /// The height of a line including `line_spacing`.
+    line_height: u32,
+    lines_per_column: u32,
+    required_columns: u32,
+    imgx: u32,
+    imgy: u32,
+    /// The lines left empty to not split files across columns.
+    empty_lines: Vec<Range<u32>>,
+    start: Instant,
+    process_start: Instant,
+}
+
+/// Read the files of `dir_content` and place them into columns, without allocating the image yet.
+fn layout<'a>(
+    dir_content: &'a DirContents,
+    progress: &mut impl Progress,
+    ss: &SyntaxSet,
+    ts: &'a ThemeSet,
     Options {
         column_width,
         line_height,
         readable,
         show_filenames,
         target_aspect_ratio,
-        threads,
-        fg_color,
-        bg_color,
-        highlight_truncated_lines,
-        display_to_be_processed_file,
         theme,
+        theme_for_extension,
         force_full_columns,
-        plain,
         ignore_files_without_syntax,
-        color_modulation,
+        detect_by_content,
         tab_spaces,
+        tab_mode,
         line_nums,
+        target_width,
+        file_summary,
+        max_columns,
+        phase_timer,
+        uniform_file_height,
+        indent_by_depth,
+        render_order,
+        highlight_files,
+        max_memory,
+        shuffle_seed,
+        line_spacing,
+        auto_column_width,
+        min_column_lines,
+        no_split_files,
+        strip_leading_indentation,
+        ..
     }: Options,
-) -> anyhow::Result<ImageBuffer<Rgb<u8>, MmapMut>> {
+) -> Result<Layout<'a>, RenderError> {
     // unused for now
     // could be used to make a "rolling code" animation
-    let start = std::time::Instant::now();
-
+    let start = Instant::now();
     let mut line_height = line_height;
     let mut char_width = 1;
     if readable {
@@ -52,72 +563,271 @@ pub fn render(
     let line_height = line_height;
     let char_width = char_width;
 
+    // resolve all themes up front, before any expensive work is done.
+    let theme = theme_by_name(ts, theme)?;
+    let themes_by_extension = theme_for_extension
+        .iter()
+        .map(|(extension, theme)| Ok((extension.to_owned(), theme_by_name(ts, theme)?)))
+        .collect::<Result<Vec<_>, RenderError>>()?;
+    let highlight_files = glob_set(highlight_files)?;
+
     //> read files (for /n counting)
-    let (content, total_line_count, num_ignored) = {
-        let mut out = Vec::with_capacity(dir_content.children_content.len());
-        let mut lines = 0;
-        let mut num_ignored = 0;
-        let mut lines_so_far = 0u32;
-        for (path, content) in &dir_content.children_content {
-            let num_content_lines = content.lines().count();
-            lines += num_content_lines;
-            if ignore_files_without_syntax && ss.find_syntax_for_file(path)?.is_none() {
-                lines -= num_content_lines;
-                num_ignored += 1;
-            } else {
-                out.push(((path, content), num_content_lines, lines_so_far));
-                lines_so_far += num_content_lines as u32;
-                if show_filenames {
-                    lines_so_far += FILENAME_LINE_COUNT;
-                }
-            }
-        }
-        (out, lines as u32, num_ignored)
-    };
+    let Collected {
+        mut content,
+        total_line_count,
+        num_ignored,
+        num_truncated,
+        num_empty,
+    } = collect_content(
+        dir_content,
+        ss,
+        CollectOptions {
+            ignore_files_without_syntax,
+            detect_by_content,
+            uniform_file_height,
+            show_filenames,
+            render_order,
+            shuffle_seed,
+        },
+    )?;
 
     if total_line_count == 0 {
-        bail!(
-            "Did not find a single line to render in {} files",
-            content.len()
-        );
+        return Err(RenderError::NoLines {
+            num_files: content.len() + num_empty,
+        });
     }
+    if num_empty != 0 {
+        progress.info(format!("Skipped {num_empty} empty files"));
+    }
+    if let (Some(height), true) = (uniform_file_height, num_truncated != 0) {
+        progress.info(format!("Truncated {num_truncated} files to {height} lines"));
+    }
+    let column_width = match auto_column_width {
+        Some(max_width) => {
+            let column_width = auto_column_width_for(
+                dir_content,
+                &content,
+                max_width,
+                tab_spaces,
+                tab_mode,
+                line_nums,
+                show_filenames,
+                indent_by_depth,
+                readable,
+                strip_leading_indentation,
+            );
+            progress.info(format!("Column width: {column_width} characters"));
+            column_width
+        }
+        None => column_width,
+    };
 
     // add lines if displaying filenames.
     let mut total_line_count = total_line_count;
     if show_filenames {
         total_line_count += content.len() as u32 * FILENAME_LINE_COUNT;
     }
+    if file_summary {
+        // each file is drawn as a single line.
+        total_line_count = content.len() as u32;
+    }
     // re-make immutable
     let total_line_count = total_line_count;
 
-    // determine number and height of columns closest to desired aspect ratio
+    // determine number and height of columns closest to desired aspect ratio,
+    // with lines getting thinner until the image fits into `max_memory`.
+    let requested_line_height = line_height;
+    let mut line_height = line_height;
+    // with `no_split_files`, the empty lines at the bottom of columns need space as well.
+    let mut layout_line_count = total_line_count;
+    let mut empty_lines = Vec::new();
     let Dimension {
         imgx,
         imgy,
         lines_per_column,
         required_columns,
-    } = crate::render::dimension::compute(
-        target_aspect_ratio,
-        column_width * char_width,
-        total_line_count,
-        line_height,
-        force_full_columns,
-        progress.add_child("determine dimensions"),
-    )?;
-
-    let num_pixels = {
-        let channel_count = Rgb::<u8>::CHANNEL_COUNT;
-        let num_pixels = imgx as usize * imgy as usize * channel_count as usize;
+    } = loop {
+        let mut dimension = crate::render::dimension::compute(
+            target_aspect_ratio,
+            target_width,
+            max_columns,
+            min_column_lines,
+            column_width * char_width,
+            layout_line_count,
+            line_height + line_spacing,
+            force_full_columns,
+            progress.add_child("determine dimensions"),
+        )?;
+        if no_split_files && !file_summary {
+            let (gaps, end) = keep_files_in_columns(
+                &mut content,
+                dimension.lines_per_column,
+                show_filenames,
+                uniform_file_height,
+            );
+            if end > dimension.lines_per_column * dimension.required_columns {
+                // more lines make for more or taller columns, until the empty lines fit as well.
+                if end > layout_line_count {
+                    layout_line_count = end;
+                    continue;
+                }
+                dimension.required_columns = end.div_ceil(dimension.lines_per_column);
+                dimension.imgx = dimension.required_columns * column_width * char_width;
+            }
+            empty_lines = gaps;
+        }
+        let num_bytes =
+            dimension.imgx as u64 * dimension.imgy as u64 * Rgb::<u8>::CHANNEL_COUNT as u64;
+        match max_memory {
+            Some(max_memory) if num_bytes > max_memory => {
+                // readable glyphs need their full height.
+                if readable || line_height == 1 {
+                    return Err(RenderError::ExceedsMaxMemory {
+                        num_bytes,
+                        max_memory,
+                        line_height,
+                    });
+                }
+                line_height -= 1;
+            }
+            _ => break dimension,
+        }
+    };
+    if !empty_lines.is_empty() {
         progress.info(format!(
-            "Image dimensions: {imgx} x {imgy} x {channel_count} [x * y * channels] ({} in virtual memory)",
-            bytesize::ByteSize(num_pixels as u64 ),
+            "Left {} lines empty to not split files across columns",
+            empty_lines
+                .iter()
+                .map(ExactSizeIterator::len)
+                .sum::<usize>()
         ));
-        num_pixels
-    };
+    }
+    if line_height != requested_line_height {
+        progress.info(format!(
+            "Reduced the line height from {requested_line_height} to {line_height} pixels to stay within {}",
+            bytesize::ByteSize(max_memory.unwrap_or_default())
+        ));
+    }
+    // from here on, lines are `line_spacing` further apart, with the spacing left blank when drawing them.
+    let line_height = line_height + line_spacing;
+    let process_start = Instant::now();
+    if let Some(phase_timer) = phase_timer {
+        phase_timer.add(Phase::Dimension, process_start - start);
+    }
 
-    let mut img = ImageBuffer::<Rgb<u8>, _>::from_raw(imgx, imgy, MmapMut::map_anon(num_pixels)?)
-        .expect("correct size computation above");
+    Ok(Layout {
+        theme,
+        themes_by_extension,
+        highlight_files,
+        content,
+        total_line_count,
+        num_ignored,
+        column_width,
+        char_width,
+        line_height,
+        lines_per_column,
+        required_columns,
+        imgx,
+        imgy,
+        empty_lines,
+        start,
+        process_start,
+    })
+}
 
+/// The backgrounds that were drawn.
+struct Drawn {
+    /// The background of the last file, which also fills the empty lines.
+    background: Option<Rgb<u8>>,
+    /// The backgrounds of all files.
+    backgrounds: Vec<Rgb<u8>>,
+    /// The pixels that glyphs were drawn on, if a `background_image` needs to tell them apart from backgrounds of
+    /// the same color.
+    coverage: Option<GrayImage>,
+}
+
+/// Draw the files into `img` as placed by `layout`.
+fn draw<'a, C>(
+    dir_content: &DirContents,
+    progress: &mut impl Progress,
+    should_interrupt: &AtomicBool,
+    ss: &SyntaxSet,
+    Options {
+        readable,
+        show_filenames,
+        threads,
+        fg_color,
+        bg_color,
+        highlight_truncated_lines,
+        display_to_be_processed_file,
+        theme: theme_name,
+        theme_for_extension,
+        plain,
+        color_modulation,
+        tab_spaces,
+        tab_mode,
+        line_nums,
+        strip_control,
+        rtl_columns,
+        cache_dir,
+        file_summary,
+        highlight_trailing_whitespace,
+        length_gradient,
+        canvas_color,
+        uniform_background,
+        uniform_file_height,
+        indent_by_depth,
+        comment_opacity,
+        only,
+        missing_glyph,
+        highlight_color,
+        highlight_border_width,
+        line_spacing,
+        display_rate_limit,
+        mono,
+        grep,
+        grep_color,
+        grep_strength,
+        fg_boost,
+        strip_leading_indentation,
+        blend_space,
+        line_changes,
+        line_authors,
+        detect_by_content,
+        trailing_whitespace_color,
+        length_gradient_color,
+        length_gradient_strength,
+        glyph_outline,
+        glyph_outline_color,
+        deadline,
+        zebra_columns,
+        zebra_strength,
+        mark_blank_files,
+        background_image,
+        ..
+    }: Options,
+    Layout {
+        theme,
+        themes_by_extension,
+        highlight_files,
+        content,
+        total_line_count,
+        num_ignored,
+        column_width,
+        char_width,
+        line_height,
+        lines_per_column,
+        required_columns,
+        empty_lines,
+        start,
+        ..
+    }: Layout<'a>,
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+) -> Result<Drawn, RenderError>
+where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
     progress.set_name("process");
     progress.init(
         Some(content.len()),
@@ -130,25 +840,103 @@ pub fn render(
         prodash::unit::label_and_mode("lines", prodash::unit::display::Mode::with_throughput())
             .into(),
     );
-    let mut cache = Cache::new_with_plain_highlighter(
+    let cache = Cache::new(
         ss,
-        ts.themes.get(theme).with_context(|| {
-            format!(
-                "Could not find theme {theme:?}, must be one of {}",
-                ts.themes
-                    .keys()
-                    .map(|s| format!("{s:?}"))
-                    .collect::<Vec<_>>()
-                    .join(", ")
-            )
-        })?,
+        theme,
+        themes_by_extension,
+        comment_opacity,
+        only,
+        detect_by_content,
     );
+    let display_limit = RateLimit::new(display_rate_limit);
+    let uniform_background = uniform_background
+        .then(|| chunk::uniform_background(&cache, ss, bg_color, color_modulation))
+        .transpose()?;
+
+    // fill whole lines of the final image with a single color.
+    let fill_lines = |img: &mut ImageBuffer<Rgb<u8>, C>, lines: Range<u32>, color: Rgb<u8>| {
+        for line_num in lines {
+            let (cur_column_x_offset, cur_y) = calc_offsets(
+                line_num,
+                lines_per_column,
+                column_width,
+                line_height,
+                required_columns,
+                rtl_columns,
+            );
+            chunk::fill_rect(
+                img,
+                cur_column_x_offset * char_width,
+                cur_y,
+                column_width * char_width,
+                line_height,
+                color,
+            );
+        }
+    };
+
+    let indent_for =
+        |relative_path: &Path| indent_for(relative_path, indent_by_depth, column_width);
 
     let threads = (threads == 0)
         .then(num_cpus::get)
         .unwrap_or(threads)
         .clamp(1, num_cpus::get());
-    let (mut line_num, longest_line_chars, background) = if threads < 2 {
+    let mut coverage = background_image.map(|_| GrayImage::new(img.width(), img.height()));
+    // cached columns don't know which of their pixels are glyphs, so they can't be used along with the coverage.
+    let column_cache = cache_dir
+        .filter(|_| coverage.is_none())
+        .map(|dir| {
+            ColumnCache::new(dir).map_err(|source| RenderError::CacheDir {
+                dir: dir.to_owned(),
+                source,
+            })
+        })
+        .transpose()?;
+    // Everything besides the file itself and its chunk context that affects how it is rendered.
+    let appearance_key = (
+        env!("CARGO_PKG_VERSION"),
+        theme_name,
+        theme_for_extension,
+        plain,
+        uniform_file_height,
+        comment_opacity.to_bits(),
+        only,
+        detect_by_content,
+    );
+
+    // Files rendered into their own column images can be cached, so caching forces that code path.
+    // the amount of files that were rendered before the `deadline` passed, if it passed before all of them were.
+    let mut stopped_after = None;
+    let (line_num, longest_line_chars, background, backgrounds) = if file_summary {
+        let (line_num, backgrounds) = summary::process(
+            img,
+            content
+                .iter()
+                .map(|((path, content), num_content_lines, _lines_so_far)| {
+                    (path.as_path(), *content, *num_content_lines)
+                }),
+            &cache,
+            ss,
+            plain,
+            summary::Layout {
+                column_width: column_width * char_width,
+                line_height,
+                line_spacing,
+                lines_per_column,
+                required_columns,
+                rtl_columns,
+                bg_color,
+                color_modulation,
+                mono,
+                uniform_background,
+            },
+            should_interrupt,
+        )?;
+        progress.inc_by(content.len());
+        line_progress.inc_by(total_line_count as usize);
+        (line_num, 0, backgrounds.last().copied(), backgrounds)
+    } else if threads < 2 && column_cache.is_none() {
         // single-threaded rendering overview:
         //
         // Sqeuentially iterates over the contents of each file to render.
@@ -158,34 +946,42 @@ pub fn render(
         let mut line_num: u32 = 0;
         let mut longest_line_chars = 0;
         let mut background = None;
-        let mut highlighter = cache.new_plain_highlighter();
-        for (file_index, ((path, content), num_content_lines, _lines_so_far)) in
-            content.into_iter().enumerate()
+        let mut backgrounds = Vec::new();
+        for (file_index, &((path, content), num_content_lines, lines_so_far)) in
+            content.iter().enumerate()
         {
+            if is_past(deadline) {
+                stopped_after = Some(file_index);
+                break;
+            }
+            // skip the lines left empty to not split files.
+            line_num = lines_so_far;
             progress.inc();
             if should_interrupt.load(Ordering::Relaxed) {
-                bail!("Cancelled by user")
-            }
-            if !plain {
-                if let Some(hl) = cache.highlighter_for_file_name(path)? {
-                    highlighter = hl;
-                }
+                return Err(RenderError::Interrupted);
             }
+            let mut highlighter = if plain {
+                cache.new_plain_highlighter()
+            } else {
+                cache.highlighter_for_file(path, content)
+            };
 
-            let relative_path = path.strip_prefix(&dir_content.parent_dir).unwrap();
-            if display_to_be_processed_file {
-                progress.info(format!("{relative_path:?}"))
+            let relative_path = &dir_content.relative_path(path);
+            if display_to_be_processed_file && display_limit.allow() {
+                progress.info(relative_path.display().to_string())
             }
             let out = chunk::process(
                 &relative_path,
                 content,
-                &mut img,
+                img,
+                coverage.as_mut(),
                 |line| highlighter.highlight_line(line, ss),
                 chunk::Context {
                     column_width,
                     line_height,
                     char_width,
-                    total_line_count,
+                    // lines left empty by `no_split_files` move files past the amount of lines.
+                    total_line_count: lines_per_column * required_columns,
                     highlight_truncated_lines,
                     line_num,
                     lines_per_column,
@@ -197,37 +993,72 @@ pub fn render(
                     readable,
                     show_filenames,
                     line_nums,
+                    strip_control,
+                    required_columns,
+                    rtl_columns,
+                    highlight_trailing_whitespace,
+                    length_gradient,
+                    canvas_color,
+                    uniform_background,
+                    indent: indent_for(relative_path),
+                    missing_glyph,
+                    line_spacing,
+                    mono,
+                    grep,
+                    grep_color,
+                    grep_strength,
+                    fg_boost,
+                    strip_leading_indentation,
+                    blend_space,
+                    tab_mode,
+                    line_changes: line_changes.and_then(|changes| changes.of(relative_path)),
+                    line_authors: line_authors.and_then(|authors| authors.of(relative_path)),
+                    trailing_whitespace_color,
+                    length_gradient_color,
+                    length_gradient_strength,
+                    glyph_outline,
+                    glyph_outline_color,
                 },
             )?;
             longest_line_chars = out.longest_line_in_chars.max(longest_line_chars);
-            line_num += num_content_lines as u32;
             if show_filenames {
                 line_num += FILENAME_LINE_COUNT
             };
+            let file_lines = uniform_file_height.unwrap_or(num_content_lines as u32);
+            fill_lines(
+                img,
+                line_num + num_content_lines as u32..line_num + file_lines,
+                out.background.unwrap_or(Rgb([0, 0, 0])),
+            );
+            line_num += file_lines;
             line_progress.inc_by(num_content_lines);
             background = out.background;
+            remember_background(&mut backgrounds, background);
         }
 
-        (line_num, longest_line_chars, background)
+        (line_num, longest_line_chars, background, backgrounds)
     } else {
         // multi-threaded rendering overview:
         //
         // Spawns threadpool and each file to be renered is sent to a thread as a message via a flume channel.
-        // Upon recieving a message, a thread renders the entire file to an image of one column width.
-        // and then returns that image to this main thread via a flume channel, to be stitched together
-        // into one large image. The ordering of files rendered in the final image is remembered and
-        // independant of thread rendering order.
+        // Upon recieving a message, a thread renders the entire file to an image of one column width,
+        // and writes its lines directly into their place in the final image. Only the outcome is sent
+        // back to this main thread, which keeps track of it independently of thread rendering order.
 
         let mut line_num: u32 = 0;
         let mut longest_line_chars = 0;
-        let mut background = None;
+        // The background of the file with the highest index so far, as the corner is filled with the last one.
+        let mut last_background = None::<(usize, Option<Rgb<u8>>)>;
+        let mut backgrounds = Vec::new();
         // An atomic integer used to tell threads which file to render next.
         // Threads read a value and then incrment it.
         // This is cheaper than creating a channel and sending the content to
         // render via channel to each thread.
         let file_index = AtomicUsize::default();
+        let shared_img = SharedImage::new(img);
+        let shared_coverage = coverage.as_mut().map(SharedImage::new);
 
-        std::thread::scope(|scope| -> anyhow::Result<()> {
+        std::thread::scope(|scope| -> Result<(), RenderError> {
             let (ttx, trx) = flume::bounded(threads);
             for tid in 0..threads {
                 scope.spawn({
@@ -235,61 +1066,170 @@ pub fn render(
                     let file_index = &file_index;
                     let ss = &ss;
                     let content = &content;
-                    let mut state = cache.clone();
+                    let state = cache.clone();
+                    let column_cache = column_cache.as_ref();
+                    let display_limit = &display_limit;
+                    let indent_for = &indent_for;
+                    let shared_img = &shared_img;
+                    let shared_coverage = &shared_coverage;
                     let mut progress = line_progress.add_child(format!("Thread {tid}"));
-                    move || -> anyhow::Result<()> {
-                        let mut highlighter = state.new_plain_highlighter();
+                    move || -> Result<(), RenderError> {
+                        // files are handed out in order, so those rendered before the deadline come first.
                         while let Ok(file_index) =
                             file_index.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| {
-                                (x < content.len()).then_some(x + 1)
+                                (x < content.len() && !is_past(deadline)).then_some(x + 1)
                             })
                         {
                             let ((path, content), num_content_lines, lines_so_far) =
                                 &content[file_index];
-                            if !plain {
-                                if let Some(hl) = state.highlighter_for_file_name(path)? {
-                                    highlighter = hl;
-                                }
+                            let file_lines =
+                                uniform_file_height.unwrap_or(*num_content_lines as u32);
+                            let mut lines_in_sub_img = file_lines;
+                            if show_filenames {
+                                lines_in_sub_img += FILENAME_LINE_COUNT;
                             }
+                            let img_height = lines_in_sub_img * line_height;
 
-                            let img_height = if show_filenames {
-                                (*num_content_lines as u32 * line_height)
-                                    + line_height * FILENAME_LINE_COUNT
-                            } else {
-                                *num_content_lines as u32 * line_height
+                            let relative_path = &dir_content.relative_path(path);
+                            let context = chunk::Context {
+                                column_width,
+                                line_height,
+                                char_width,
+                                total_line_count,
+                                highlight_truncated_lines,
+                                line_num: 0,
+                                lines_per_column: total_line_count,
+                                fg_color,
+                                bg_color,
+                                file_index,
+                                color_modulation,
+                                tab_spaces,
+                                readable,
+                                show_filenames,
+                                line_nums,
+                                strip_control,
+                                required_columns: 1,
+                                rtl_columns: false,
+                                highlight_trailing_whitespace,
+                                length_gradient,
+                                canvas_color,
+                                uniform_background,
+                                indent: indent_for(relative_path),
+                                missing_glyph,
+                                line_spacing,
+                                mono,
+                                grep,
+                                grep_color,
+                                grep_strength,
+                                fg_boost,
+                                strip_leading_indentation,
+                                blend_space,
+                                tab_mode,
+                                line_changes: line_changes
+                                    .and_then(|changes| changes.of(relative_path)),
+                                line_authors: line_authors
+                                    .and_then(|authors| authors.of(relative_path)),
+                                trailing_whitespace_color,
+                                length_gradient_color,
+                                length_gradient_strength,
+                                glyph_outline,
+                                glyph_outline_color,
                             };
+                            let cache_key = column_cache.is_some().then(|| {
+                                let mut state = KeyHasher::new();
+                                appearance_key.hash(&mut state);
+                                relative_path.hash(&mut state);
+                                content.hash(&mut state);
+                                context.hash_appearance(&mut state);
+                                state.key()
+                            });
+                            let cached =
+                                column_cache.zip(cache_key).and_then(|(column_cache, key)| {
+                                    column_cache.load(&key, column_width * char_width, img_height)
+                                });
+                            let mut sub_coverage = None;
+                            let (sub_img, out) = match cached {
+                                Some(cached) => cached,
+                                None => {
+                                    let mut highlighter = if plain {
+                                        state.new_plain_highlighter()
+                                    } else {
+                                        state.highlighter_for_file(path, content)
+                                    };
 
-                            // create an image that fits one column
-                            let mut img = RgbImage::new(column_width * char_width, img_height);
+                                    // create an image that fits one column
+                                    let mut img =
+                                        RgbImage::new(column_width * char_width, img_height);
+                                    sub_coverage = shared_coverage.as_ref().map(|_| {
+                                        GrayImage::new(column_width * char_width, img_height)
+                                    });
 
-                            let relative_path = path.strip_prefix(&dir_content.parent_dir).unwrap();
-                            if display_to_be_processed_file {
-                                progress.info(format!("{relative_path:?}"))
-                            }
-                            let out = chunk::process(
-                                &relative_path,
-                                content,
-                                &mut img,
-                                |line| highlighter.highlight_line(line, ss),
-                                chunk::Context {
+                                    if display_to_be_processed_file && display_limit.allow() {
+                                        progress.info(relative_path.display().to_string())
+                                    }
+                                    let out = chunk::process(
+                                        relative_path,
+                                        content,
+                                        &mut img,
+                                        sub_coverage.as_mut(),
+                                        |line| highlighter.highlight_line(line, ss),
+                                        context,
+                                    )?;
+                                    // pad files that are shorter than the uniform file height.
+                                    let padding =
+                                        (file_lines - *num_content_lines as u32) * line_height;
+                                    chunk::fill_rect(
+                                        &mut img,
+                                        0,
+                                        img_height - padding,
+                                        column_width * char_width,
+                                        padding,
+                                        out.background.unwrap_or(Rgb([0, 0, 0])),
+                                    );
+                                    if let Some((column_cache, key)) = column_cache.zip(cache_key) {
+                                        column_cache.store(&key, &img, &out)?;
+                                    }
+                                    (img, out)
+                                }
+                            };
+
+                            // transfer pixels from sub_img to img. Where sub_img is a 1 column wide
+                            // image of one file. And img is our multi-column wide final output image.
+                            for line in 0..lines_in_sub_img {
+                                let (x_offset, line_y) = calc_offsets(
+                                    lines_so_far + line,
+                                    lines_per_column,
                                     column_width,
                                     line_height,
-                                    char_width,
-                                    total_line_count,
-                                    highlight_truncated_lines,
-                                    line_num: 0,
-                                    lines_per_column: total_line_count,
-                                    fg_color,
-                                    bg_color,
-                                    file_index,
-                                    color_modulation,
-                                    tab_spaces,
-                                    readable,
-                                    show_filenames,
-                                    line_nums,
-                                },
-                            )?;
-                            ttx.send((img, out, *num_content_lines, *lines_so_far))?;
+                                    required_columns,
+                                    rtl_columns,
+                                );
+                                // SAFETY: the lines of a file are `lines_so_far..lines_so_far + lines_in_sub_img`,
+                                // which don't overlap with the lines of any other file, and each file is rendered
+                                // by a single thread as handed out by `file_index`. Distinct lines are placed into
+                                // distinct cells of the column layout, so no other thread writes these pixels.
+                                unsafe {
+                                    shared_img.write_line(
+                                        &sub_img,
+                                        line,
+                                        line_height,
+                                        (x_offset * char_width, line_y),
+                                    );
+                                    if let Some((shared_coverage, sub_coverage)) =
+                                        shared_coverage.as_ref().zip(sub_coverage.as_ref())
+                                    {
+                                        shared_coverage.write_line(
+                                            sub_coverage,
+                                            line,
+                                            line_height,
+                                            (x_offset * char_width, line_y),
+                                        );
+                                    }
+                                };
+                            }
+                            if ttx.send((out, *num_content_lines, file_index)).is_err() {
+                                break;
+                            }
                         }
                         Ok(())
                     }
@@ -297,63 +1237,130 @@ pub fn render(
             }
             drop(ttx);
 
-            // for each file image that was rendered by a thread.
-            for (sub_img, out, num_content_lines, lines_so_far) in trx {
+            // for each file that was rendered by a thread.
+            let mut rendered_files = 0;
+            for (out, num_content_lines, file_index) in trx {
+                rendered_files += 1;
                 longest_line_chars = out.longest_line_in_chars.max(longest_line_chars);
-                background = out.background;
-
-                let calc_offsets = |line_num: u32| {
-                    let actual_line = line_num % total_line_count;
-                    calc_offsets(actual_line, lines_per_column, column_width, line_height)
-                };
-
-                let mut lines_in_sub_img = num_content_lines as u32;
-                if show_filenames {
-                    lines_in_sub_img += FILENAME_LINE_COUNT;
-                }
-                // transfer pixels from sub_img to img. Where sub_img is a 1 column wide
-                // image of one file. And img is our multi-column wide final output image.
-                for line in 0..lines_in_sub_img {
-                    let (x_offset, line_y) = calc_offsets(lines_so_far + line);
-                    for x in 0..column_width * char_width {
-                        for height in 0..line_height {
-                            let pix = sub_img.get_pixel(x, line * line_height + height);
-                            img.put_pixel(x_offset * char_width + x, line_y + height, *pix);
-                        }
-                    }
+                if last_background.map_or(true, |(last_index, _)| file_index > last_index) {
+                    last_background = Some((file_index, out.background));
                 }
+                remember_background(&mut backgrounds, out.background);
 
                 line_progress.inc_by(num_content_lines);
-                line_num += num_content_lines as u32;
+                // files finish in any order, and lines may be left empty between them.
+                let (_, _, lines_so_far) = content[file_index];
+                let mut file_end =
+                    lines_so_far + uniform_file_height.unwrap_or(num_content_lines as u32);
                 if show_filenames {
-                    line_num += FILENAME_LINE_COUNT
+                    file_end += FILENAME_LINE_COUNT
                 };
+                line_num = line_num.max(file_end);
                 progress.inc();
                 if should_interrupt.load(Ordering::Relaxed) {
-                    bail!("Cancelled by user")
+                    return Err(RenderError::Interrupted);
                 }
             }
+            if rendered_files < content.len() {
+                stopped_after = Some(rendered_files);
+            }
             Ok(())
         })?;
-        (line_num, longest_line_chars, background)
+        let background = last_background.and_then(|(_, background)| background);
+        (line_num, longest_line_chars, background, backgrounds)
     };
 
+    for lines in empty_lines {
+        fill_lines(img, lines, background.unwrap_or(Rgb([0, 0, 0])));
+    }
     // fill in any empty bottom right corner, with background color
-    while line_num < lines_per_column * required_columns {
-        let (cur_column_x_offset, cur_y) =
-            calc_offsets(line_num, lines_per_column, column_width, line_height);
-        let background = background.unwrap_or(Rgb([0, 0, 0]));
-
-        for cur_line_x in 0..column_width * char_width {
-            for y_pos in cur_y..cur_y + line_height {
-                img.put_pixel(
-                    cur_column_x_offset * char_width + cur_line_x,
-                    y_pos,
-                    background,
-                );
+    fill_lines(
+        img,
+        line_num..lines_per_column * required_columns,
+        background.unwrap_or(Rgb([0, 0, 0])),
+    );
+
+    if zebra_columns {
+        // the background of the empty lines is shifted along with those of the files.
+        let mut backgrounds = backgrounds.clone();
+        remember_background(&mut backgrounds, Some(background.unwrap_or(Rgb([0, 0, 0]))));
+        composite::zebra_columns(
+            img,
+            column_width * char_width,
+            &backgrounds,
+            zebra_strength,
+            blend_space,
+        );
+    }
+
+    // mark files with nothing but whitespace in each column they are placed in.
+    if mark_blank_files && !file_summary {
+        for &((_, file_content), num_content_lines, lines_so_far) in &content {
+            if !file_content.trim().is_empty() {
+                continue;
+            }
+            let mut num_lines = uniform_file_height.unwrap_or(num_content_lines as u32);
+            if show_filenames {
+                num_lines += FILENAME_LINE_COUNT;
+            }
+            for rect in chunk::line_rects(
+                lines_so_far,
+                num_lines,
+                lines_per_column,
+                column_width * char_width,
+                line_height,
+                required_columns,
+                rtl_columns,
+            ) {
+                composite::mark_blank_file(img, rect, blend_space);
             }
         }
-        line_num += 1;
+    }
+
+    // draw borders around highlighted files, with one for each column a file is placed in.
+    if !highlight_files.is_empty() {
+        for (file_index, &((path, _), num_content_lines, lines_so_far)) in
+            content.iter().enumerate()
+        {
+            let relative_path = &dir_content.relative_path(path);
+            if !highlight_files.is_match(relative_path) {
+                continue;
+            }
+            let (first_line, num_lines) = if file_summary {
+                (file_index as u32, 1)
+            } else {
+                let mut num_lines = uniform_file_height.unwrap_or(num_content_lines as u32);
+                if show_filenames {
+                    num_lines += FILENAME_LINE_COUNT;
+                }
+                (lines_so_far, num_lines)
+            };
+            for rect in chunk::line_rects(
+                first_line,
+                num_lines,
+                lines_per_column,
+                column_width * char_width,
+                line_height,
+                required_columns,
+                rtl_columns,
+            ) {
+                composite::draw_border(img, rect, highlight_border_width, highlight_color);
+            }
+        }
+    }
+
+    if let Some(rendered_files) = stopped_after {
+        progress.info(format!(
+            "Stopped at the deadline after rendering {rendered_files} of {} files",
+            content.len()
+        ));
+    }
+    if let Some(column_cache) = column_cache {
+        let (hits, lookups) = column_cache.stats();
+        progress.info(format!(
+            "Reused {hits} of {lookups} files from the cache ({:.0}%)",
+            hits as f32 / lookups.max(1) as f32 * 100.0
+        ));
     }
 
     progress.show_throughput(start);
@@ -364,6 +1371,281 @@ pub fn render(
     if num_ignored != 0 {
         progress.info(format!("Ignored {num_ignored} files due to missing syntax",))
     }
+    if display_limit.skipped() != 0 {
+        progress.info(format!(
+            "Skipped displaying {} files to stay within the rate limit",
+            display_limit.skipped()
+        ))
+    }
 
-    Ok(img)
+    Ok(Drawn {
+        background,
+        backgrounds,
+        coverage,
+    })
+}
+
+/// Return `true` if the `deadline` is set and has passed.
+fn is_past(deadline: Option<Instant>) -> bool {
+    deadline.map_or(false, |deadline| Instant::now() >= deadline)
+}
+
+/// A file to render as `((path, content), num_content_lines, lines_so_far)`, with `lines_so_far` being the line
+/// of the layout it starts at.
+pub(crate) type FileEntry<'a> = ((&'a PathBuf, &'a str), usize, u32);
+
+/// The parts of [`Options`] that [`collect_content()`] needs to pick the files to render and order them.
+#[derive(Clone, Copy)]
+pub(crate) struct CollectOptions {
+    pub ignore_files_without_syntax: bool,
+    pub detect_by_content: bool,
+    pub uniform_file_height: Option<u32>,
+    pub show_filenames: bool,
+    pub render_order: Option<RenderOrder>,
+    pub shuffle_seed: Option<u64>,
+}
+
+/// The files to render as returned by [`collect_content()`].
+pub(crate) struct Collected<'a> {
+    /// The files in the order to render them.
+    pub content: Vec<FileEntry<'a>>,
+    /// The amount of lines of all files, without their filenames.
+    pub total_line_count: u32,
+    /// The amount of files left out for not having a syntax.
+    pub num_ignored: usize,
+    /// The amount of files cut off at the uniform file height.
+    pub num_truncated: usize,
+    /// The amount of files left out for being empty.
+    pub num_empty: usize,
+}
+
+/// Collect the files of `dir_content` in the order to render them, along with the amount of lines of all of them,
+/// and the amount of files that were ignored, truncated or skipped for being empty.
+///
+/// Empty files are skipped as they have no lines to show, even if their filename or a uniform file height would
+/// take some.
+pub(crate) fn collect_content<'a>(
+    dir_content: &'a DirContents,
+    ss: &SyntaxSet,
+    CollectOptions {
+        ignore_files_without_syntax,
+        detect_by_content,
+        uniform_file_height,
+        show_filenames,
+        render_order,
+        shuffle_seed,
+    }: CollectOptions,
+) -> Result<Collected<'a>, RenderError> {
+    let mut out = Vec::with_capacity(dir_content.children_content.len());
+    let mut lines = 0;
+    let mut num_ignored = 0;
+    let mut num_truncated = 0;
+    let mut num_empty = 0;
+    let mut lines_so_far = 0u32;
+    let mut files = dir_content.children_content.iter().collect::<Vec<_>>();
+    if let Some(render_order) = render_order {
+        render_order.sort(&mut files);
+    }
+    if let Some(seed) = shuffle_seed {
+        shuffle(&mut files, seed);
+    }
+    for (path, content) in files {
+        if ignore_files_without_syntax
+            && find_syntax(ss, path, content, detect_by_content).is_none()
+        {
+            num_ignored += 1;
+            continue;
+        }
+        if content.is_empty() {
+            num_empty += 1;
+            continue;
+        }
+        let mut content = content.as_str();
+        let mut num_content_lines = content.lines().count();
+        if let Some(height) = uniform_file_height {
+            if num_content_lines > height as usize {
+                content = truncate_lines(content, height as usize);
+                num_content_lines = height as usize;
+                num_truncated += 1;
+            }
+        }
+        let file_lines = uniform_file_height.unwrap_or(num_content_lines as u32);
+        lines += file_lines;
+        out.push(((path, content), num_content_lines, lines_so_far));
+        lines_so_far += file_lines;
+        if show_filenames {
+            lines_so_far += FILENAME_LINE_COUNT;
+        }
+    }
+    Ok(Collected {
+        content: out,
+        total_line_count: lines,
+        num_ignored,
+        num_truncated,
+        num_empty,
+    })
+}
+
+/// Move files of `content` that would be split across columns of `lines_per_column` lines to the top of the next
+/// column, unless they are taller than a column anyway.
+///
+/// Return the ranges of lines that are left empty this way, along with the line after the last file.
+pub(crate) fn keep_files_in_columns(
+    content: &mut [FileEntry<'_>],
+    lines_per_column: u32,
+    show_filenames: bool,
+    uniform_file_height: Option<u32>,
+) -> (Vec<Range<u32>>, u32) {
+    let mut gaps = Vec::new();
+    let mut line = 0;
+    for (_, num_content_lines, lines_so_far) in content {
+        let mut file_lines = uniform_file_height.unwrap_or(*num_content_lines as u32);
+        if show_filenames {
+            file_lines += FILENAME_LINE_COUNT;
+        }
+        let line_in_column = line % lines_per_column;
+        if line_in_column != 0
+            && file_lines <= lines_per_column
+            && line_in_column + file_lines > lines_per_column
+        {
+            let next_column = line - line_in_column + lines_per_column;
+            gaps.push(line..next_column);
+            line = next_column;
+        }
+        *lines_so_far = line;
+        line += file_lines;
+    }
+    (gaps, line)
+}
+
+/// The amount of cells the longest line of `content` takes, including line numbers and indentation, so columns
+/// of that width truncate no line, but at least 1 and at most `max_width`.
+///
+/// In `readable` mode, characters take as many cells as they are wide on a display, like two for most CJK ideographs.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn auto_column_width_for(
+    dir_content: &DirContents,
+    content: &[FileEntry<'_>],
+    max_width: u32,
+    tab_spaces: u32,
+    tab_mode: TabMode,
+    line_nums: bool,
+    show_filenames: bool,
+    indent_by_depth: Option<u32>,
+    readable: bool,
+    strip_leading_indentation: bool,
+) -> u32 {
+    let cells_of = |start: u32, line: &str| {
+        line.chars().fold(start, |cells, chr| match chr {
+            '\t' => cells + tab_mode.cells(cells, tab_spaces.max(1)),
+            '\n' | '\r' => cells,
+            chr if readable => cells + chunk::glyph_cells(chr),
+            _ => cells + 1,
+        })
+    };
+    let mut longest = 0;
+    for ((path, content), _num_content_lines, _lines_so_far) in content {
+        let relative_path = dir_content.relative_path(path);
+        let indent = indent_for(&relative_path, indent_by_depth, u32::MAX);
+        // line numbers are right-aligned to one more than the digits of the last one, and followed by a space.
+        let line_num_cells = if line_nums {
+            format!("{}", content.lines().count()).len() as u32 + 2
+        } else {
+            0
+        };
+        let dedent = if strip_leading_indentation {
+            chunk::common_indent(content).len()
+        } else {
+            0
+        };
+        for line in content.lines() {
+            let line = &line[chunk::indent_to_strip(line.as_bytes(), dedent)..];
+            longest = longest.max(cells_of(indent + line_num_cells, line));
+            if longest >= max_width {
+                return max_width.max(1);
+            }
+        }
+        if show_filenames {
+            longest = longest.max(cells_of(indent, &relative_path.to_string_lossy()));
+        }
+    }
+    longest.clamp(1, max_width.max(1))
+}
+
+/// The cells to leave empty at the start of each line of the file at `relative_path`.
+pub(crate) fn indent_for(
+    relative_path: &Path,
+    indent_by_depth: Option<u32>,
+    column_width: u32,
+) -> u32 {
+    indent_by_depth.map_or(0, |cells_per_level| {
+        let depth = relative_path.components().count().saturating_sub(1) as u32;
+        depth.saturating_mul(cells_per_level).min(column_width / 2)
+    })
+}
+
+/// Add `background` to `backgrounds` if it's not yet contained.
+fn remember_background(backgrounds: &mut Vec<Rgb<u8>>, background: Option<Rgb<u8>>) {
+    if let Some(background) = background {
+        if !backgrounds.contains(&background) {
+            backgrounds.push(background);
+        }
+    }
+}
+
+/// Look up the theme with the given `name`, ignoring case if there is no exact match.
+///
+/// If it doesn't exist, fail with a list of all available themes and the most similar one.
+pub(crate) fn theme_by_name<'a>(ts: &'a ThemeSet, name: &str) -> Result<&'a Theme, RenderError> {
+    if let Some(theme) = ts.themes.get(name) {
+        return Ok(theme);
+    }
+    let lowercase_name = name.to_lowercase();
+    if let Some((_, theme)) = ts
+        .themes
+        .iter()
+        .find(|(candidate, _)| candidate.to_lowercase() == lowercase_name)
+    {
+        return Ok(theme);
+    }
+    Err(RenderError::ThemeNotFound {
+        name: name.to_owned(),
+        suggestion: ts
+            .themes
+            .keys()
+            .map(|candidate| {
+                let similarity = strsim::jaro_winkler(&candidate.to_lowercase(), &lowercase_name);
+                (similarity, candidate)
+            })
+            .filter(|(similarity, _)| *similarity > 0.8)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, candidate)| candidate.to_owned()),
+        available: ts.themes.keys().cloned().collect(),
+    })
+}
+
+/// Compile `globs` into a set matching any of them.
+fn glob_set(globs: &[String]) -> Result<GlobSet, RenderError> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).map_err(|source| RenderError::InvalidGlob {
+            glob: glob.clone(),
+            source,
+        })?);
+    }
+    builder.build().map_err(|source| RenderError::InvalidGlob {
+        glob: globs.join(","),
+        source,
+    })
+}
+
+/// Return the first `num_lines` lines of `content`, including their line terminators.
+fn truncate_lines(content: &str, num_lines: usize) -> &str {
+    match num_lines.checked_sub(1) {
+        Some(last_line) => content
+            .match_indices('\n')
+            .nth(last_line)
+            .map_or(content, |(pos, _)| &content[..=pos]),
+        None => "",
+    }
 }
@@ -0,0 +1,110 @@
+use crate::render::chunk::draw_text;
+use image::{GenericImage, Rgb, RgbImage};
+
+/// The color behind and between the cells of a grid.
+const GRID_BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+/// The color of the label above each cell.
+const LABEL_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+/// The amount of pixels between cells, and around all of them.
+const GAP: u32 = 8;
+/// The height of a label, which is the height of a readable glyph.
+const LABEL_HEIGHT: u32 = 16;
+
+/// Arrange `cells` as `(label, image)` pairs row by row in a grid with about as many columns as rows, with each
+/// image below its label.
+///
+/// All cells are as large as the largest image, with smaller images placed in their top-left corner.
+/// Labels that are wider than their cell are cut off.
+pub fn grid(cells: &[(String, RgbImage)]) -> RgbImage {
+    let columns = (cells.len() as f64).sqrt().ceil() as u32;
+    grid_with_columns(cells, columns)
+}
+
+/// Arrange `cells` like [`grid()`], but in rows of `columns` cells.
+///
+/// If all labels are empty, no space is left for them.
+pub fn grid_with_columns(cells: &[(String, RgbImage)], columns: u32) -> RgbImage {
+    let num_cells = cells.len() as u32;
+    let columns = columns.clamp(1, num_cells.max(1));
+    let rows = num_cells.div_ceil(columns);
+    let label_height = if cells.iter().all(|(label, _)| label.is_empty()) {
+        0
+    } else {
+        LABEL_HEIGHT
+    };
+    let cell_width = cells.iter().map(|(_, img)| img.width()).max().unwrap_or(0);
+    let cell_height = label_height + cells.iter().map(|(_, img)| img.height()).max().unwrap_or(0);
+
+    let mut out = RgbImage::from_pixel(
+        GAP + columns * (cell_width + GAP),
+        GAP + rows * (cell_height + GAP),
+        GRID_BACKGROUND,
+    );
+    for (index, (label, img)) in (0..).zip(cells) {
+        let x = GAP + index % columns * (cell_width + GAP);
+        let y = GAP + index / columns * (cell_height + GAP);
+        draw_text(
+            &mut out,
+            label,
+            (x, y),
+            cell_width,
+            LABEL_COLOR,
+            GRID_BACKGROUND,
+        );
+        out.copy_from(img, x, y + label_height)
+            .expect("cells fit into the grid");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_places_labeled_images_in_rows() {
+        let red = image::Rgb([255, 0, 0]);
+        let cells = ["a", "b", "c"]
+            .map(|label| (label.to_owned(), image::RgbImage::from_pixel(20, 10, red)));
+        let grid = grid(&cells);
+        assert_eq!(
+            grid.dimensions(),
+            (8 + 2 * (20 + 8), 8 + 2 * (16 + 10 + 8)),
+            "three cells take two columns and two rows"
+        );
+        for (x, y) in [(8, 8), (8 + 28, 8), (8, 8 + 34)] {
+            assert_ne!(
+                *grid.get_pixel(x, y + 16),
+                *grid.get_pixel(x + 20, y + 16),
+                "each image is below its label"
+            );
+            assert_eq!(*grid.get_pixel(x, y + 16), red);
+            assert!(
+                (0..20).any(|dx| (0..16)
+                    .any(|dy| *grid.get_pixel(x + dx, y + dy) == image::Rgb([255, 255, 255]))),
+                "each image has a label"
+            );
+        }
+    }
+
+    #[test]
+    fn grid_with_columns_fills_rows_of_that_many_cells() {
+        let red = image::Rgb([255, 0, 0]);
+        let unlabeled =
+            ["", "", ""].map(|label| (label.to_owned(), image::RgbImage::from_pixel(20, 10, red)));
+        let grid = grid_with_columns(&unlabeled, 3);
+        assert_eq!(
+            grid.dimensions(),
+            (8 + 3 * (20 + 8), 8 + 10 + 8),
+            "a single row without space for labels"
+        );
+        assert_eq!(*grid.get_pixel(8 + 2 * 28, 8), red);
+
+        let labeled = [("a".to_owned(), image::RgbImage::from_pixel(20, 10, red))];
+        assert_eq!(
+            grid_with_columns(&labeled, 4).dimensions(),
+            (8 + 20 + 8, 8 + 16 + 10 + 8),
+            "no more columns than cells"
+        );
+    }
+}
@@ -1,9 +1,21 @@
+use crate::render::Only;
+use std::ops::Range;
 use std::path::Path;
+use syntect::easy::ScopeRegionIterator;
+use syntect::highlighting::{HighlightState, RangedHighlightIterator, Style, Theme};
+use syntect::parsing::{ParseState, Scope, ScopeStack, ScopeStackOp, SyntaxReference, SyntaxSet};
 
 pub(crate) struct Cache<'syntax, 'theme> {
     syntax: &'syntax syntect::parsing::SyntaxSet,
     theme: &'theme syntect::highlighting::Theme,
-    prev_syntax: usize,
+    /// Themes to use instead of `theme` for files with the given extension.
+    themes_by_extension: Vec<(String, &'theme syntect::highlighting::Theme)>,
+    /// How visible comments are, from 0.0 for invisible to 1.0 for unchanged.
+    comment_opacity: f32,
+    /// If set, the only part of the code that is visible.
+    only: Option<Only>,
+    /// Whether to find the syntax of files by their first line if their name doesn't tell.
+    detect_by_content: bool,
 }
 
 impl<'a, 'b> Clone for Cache<'a, 'b> {
@@ -11,44 +23,199 @@ impl<'a, 'b> Clone for Cache<'a, 'b> {
         Cache {
             syntax: self.syntax,
             theme: self.theme,
-            prev_syntax: self.prev_syntax,
+            themes_by_extension: self.themes_by_extension.clone(),
+            comment_opacity: self.comment_opacity,
+            only: self.only,
+            detect_by_content: self.detect_by_content,
         }
     }
 }
 
 impl<'syntax, 'theme> Cache<'syntax, 'theme> {
-    pub fn new_with_plain_highlighter(
+    pub fn new(
         syntax: &'syntax syntect::parsing::SyntaxSet,
         theme: &'theme syntect::highlighting::Theme,
+        themes_by_extension: Vec<(String, &'theme syntect::highlighting::Theme)>,
+        comment_opacity: f32,
+        only: Option<Only>,
+        detect_by_content: bool,
     ) -> Self {
-        let plain = syntax.find_syntax_plain_text();
         Cache {
             syntax,
             theme,
-            prev_syntax: plain as *const _ as usize,
+            themes_by_extension,
+            comment_opacity,
+            only,
+            detect_by_content,
         }
     }
 
-    pub fn new_plain_highlighter(&self) -> syntect::easy::HighlightLines<'theme> {
-        syntect::easy::HighlightLines::new(self.syntax.find_syntax_plain_text(), self.theme)
+    pub fn new_plain_highlighter(&self) -> Highlighter<'theme> {
+        // Plain text has no comments to fade.
+        Highlighter::new(self.syntax.find_syntax_plain_text(), self.theme, 1.0, None)
     }
 }
 
 impl<'syntax, 'theme> Cache<'syntax, 'theme> {
-    pub fn highlighter_for_file_name(
-        &mut self,
-        path: &Path,
-    ) -> std::io::Result<Option<syntect::easy::HighlightLines<'theme>>> {
-        let syntax = self
-            .syntax
-            .find_syntax_for_file(path)
-            .unwrap()
+    /// Return a new highlighter for the file at `path` with `content`.
+    ///
+    /// Highlighters carry parse state from line to line, so each file needs its own to not be affected
+    /// by unterminated constructs, like comments, at the end of the previous file.
+    pub fn highlighter_for_file(&self, path: &Path, content: &str) -> Highlighter<'theme> {
+        let syntax = find_syntax(self.syntax, path, content, self.detect_by_content)
             .unwrap_or_else(|| self.syntax.find_syntax_plain_text());
-        if syntax as *const _ as usize != self.prev_syntax {
-            self.prev_syntax = syntax as *const _ as usize;
-            Ok(Some(syntect::easy::HighlightLines::new(syntax, self.theme)))
+        Highlighter::new(
+            syntax,
+            self.theme_for_file_name(path),
+            self.comment_opacity,
+            self.only,
+        )
+    }
+
+    /// Return the theme configured for the extension of `path`, or the default theme.
+    fn theme_for_file_name(&self, path: &Path) -> &'theme syntect::highlighting::Theme {
+        path.extension()
+            .and_then(|ext| {
+                self.themes_by_extension
+                    .iter()
+                    .find_map(|(extension, theme)| (ext == extension.as_str()).then_some(*theme))
+            })
+            .unwrap_or(self.theme)
+    }
+}
+
+/// Find the syntax of the file at `path` by its name or extension, or by the first line of its `content` if
+/// `detect_by_content` is true, like [`SyntaxSet::find_syntax_for_file()`] but without reading the file, which may
+/// not even exist.
+pub fn find_syntax<'a>(
+    syntax_set: &'a SyntaxSet,
+    path: &Path,
+    content: &str,
+    detect_by_content: bool,
+) -> Option<&'a SyntaxReference> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    syntax_set
+        .find_syntax_by_extension(file_name)
+        .or_else(|| syntax_set.find_syntax_by_extension(extension))
+        .or_else(|| {
+            detect_by_content
+                .then(|| syntax_set.find_syntax_by_first_line(content.lines().next()?))
+                .flatten()
+        })
+}
+
+/// Highlights the lines of a single file like [`syntect::easy::HighlightLines`], but can fade comments, or
+/// everything but comments, into the background.
+pub(crate) struct Highlighter<'theme> {
+    highlighter: syntect::highlighting::Highlighter<'theme>,
+    parse_state: ParseState,
+    highlight_state: HighlightState,
+    comment_opacity: f32,
+    only: Option<Only>,
+}
+
+impl<'theme> Highlighter<'theme> {
+    fn new(
+        syntax: &SyntaxReference,
+        theme: &'theme Theme,
+        comment_opacity: f32,
+        only: Option<Only>,
+    ) -> Self {
+        let highlighter = syntect::highlighting::Highlighter::new(theme);
+        let highlight_state = HighlightState::new(&highlighter, ScopeStack::new());
+        Highlighter {
+            highlighter,
+            parse_state: ParseState::new(syntax),
+            highlight_state,
+            comment_opacity,
+            only,
+        }
+    }
+
+    /// Highlight the next line of the file.
+    ///
+    /// Foregrounds are opaque unless they were faded, so the alpha channel of colors in the theme itself is ignored.
+    pub fn highlight_line<'line>(
+        &mut self,
+        line: &'line str,
+        syntax_set: &SyntaxSet,
+    ) -> Result<Vec<(Style, &'line str)>, syntect::Error> {
+        let ops = self.parse_state.parse_line(line, syntax_set)?;
+        let comments = if self.comment_opacity < 1.0 || self.only.is_some() {
+            comment_ranges(&self.highlight_state.path, &ops, line)?
         } else {
-            Ok(None)
+            Vec::new()
+        };
+        Ok(
+            RangedHighlightIterator::new(&mut self.highlight_state, &ops, line, &self.highlighter)
+                .map(|(style, region, range)| {
+                    let is_comment = comments
+                        .iter()
+                        .any(|comment| comment.contains(&range.start));
+                    let opacity = match (self.only, is_comment) {
+                        (Some(Only::Code), true) | (Some(Only::Comments), false) => 0.0,
+                        (_, true) => self.comment_opacity,
+                        (_, false) => 1.0,
+                    };
+                    (fade(style, opacity), region)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Return the byte ranges of `line` that are within a comment, starting with the scopes in `stack`.
+fn comment_ranges(
+    stack: &ScopeStack,
+    ops: &[(usize, ScopeStackOp)],
+    line: &str,
+) -> Result<Vec<Range<usize>>, syntect::Error> {
+    let comment = Scope::new("comment").expect("valid scope");
+    let mut stack = stack.clone();
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for (region, op) in ScopeRegionIterator::new(ops, line) {
+        stack.apply(op)?;
+        if stack
+            .as_slice()
+            .iter()
+            .any(|scope| comment.is_prefix_of(*scope))
+        {
+            ranges.push(start..start + region.len());
         }
+        start += region.len();
+    }
+    Ok(ranges)
+}
+
+/// Make the foreground of `style` translucent, keeping `opacity` of it, so it's blended into the background when drawn.
+fn fade(mut style: Style, opacity: f32) -> Style {
+    style.foreground.a = (u8::MAX as f32 * opacity).round() as u8;
+    style
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syntaxes_are_found_by_name_extension_or_first_line() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let name_of = |path: &str, content: &str, detect_by_content| {
+            find_syntax(&syntax_set, Path::new(path), content, detect_by_content)
+                .map(|syntax| syntax.name.as_str())
+        };
+        assert_eq!(name_of("src/lib.rs", "", true), Some("Rust"));
+        assert_eq!(name_of("Makefile", "all:\n", true), Some("Makefile"));
+        assert_eq!(
+            name_of("script", "#!/usr/bin/env python\n", true),
+            Some("Python")
+        );
+        assert_eq!(name_of("script", "#!/usr/bin/env python\n", false), None);
+        assert_eq!(name_of("notes", "just text\n", true), None);
     }
 }
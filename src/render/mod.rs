@@ -1,17 +1,23 @@
-use image::Rgb;
+use image::{Rgb, RgbImage};
+use std::path::{Path, PathBuf};
 use syntect::highlighting::Style;
 
 /// Determine the foreground pixel color.
-#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum FgColor {
     /// Use the style of the syntax to color the foreground pixel.
     Style,
     /// Encode the ascii value into the brightness of the style color
     StyleAsciiBrightness,
+    /// Use the foreground color of plain text in the theme for all glyphs, regardless of syntax.
+    ThemeDefault,
+    /// Use the given color for all glyphs, regardless of syntax.
+    #[value(skip)]
+    Fixed(Rgb<u8>),
 }
 
 /// Determine the background pixel color.
-#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum BgColor {
     /// Use the style of the syntax to color the background pixel.
     Style,
@@ -52,10 +58,156 @@ impl BgColor {
     }
 }
 
+/// Determine how a background image is fitted to the rendered image.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BgFit {
+    /// Scale the background image to cover the whole image, cutting off what doesn't fit.
+    Cover,
+    /// Scale the background image to fit into the image, leaving the uncovered parts as they are.
+    Contain,
+    /// Stretch the background image to the size of the image, ignoring its aspect ratio.
+    Stretch,
+    /// Repeat the background image at its original size.
+    Tile,
+}
+
+/// The part of the code to show, with everything else blended into the background.
+///
+/// Comments are the regions within a `comment` scope of the syntax, like `comment.line.double-slash`, no matter what
+/// the theme makes of them.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Only {
+    /// Show everything but comments.
+    Code,
+    /// Show only comments.
+    Comments,
+}
+
+/// Determine how tabs are drawn.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum TabMode {
+    /// Expand tabs with blank cells up to the next multiple of the tab width.
+    Expand,
+    /// Draw tabs as an arrow in a single cell.
+    Glyph,
+    /// Draw tabs as a single blank cell, like a space.
+    Single,
+}
+
+impl TabMode {
+    /// The amount of cells a tab at cell `column` of a line takes, with tab stops every `tab_spaces` cells.
+    pub fn cells(self, column: u32, tab_spaces: u32) -> u32 {
+        match self {
+            TabMode::Expand => tab_spaces - column % tab_spaces,
+            TabMode::Glyph | TabMode::Single => 1,
+        }
+    }
+}
+
+/// Determine in which color space colors are blended.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum BlendSpace {
+    /// Blend the sRGB encoded channels as they are, which makes mixed colors look darker than they should.
+    Srgb,
+    /// Blend in linear light, converting from and to sRGB, which mixes colors like light does.
+    Linear,
+}
+
+impl BlendSpace {
+    /// Mix `amount` of `to` into `from`, with 0.0 keeping `from` and 1.0 resulting in `to`.
+    pub fn mix(self, from: Rgb<u8>, to: Rgb<u8>, amount: f32) -> Rgb<u8> {
+        let mix = |from: f32, to: f32| from + (to - from) * amount;
+        Rgb([0, 1, 2].map(|c| match self {
+            BlendSpace::Srgb => mix(from[c] as f32, to[c] as f32).round() as u8,
+            BlendSpace::Linear => to_srgb(mix(to_linear(from[c]), to_linear(to[c]))),
+        }))
+    }
+}
+
+/// Convert an sRGB encoded channel to linear light between 0.0 and 1.0.
+fn to_linear(channel: u8) -> f32 {
+    let channel = channel as f32 / 255.0;
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert linear light between 0.0 and 1.0 to an sRGB encoded channel.
+fn to_srgb(linear: f32) -> u8 {
+    let linear = linear.clamp(0.0, 1.0);
+    let channel = if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (channel * 255.0).round() as u8
+}
+
+/// Determine where the rendered image is placed when padding it to an exact aspect ratio.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Pad {
+    /// Center the image, padding it evenly on both sides.
+    Center,
+    /// Keep the image in the top left corner, padding it on the bottom and right.
+    BottomRight,
+}
+
+/// Determine in which corner of the image to show the amount of files and lines, if at all.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Badge {
+    /// Show no badge.
+    None,
+    /// Show the badge in the top left corner.
+    TopLeft,
+    /// Show the badge in the top right corner.
+    TopRight,
+    /// Show the badge in the bottom left corner.
+    BottomLeft,
+    /// Show the badge in the bottom right corner.
+    BottomRight,
+}
+
+/// Determine the order in which files are rendered, and thus placed in the image.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RenderOrder {
+    /// Keep the files of each directory together, with files of a directory coming before those of its subdirectories.
+    Directory,
+    /// Put the largest files first.
+    SizeDesc,
+    /// Put the smallest files first.
+    SizeAsc,
+}
+
+impl RenderOrder {
+    /// Sort `files` as `(path, content)` pairs in this order, keeping the given order of files that compare equal.
+    pub fn sort(&self, files: &mut [&(PathBuf, String)]) {
+        match self {
+            RenderOrder::Directory => files.sort_by(|(a, _), (b, _)| {
+                (a.parent(), a.file_name()).cmp(&(b.parent(), b.file_name()))
+            }),
+            RenderOrder::SizeDesc => {
+                files.sort_by_key(|(_, content)| std::cmp::Reverse(content.len()))
+            }
+            RenderOrder::SizeAsc => files.sort_by_key(|(_, content)| content.len()),
+        }
+    }
+}
+
+/// A rectangular region of an image, in pixels.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
 /// Configure how to render an image.
 #[derive(Debug, Copy, Clone)]
 pub struct Options<'a> {
-    /// How many characters wide each column is.
+    /// How many characters wide each column is, with each character being a pixel wide, or 8 pixels if `readable`.
     pub column_width: u32,
     /// How many pixels high each line is.
     pub line_height: u32,
@@ -63,6 +215,9 @@ pub struct Options<'a> {
     pub readable: bool,
 
     /// Whether or not to write the file path and name at the top of each file.
+    ///
+    /// The path is the one returned by [`DirContents::relative_path()`](crate::DirContents::relative_path()),
+    /// so `./src/lib.rs` is written as `src/lib.rs`.
     pub show_filenames: bool,
 
     pub target_aspect_ratio: f64,
@@ -75,8 +230,15 @@ pub struct Options<'a> {
     pub bg_color: BgColor,
     /// The color theme to use.
     pub theme: &'a str,
+    /// Pairs of `(extension, theme)` to use a different theme for files with the given extension.
+    pub theme_for_extension: &'a [(String, String)],
 
     /// Sacrifice aspect ratio to fill the image with full columns.
+    ///
+    /// Columns are then no taller than needed to hold all lines, with `lines_per_column` being the amount of lines
+    /// divided by the amount of columns, rounded up, so the last column lacks fewer lines than there are columns.
+    /// Otherwise columns may be taller if that is closer to `target_aspect_ratio`, leaving more of the last column
+    /// empty. No column is ever entirely empty.
     pub force_full_columns: bool,
     /// Whether to ignore files without syntactic highlighting.
     pub ignore_files_without_syntax: bool,
@@ -86,6 +248,151 @@ pub struct Options<'a> {
     /// The number of spaces to use for a tab character.
     pub tab_spaces: u32,
     pub line_nums: bool,
+    /// Whether to drop control characters instead of rendering them as a placeholder glyph.
+    pub strip_control: bool,
+    /// An image to show through all pixels that were drawn with a background color.
+    pub background_image: Option<&'a RgbImage>,
+    /// How to fit the `background_image` to the rendered image.
+    pub bg_fit: BgFit,
+    /// Whether to convert the image to grayscale, keeping the brightness of each color.
+    pub grayscale: bool,
+    /// Whether to fill columns from right to left, placing the first file in the rightmost column.
+    pub rtl_columns: bool,
+    /// A directory in which to keep the rendered images of individual files, to reuse them if neither
+    /// the files nor the options affecting their appearance change.
+    ///
+    /// It's not used with a `background_image`, which needs to know which pixels are glyphs.
+    pub cache_dir: Option<&'a Path>,
+    /// The width of the image in pixels, which overrides `target_aspect_ratio` if set.
+    /// It is rounded down to a multiple of the column width.
+    pub target_width: Option<u32>,
+    /// Whether to draw a single line per file, with a bar whose length is proportional to its amount of lines.
+    pub file_summary: bool,
+    /// The maximum amount of columns, which makes the image taller than the aspect ratio or width demands if needed.
+    pub max_columns: Option<u32>,
+    /// Whether to paint spaces and tabs at the end of a line in a warning color instead of the background color.
+    pub highlight_trailing_whitespace: bool,
+    /// If set, shade the characters of a line past this many characters increasingly towards orange, to make long lines stand out.
+    pub length_gradient: Option<u32>,
+    /// If set, the time spent determining dimensions and processing lines is added to it.
+    pub phase_timer: Option<&'a crate::PhaseTimer>,
+    /// The color behind glyphs if `fg_color` is `FgColor::StyleAsciiBrightness`, instead of the one derived from `bg_color`.
+    pub canvas_color: Option<Rgb<u8>>,
+    /// Whether to pad the image with the background color to exactly match `target_aspect_ratio`.
+    pub exact_aspect: bool,
+    /// Where to place the image within the padding added by `exact_aspect`.
+    pub pad: Pad,
+    /// If set, every file takes exactly this many lines, truncating longer files and padding shorter ones with their background.
+    pub uniform_file_height: Option<u32>,
+    /// If set, indent every line of a file by this many cells per directory it's nested in, up to half the column width.
+    pub indent_by_depth: Option<u32>,
+    /// If set, the order to render files in instead of the order they are given in.
+    pub render_order: Option<RenderOrder>,
+    /// How visible comments are, from 0.0 for blending them into the background to 1.0 for leaving them unchanged.
+    pub comment_opacity: f32,
+    /// If set, only show code or only comments, blending the other into the background like `comment_opacity` does.
+    /// Ignored with `plain`, which doesn't know about comments.
+    pub only: Option<Only>,
+    /// The character to draw in `readable` mode in place of characters the font has no glyph for, if set.
+    pub missing_glyph: Option<char>,
+    /// If set, add bands of this color around the finished image to exactly match `target_aspect_ratio`,
+    /// if its aspect ratio is more than 5% off.
+    pub letterbox_color: Option<Rgb<u8>>,
+    /// Globs matching paths relative to the parent directory of files to draw a border around.
+    pub highlight_files: &'a [String],
+    /// The color of the border around files matching `highlight_files`.
+    pub highlight_color: Rgb<u8>,
+    /// How many pixels wide the border around files matching `highlight_files` is.
+    pub highlight_border_width: u32,
+    /// If set, reduce the line height until the image takes at most this many bytes, failing if even a line height of 1 is too much.
+    pub max_memory: Option<u64>,
+    /// If set, shuffle the files with this seed after putting them in `render_order`, with the same seed always giving the same order.
+    pub shuffle_seed: Option<u64>,
+    /// How many blank pixel rows to leave below each line, in addition to `line_height`.
+    pub line_spacing: u32,
+    /// If set, display at most this many files per second with `display_to_be_processed_file`, skipping the others.
+    pub display_rate_limit: Option<u32>,
+    /// If set, draw everything in this `(foreground, background)` pair of colors instead of the colors of the theme, for a monochrome look.
+    pub mono: Option<(Rgb<u8>, Rgb<u8>)>,
+    /// If set, ignore `column_width` and make columns as wide as the longest line, including line numbers and indentation, but at most this many characters.
+    pub auto_column_width: Option<u32>,
+    /// If set, tint lines matching this expression towards `grep_color`.
+    pub grep: Option<&'a regex::Regex>,
+    /// The color that lines matching `grep` are tinted towards.
+    pub grep_color: Rgb<u8>,
+    /// How strongly lines matching `grep` are tinted, from 0.0 for not at all to 1.0 for replacing their colors.
+    pub grep_strength: f32,
+    /// If set, make columns at least this many lines tall, using fewer columns than the aspect ratio or width demand if needed.
+    pub min_column_lines: Option<u32>,
+    /// How much brighter than the style color glyphs get with `FgColor::StyleAsciiBrightness`, with higher ASCII values being brighter.
+    pub fg_boost: f32,
+    /// How many pixels wide the border in `border_color` around the finished image is, which makes the image larger.
+    pub border: u32,
+    /// The color of the border around the finished image.
+    pub border_color: Rgb<u8>,
+    /// How many pixels wide the soft shadow around the finished image is, which is drawn on `shadow_backdrop` and makes the image larger.
+    pub shadow: u32,
+    /// The color that the shadow is drawn on, as images have no transparency.
+    pub shadow_backdrop: Rgb<u8>,
+    /// The corner in which to show the amount of files and lines that were rendered.
+    pub badge: Badge,
+    /// Whether to start files that don't fit into the rest of a column at the top of the next one, leaving the rest empty, so files are only split across columns if they are taller than a column. It has no effect with `file_summary`.
+    pub no_split_files: bool,
+    /// Whether to remove the leading whitespace that all non-blank lines of a file have in common, keeping their relative indentation, so more of each line fits into a column.
+    pub strip_leading_indentation: bool,
+    /// The color space in which to blend colors, like when fading comments, tinting lines matching `grep` or shading long lines.
+    pub blend_space: BlendSpace,
+    /// How tabs are drawn, either expanded to the next multiple of `tab_spaces` cells, as an arrow glyph in a single cell, or as a single space.
+    pub tab_mode: TabMode,
+    /// How many pixels far bright colors glow into their surroundings, like syntax tokens on a dark background, or 0 to not let them glow.
+    pub glow_radius: u32,
+    /// How strongly the glow of `glow_radius` is added to the image, with 1.0 adding the blurred bright colors in full.
+    pub glow_intensity: f32,
+    /// If true, all files get the background of the first file in the default theme, instead of one derived from their
+    /// own style, so only their foreground varies.
+    pub uniform_background: bool,
+    /// If set, tint added lines green and modified lines yellow, as looked up by the path of their file relative to
+    /// the input directory. Ignored with `file_summary`.
+    pub line_changes: Option<&'a crate::LineChanges>,
+    /// If set, tint lines in the color of their author, as looked up by the path of their file relative to the input
+    /// directory. Ignored with `file_summary`.
+    pub line_authors: Option<&'a crate::LineAuthors>,
+    /// If true, files whose name or extension has no known syntax are highlighted by the syntax their first line
+    /// suggests, like a `#!/usr/bin/env python` shebang.
+    pub detect_by_content: bool,
+    /// If true, cut off pixel columns at the right of the image that only show the background, like those of a last
+    /// column that isn't full. Ignored with `exact_aspect`.
+    pub exclude_empty_columns: bool,
+    /// Pairs of `(extension, column_width)` to render the files with one of these extensions, without leading dot,
+    /// in columns of a different width.
+    ///
+    /// Each width is rendered as its own image, with files of other extensions first, and these are placed next to each other
+    /// with the bottom of shorter ones filled with their background. Their target aspect ratio or width is split
+    /// by how many lines and characters they show. Ignored by [`render_in_bands()`](crate::render_in_bands()).
+    pub width_for_extension: &'a [(String, u32)],
+    /// The color spaces and tabs at the end of lines are painted in with `highlight_trailing_whitespace`.
+    pub trailing_whitespace_color: Rgb<u8>,
+    /// The color that characters of long lines are shaded towards with `length_gradient`.
+    pub length_gradient_color: Rgb<u8>,
+    /// How much of `length_gradient_color` is mixed in at the end of a column, from 0.0 to 1.0.
+    pub length_gradient_strength: f32,
+    /// If true, surround the glyphs of `readable` text with a 1px outline in `glyph_outline_color`, so they stay legible
+    /// on backgrounds of a similar color.
+    pub glyph_outline: bool,
+    /// The color of the outline of `glyph_outline`, or `None` to darken the background around glyphs instead.
+    pub glyph_outline_color: Option<Rgb<u8>>,
+    /// If set, stop rendering files once this instant has passed, and leave the lines of the files that weren't
+    /// rendered blank like the empty end of the last column, so the image keeps its size. Ignored with `file_summary`.
+    pub deadline: Option<std::time::Instant>,
+    /// If true, shift the brightness of the background in every other column by `zebra_strength`, to tell columns apart
+    /// without gaps between them.
+    pub zebra_columns: bool,
+    /// How much the background of every other column is shifted towards white if it's dark, or black if it's bright,
+    /// with `zebra_columns`, from 0.0 to 1.0.
+    pub zebra_strength: f32,
+    /// If true, draw a thin line along the left edge of files with nothing but whitespace, so they can be told apart
+    /// from the empty end of the last column. Ignored with `file_summary`.
+    pub mark_blank_files: bool,
 }
 
 impl Default for Options<'_> {
@@ -101,6 +408,7 @@ impl Default for Options<'_> {
             fg_color: FgColor::StyleAsciiBrightness,
             bg_color: BgColor::Style,
             theme: "Solarized (dark)",
+            theme_for_extension: &[],
             force_full_columns: true,
             ignore_files_without_syntax: false,
             plain: false,
@@ -108,16 +416,133 @@ impl Default for Options<'_> {
             color_modulation: 0.3,
             tab_spaces: 4,
             line_nums: false,
+            strip_control: false,
+            background_image: None,
+            bg_fit: BgFit::Cover,
+            grayscale: false,
+            rtl_columns: false,
+            cache_dir: None,
+            target_width: None,
+            file_summary: false,
+            max_columns: None,
+            highlight_trailing_whitespace: false,
+            length_gradient: None,
+            phase_timer: None,
+            canvas_color: None,
+            exact_aspect: false,
+            pad: Pad::Center,
+            uniform_file_height: None,
+            indent_by_depth: None,
+            render_order: None,
+            comment_opacity: 1.0,
+            only: None,
+            missing_glyph: None,
+            letterbox_color: None,
+            highlight_files: &[],
+            highlight_color: Rgb([255, 0, 0]),
+            highlight_border_width: 2,
+            max_memory: None,
+            shuffle_seed: None,
+            line_spacing: 0,
+            display_rate_limit: None,
+            mono: None,
+            auto_column_width: None,
+            grep: None,
+            grep_color: Rgb([255, 215, 0]),
+            grep_strength: 0.5,
+            min_column_lines: None,
+            fg_boost: 2.4,
+            border: 0,
+            border_color: Rgb([255, 255, 255]),
+            shadow: 0,
+            shadow_backdrop: Rgb([255, 255, 255]),
+            badge: Badge::None,
+            no_split_files: false,
+            strip_leading_indentation: false,
+            blend_space: BlendSpace::Linear,
+            tab_mode: TabMode::Expand,
+            glow_radius: 0,
+            glow_intensity: 0.8,
+            uniform_background: false,
+            line_changes: None,
+            line_authors: None,
+            detect_by_content: true,
+            exclude_empty_columns: false,
+            width_for_extension: &[],
+            trailing_whitespace_color: Rgb([255, 0, 0]),
+            length_gradient_color: Rgb([255, 140, 0]),
+            length_gradient_strength: 0.7,
+            glyph_outline: false,
+            glyph_outline_color: None,
+            deadline: None,
+            zebra_columns: false,
+            zebra_strength: 0.08,
+            mark_blank_files: false,
         }
     }
 }
 
 mod highlight;
+pub use highlight::find_syntax;
 use highlight::Cache;
 
 pub(crate) mod function;
 
+pub(crate) mod band;
+
 mod chunk;
+pub(crate) use chunk::is_stray_control_char;
 
 mod dimension;
 use dimension::Dimension;
+
+mod crop;
+pub use crop::{crop, tiles};
+
+mod composite;
+
+mod column_cache;
+
+mod stitch;
+
+mod rate_limit;
+use rate_limit::RateLimit;
+
+mod grid;
+pub use grid::{grid, grid_with_columns};
+
+mod downscale;
+pub use downscale::{downscale, DownscaleFilter};
+
+mod palette;
+pub use palette::palette;
+
+mod shuffle;
+pub(crate) use shuffle::shuffle;
+
+mod summary;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blending_in_linear_light_keeps_midtones_bright() {
+        let (black, white) = (image::Rgb([0, 0, 0]), image::Rgb([255, 255, 255]));
+        assert_eq!(
+            BlendSpace::Linear.mix(black, white, 0.5),
+            image::Rgb([188, 188, 188])
+        );
+        assert_eq!(
+            BlendSpace::Srgb.mix(black, white, 0.5),
+            image::Rgb([128, 128, 128])
+        );
+        for space in [BlendSpace::Linear, BlendSpace::Srgb] {
+            for channel in [0, 1, 17, 128, 254, 255] {
+                let color = image::Rgb([channel, 255 - channel, channel / 2]);
+                assert_eq!(space.mix(color, white, 0.0), color, "{space:?}");
+                assert_eq!(space.mix(black, color, 1.0), color, "{space:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use image::{ImageBuffer, Pixel, Rgb};
+use std::collections::HashMap;
+use std::ops::Deref;
+
+/// Count how many pixels of `img` have each of its colors, and return the colors along with their count,
+/// the most common first.
+///
+/// Colors with the same count are ordered by their value, so the result is the same for the same image.
+pub fn palette<C>(img: &ImageBuffer<Rgb<u8>, C>) -> Vec<(Rgb<u8>, u64)>
+where
+    C: Deref<Target = [u8]>,
+{
+    let mut counts = HashMap::<[u8; 3], u64>::new();
+    let buf: &[u8] = img;
+    for pixel in buf.chunks_exact(Rgb::<u8>::CHANNEL_COUNT as usize) {
+        *counts.entry([pixel[0], pixel[1], pixel[2]]).or_default() += 1;
+    }
+    let mut colors = counts
+        .into_iter()
+        .map(|(color, count)| (Rgb(color), count))
+        .collect::<Vec<_>>();
+    colors.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.0.cmp(&b.0)));
+    colors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_counts_pixels_of_each_color_most_common_first() {
+        let mut img = image::RgbImage::from_pixel(4, 3, image::Rgb([0, 0, 0]));
+        img.put_pixel(0, 0, image::Rgb([255, 0, 0]));
+        img.put_pixel(1, 0, image::Rgb([0, 0, 255]));
+        img.put_pixel(2, 0, image::Rgb([0, 0, 255]));
+        img.put_pixel(3, 0, image::Rgb([0, 255, 0]));
+
+        assert_eq!(
+            palette(&img),
+            vec![
+                (image::Rgb([0, 0, 0]), 8),
+                (image::Rgb([0, 0, 255]), 2),
+                (image::Rgb([0, 255, 0]), 1),
+                (image::Rgb([255, 0, 0]), 1),
+            ],
+            "colors with the same count are ordered by value"
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Let at most a given amount of messages per second through, shared by all rendering threads.
+pub(crate) struct RateLimit {
+    interval: Duration,
+    /// The earliest time at which the next message may be let through.
+    next: Mutex<Option<Instant>>,
+    skipped: AtomicUsize,
+}
+
+impl RateLimit {
+    /// Let at most `per_second` messages through, or all of them if `None`.
+    pub fn new(per_second: Option<u32>) -> Self {
+        RateLimit {
+            interval: per_second
+                .filter(|per_second| *per_second != 0)
+                .map_or(Duration::ZERO, |per_second| {
+                    Duration::from_secs(1) / per_second
+                }),
+            next: Mutex::new(None),
+            skipped: AtomicUsize::default(),
+        }
+    }
+
+    /// Return `true` if a message may be emitted now, or count it as skipped otherwise.
+    pub fn allow(&self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    /// Like [`allow()`](Self::allow()), but as if it was `now`.
+    fn allow_at(&self, now: Instant) -> bool {
+        if self.interval.is_zero() {
+            return true;
+        }
+        let mut next = self.next.lock().expect("no panics while holding the lock");
+        if next.map_or(true, |next| now >= next) {
+            *next = Some(now + self.interval);
+            true
+        } else {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    /// The amount of messages that weren't let through.
+    pub fn skipped(&self) -> usize {
+        self.skipped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_let_through_once_per_interval() {
+        let limit = RateLimit::new(Some(4));
+        let start = Instant::now();
+        let at = |millis: u64| limit.allow_at(start + Duration::from_millis(millis));
+        assert!(at(0), "the first message is always let through");
+        assert!(!at(0));
+        assert!(!at(249));
+        assert!(at(250), "a quarter second later, the next one may pass");
+        assert!(!at(300));
+        assert!(
+            at(1000),
+            "the interval starts at the last message that was let through"
+        );
+        assert!(!at(1249));
+        assert_eq!(limit.skipped(), 4);
+    }
+
+    #[test]
+    fn no_or_zero_rate_lets_everything_through() {
+        for limit in [RateLimit::new(None), RateLimit::new(Some(0))] {
+            let now = Instant::now();
+            assert!((0..10).all(|_| limit.allow_at(now)));
+            assert_eq!(limit.skipped(), 0);
+        }
+    }
+}
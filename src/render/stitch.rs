@@ -0,0 +1,74 @@
+use image::{ImageBuffer, Pixel};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+/// The final image, shared by all rendering threads to write the lines of the files they rendered into it directly.
+///
+/// Each line of the layout is a cell of its own in one of the columns, so threads that write the lines
+/// of different files never write to the same pixels.
+pub(crate) struct SharedImage<'a, P> {
+    pixels: *mut u8,
+    width: u32,
+    height: u32,
+    /// The image is borrowed mutably for as long as it's shared, so nobody else can access it.
+    _img: PhantomData<&'a mut [P]>,
+}
+
+// SAFETY: the image is only written through `write_line()`, whose callers guarantee that no two threads write
+// to the same pixels.
+unsafe impl<P> Send for SharedImage<'_, P> {}
+unsafe impl<P> Sync for SharedImage<'_, P> {}
+
+impl<'a, P> SharedImage<'a, P>
+where
+    P: Pixel<Subpixel = u8>,
+{
+    pub fn new<C>(img: &'a mut ImageBuffer<P, C>) -> Self
+    where
+        C: Deref<Target = [u8]>,
+        C: DerefMut,
+    {
+        let (width, height) = img.dimensions();
+        let pixels: &mut [u8] = img;
+        SharedImage {
+            pixels: pixels.as_mut_ptr(),
+            width,
+            height,
+            _img: PhantomData,
+        }
+    }
+
+    /// Copy the line at index `line` of `column`, an image of a single column with lines `line_height` pixels high,
+    /// into this image with its top-left corner at `(x, y)`.
+    ///
+    /// # Panics
+    ///
+    /// If the line doesn't fit into either image.
+    ///
+    /// # Safety
+    ///
+    /// No other thread may write the same pixels of this image at the same time.
+    pub unsafe fn write_line(
+        &self,
+        column: &ImageBuffer<P, Vec<u8>>,
+        line: u32,
+        line_height: u32,
+        (x, y): (u32, u32),
+    ) {
+        let width = column.width();
+        assert!(
+            x + width <= self.width && y + line_height <= self.height,
+            "line must be within the image"
+        );
+        let channel_count = P::CHANNEL_COUNT as usize;
+        let row_len = width as usize * channel_count;
+        for row in 0..line_height {
+            let src_start = (line * line_height + row) as usize * row_len;
+            let src = &column.as_raw()[src_start..][..row_len];
+            let dst_start = ((y + row) as usize * self.width as usize + x as usize) * channel_count;
+            // SAFETY: the row is within the image as asserted above, and the caller guarantees that nobody else
+            // writes to it.
+            std::ptr::copy_nonoverlapping(src.as_ptr(), self.pixels.add(dst_start), row_len);
+        }
+    }
+}
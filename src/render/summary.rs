@@ -0,0 +1,98 @@
+use crate::render::chunk::{calc_offsets, fill_rect, restyle};
+use crate::render::{BgColor, Cache};
+use crate::RenderError;
+use image::{ImageBuffer, Rgb};
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use syntect::parsing::SyntaxSet;
+
+/// Where to draw the lines of a file summary, and in which colors.
+pub(crate) struct Layout {
+    /// The width of a column in pixels.
+    pub column_width: u32,
+    /// The height of a line in pixels, including `line_spacing`.
+    pub line_height: u32,
+    /// The amount of pixel rows below each bar that are left blank.
+    pub line_spacing: u32,
+    pub lines_per_column: u32,
+    pub required_columns: u32,
+    pub rtl_columns: bool,
+    pub bg_color: BgColor,
+    pub color_modulation: f32,
+    /// If set, the `(foreground, background)` colors to use for all files.
+    pub mono: Option<(Rgb<u8>, Rgb<u8>)>,
+    /// If set, the background to use for all files instead of the one derived from their style.
+    pub uniform_background: Option<Rgb<u8>>,
+}
+
+/// Draw one line per file in `files`, given as `(path, content, num_lines)`, with a bar as long as the file has lines
+/// relative to the longest file, in the foreground color of its syntax.
+///
+/// Returns the amount of drawn lines along with all used background colors, the last one being the last one used.
+pub(crate) fn process<'a, C>(
+    img: &mut ImageBuffer<Rgb<u8>, C>,
+    files: impl ExactSizeIterator<Item = (&'a Path, &'a str, usize)> + Clone,
+    cache: &Cache<'_, '_>,
+    ss: &SyntaxSet,
+    plain: bool,
+    Layout {
+        column_width,
+        line_height,
+        line_spacing,
+        lines_per_column,
+        required_columns,
+        rtl_columns,
+        bg_color,
+        color_modulation,
+        mono,
+        uniform_background,
+    }: Layout,
+    should_interrupt: &AtomicBool,
+) -> Result<(u32, Vec<Rgb<u8>>), RenderError>
+where
+    C: Deref<Target = [u8]>,
+    C: DerefMut,
+{
+    let longest_file = files
+        .clone()
+        .map(|(_, _, lines)| lines)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let mut backgrounds = Vec::new();
+    let mut line_num = 0;
+    for (file_index, (path, content, num_lines)) in files.enumerate() {
+        if should_interrupt.load(Ordering::Relaxed) {
+            return Err(RenderError::Interrupted);
+        }
+        let mut highlighter = if plain {
+            cache.new_plain_highlighter()
+        } else {
+            cache.highlighter_for_file(path, content)
+        };
+        let style = restyle(highlighter.highlight_line(" ", ss)?[0].0, mono);
+        let background = match (mono, uniform_background) {
+            (Some((_, background)), _) | (None, Some(background)) => background,
+            (None, None) => bg_color.to_rgb(style, file_index, color_modulation),
+        };
+        let foreground = Rgb([style.foreground.r, style.foreground.g, style.foreground.b]);
+        backgrounds.retain(|bg| *bg != background);
+        backgrounds.push(background);
+
+        let (x, y) = calc_offsets(
+            line_num,
+            lines_per_column,
+            column_width,
+            line_height,
+            required_columns,
+            rtl_columns,
+        );
+        let bar_width =
+            ((num_lines as f64 / longest_file as f64) * column_width as f64).ceil() as u32;
+        fill_rect(img, x, y, column_width, line_height, background);
+        fill_rect(img, x, y, bar_width, line_height - line_spacing, foreground);
+        line_num += 1;
+    }
+    Ok((line_num, backgrounds))
+}
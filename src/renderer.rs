@@ -0,0 +1,152 @@
+use crate::render::Options;
+use crate::{DirContents, RenderError};
+use image::{ImageBuffer, Rgb, RgbImage};
+use memmap2::MmapMut;
+use prodash::Progress;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+
+/// Owns the syntax and theme sets that are expensive to load, so many directories can be rendered with them.
+pub struct Renderer {
+    syntax_set: SyntaxSet,
+    theme_set: ThemeSet,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::new()
+    }
+}
+
+impl Renderer {
+    /// Create a renderer with the syntaxes and themes that come with `syntect`.
+    pub fn new() -> Self {
+        Renderer {
+            syntax_set: SyntaxSet::load_defaults_newlines(),
+            theme_set: ThemeSet::load_defaults(),
+        }
+    }
+
+    /// Create a renderer with the given syntaxes and themes.
+    pub fn from_sets(syntax_set: SyntaxSet, theme_set: ThemeSet) -> Self {
+        Renderer {
+            syntax_set,
+            theme_set,
+        }
+    }
+
+    /// Create a renderer with the syntaxes that come with `syntect` along with the `.sublime-syntax` files
+    /// in `dir`, and the default themes.
+    pub fn with_syntax_dir(dir: &Path) -> Result<Self, RenderError> {
+        let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+        builder
+            .add_from_folder(dir, true)
+            .map_err(|source| RenderError::SyntaxDir {
+                dir: dir.to_owned(),
+                source,
+            })?;
+        Ok(Renderer {
+            syntax_set: builder.build(),
+            theme_set: ThemeSet::load_defaults(),
+        })
+    }
+
+    /// The syntaxes used for highlighting.
+    pub fn syntax_set(&self) -> &SyntaxSet {
+        &self.syntax_set
+    }
+
+    /// The themes that can be chosen in [`Options`].
+    pub fn theme_set(&self) -> &ThemeSet {
+        &self.theme_set
+    }
+
+    /// Make `theme` available under `name`, replacing any theme of the same name.
+    pub fn add_theme(&mut self, name: impl Into<String>, theme: Theme) {
+        self.theme_set.themes.insert(name.into(), theme);
+    }
+
+    /// Render `dir_content` to an image like [`render()`](crate::render()).
+    pub fn render(
+        &self,
+        dir_content: &DirContents,
+        opts: Options<'_>,
+        progress: impl Progress,
+        should_interrupt: &AtomicBool,
+    ) -> Result<ImageBuffer<Rgb<u8>, MmapMut>, RenderError> {
+        crate::render(
+            dir_content,
+            progress,
+            should_interrupt,
+            &self.syntax_set,
+            &self.theme_set,
+            opts,
+        )
+    }
+
+    /// Render `dir_content` into `buffer` like [`render_into()`](crate::render_into()).
+    pub fn render_into<C>(
+        &self,
+        buffer: &mut ImageBuffer<Rgb<u8>, C>,
+        dir_content: &DirContents,
+        opts: Options<'_>,
+        progress: impl Progress,
+        should_interrupt: &AtomicBool,
+    ) -> Result<(), RenderError>
+    where
+        C: Deref<Target = [u8]>,
+        C: DerefMut,
+    {
+        crate::render_into(
+            buffer,
+            dir_content,
+            progress,
+            should_interrupt,
+            &self.syntax_set,
+            &self.theme_set,
+            opts,
+        )
+    }
+
+    /// The dimensions of the buffer to pass to [`render_into()`](Self::render_into()), like
+    /// [`compute_layout()`](crate::compute_layout()).
+    pub fn compute_layout(
+        &self,
+        dir_content: &DirContents,
+        opts: Options<'_>,
+        progress: impl Progress,
+    ) -> Result<(u32, u32), RenderError> {
+        crate::compute_layout(
+            dir_content,
+            progress,
+            &self.syntax_set,
+            &self.theme_set,
+            opts,
+        )
+    }
+
+    /// Render `dir_content` in bands like [`render_in_bands()`](crate::render_in_bands()).
+    pub fn render_in_bands(
+        &self,
+        dir_content: &DirContents,
+        opts: Options<'_>,
+        progress: impl Progress,
+        should_interrupt: &AtomicBool,
+        band_lines: u32,
+        write_band: impl FnMut((u32, u32), &RgbImage) -> std::io::Result<()>,
+    ) -> Result<(u32, u32), RenderError> {
+        crate::render_in_bands(
+            dir_content,
+            progress,
+            should_interrupt,
+            &self.syntax_set,
+            &self.theme_set,
+            opts,
+            band_lines,
+            write_band,
+        )
+    }
+}
@@ -0,0 +1,104 @@
+use image::Rgb;
+use std::str::FromStr;
+use syntect::highlighting::{
+    Color, ScopeSelectors, StyleModifier, Theme, ThemeItem, ThemeSettings,
+};
+
+/// The name of the theme built by [`terminal_theme()`].
+pub const TERMINAL_THEME: &str = "Terminal";
+
+/// The index into the 16 ANSI colors that each group of scopes is highlighted with.
+const SCOPES: &[(&str, usize)] = &[
+    ("comment, punctuation.definition.comment", 8),
+    ("string, punctuation.definition.string", 2),
+    ("constant, support.constant", 6),
+    ("keyword, storage.modifier, storage.type.function", 5),
+    (
+        "storage.type, entity.name.type, support.type, support.class",
+        3,
+    ),
+    (
+        "entity.name.function, support.function, meta.function-call",
+        4,
+    ),
+    ("entity.name.tag, variable.parameter", 1),
+    ("entity.other.attribute-name, meta.attribute", 11),
+    ("invalid", 9),
+];
+
+/// Build a theme from the 16 ANSI `colors` of a terminal, ordered like black, red, green, yellow, blue, magenta,
+/// cyan and white followed by their bright variants, with text in white on black.
+///
+/// Only a few broad groups of scopes get a color of their own, so files look like they do in terminal editors
+/// rather than as detailed as with a full theme.
+pub fn terminal_theme(colors: &[Rgb<u8>; 16]) -> Theme {
+    let color = |index: usize| {
+        let Rgb([r, g, b]) = colors[index];
+        Color { r, g, b, a: 0xff }
+    };
+    Theme {
+        name: Some(TERMINAL_THEME.into()),
+        author: None,
+        settings: ThemeSettings {
+            foreground: Some(color(7)),
+            background: Some(color(0)),
+            ..Default::default()
+        },
+        scopes: SCOPES
+            .iter()
+            .map(|&(scopes, index)| ThemeItem {
+                scope: ScopeSelectors::from_str(scopes).expect("scope selectors are valid"),
+                style: StyleModifier {
+                    foreground: Some(color(index)),
+                    background: None,
+                    font_style: None,
+                },
+            })
+            .collect(),
+    }
+}
+
+/// Parse exactly 16 colors like `#RRGGBB`, separated by whitespace, commas or colons, like the `colors` file of
+/// `pywal` with one color per line, or return `None` if there are more, less, or any of them is invalid.
+pub fn parse_ansi_colors(text: &str) -> Option<[Rgb<u8>; 16]> {
+    let colors = text
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+        .filter(|color| !color.is_empty())
+        .map(|color| {
+            let hex = color.strip_prefix('#').unwrap_or(color);
+            let channel = |range: std::ops::Range<usize>| {
+                hex.get(range)
+                    .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+            };
+            match (hex.len(), channel(0..2), channel(2..4), channel(4..6)) {
+                (6, Some(r), Some(g), Some(b)) => Some(Rgb([r, g, b])),
+                _ => None,
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+    colors.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ansi_colors_are_parsed_from_exactly_16_colors() {
+        let ansi = (0..16u8)
+            .map(|index| format!("#{:02x}{:02x}{:02x}", index * 16, 0x40, 0xff - index * 16))
+            .collect::<Vec<_>>();
+        let colors = parse_ansi_colors(&ansi.join("\n")).expect("one color per line");
+        assert_eq!(colors[1], Rgb([0x10, 0x40, 0xef]));
+        assert_eq!(
+            parse_ansi_colors(&ansi.join(":")),
+            Some(colors),
+            "colons separate colors as well"
+        );
+        assert_eq!(parse_ansi_colors(&ansi[..15].join(",")), None);
+        assert_eq!(
+            parse_ansi_colors(&ansi.join(",").replace("#f0", "#g0")),
+            None
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The phases of turning a directory into an image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Phase {
+    /// Finding and reading all input files, and counting their lines.
+    Scan,
+    /// Determining the image dimensions.
+    Dimension,
+    /// Drawing all lines into the image.
+    Process,
+    /// Encoding and writing the image.
+    Save,
+}
+
+impl Phase {
+    /// All phases in the order they happen in.
+    pub const ALL: [Phase; 4] = [Phase::Scan, Phase::Dimension, Phase::Process, Phase::Save];
+
+    /// A short lower-case name for display.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Scan => "scan",
+            Phase::Dimension => "dimension",
+            Phase::Process => "process",
+            Phase::Save => "save",
+        }
+    }
+}
+
+/// Accumulates the time spent in each [`Phase`], for benchmarking.
+///
+/// It's shared by reference and can be recorded into from multiple threads.
+#[derive(Debug, Default)]
+pub struct PhaseTimer {
+    elapsed: Mutex<[Duration; Phase::ALL.len()]>,
+}
+
+impl PhaseTimer {
+    /// Add `elapsed` to the time spent in `phase`.
+    pub fn add(&self, phase: Phase, elapsed: Duration) {
+        self.elapsed.lock().expect("no panics while locked")[phase as usize] += elapsed;
+    }
+
+    /// Return the total time spent in `phase` so far.
+    pub fn elapsed(&self, phase: Phase) -> Duration {
+        self.elapsed.lock().expect("no panics while locked")[phase as usize]
+    }
+}
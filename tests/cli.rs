@@ -0,0 +1,42 @@
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+use common::TempDir;
+
+/// Run the `codevis` binary on the `line-endings` fixture with `args`, and return the hash it printed for `out`.
+fn print_hash(out: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_codevis"))
+        .args(["-i", "tests/fixtures/line-endings", "--print-hash", "-o"])
+        .arg(out)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let (hash, path) = stdout
+        .trim_end()
+        .split_once("  ")
+        .expect("the hash is followed by the path");
+    assert_eq!(Path::new(path), out);
+    assert_eq!(hash.len(), 64, "BLAKE3 hashes are 32 bytes in hex");
+    hash.to_owned()
+}
+
+#[test]
+fn print_hash_is_the_same_without_writing_the_image() {
+    let dir = TempDir::new("cli");
+    let out = dir.path().join("out.png");
+
+    let hash = print_hash(&out, &["--no-write"]);
+    assert!(!out.exists(), "--no-write doesn't write the image");
+    assert_eq!(
+        print_hash(&out, &["--low-memory", "--no-write"]),
+        hash,
+        "--low-memory hashes the same image band by band"
+    );
+    assert!(!out.exists());
+
+    assert_eq!(print_hash(&out, &[]), hash);
+    assert!(out.exists(), "the image is written by default");
+}
@@ -0,0 +1,3 @@
+fn c() {
+    b();
+}
@@ -0,0 +1,15 @@
+use std::collections::HashMap;
+
+/// Count how often each word appears in `text`.
+fn word_counts(text: &str) -> HashMap<&str, usize> {
+	let mut counts = HashMap::new();
+	for word in text.split_whitespace() {
+		*counts.entry(word).or_insert(0) += 1;
+	}
+	counts
+}
+
+fn main() {
+    let counts = word_counts("the quick brown fox jumps over the lazy dog");
+    println!("{counts:?}");
+}
@@ -0,0 +1,103 @@
+//! Compare renders of the fixture in `tests/fixtures/golden/input` pixel by pixel with committed images.
+//!
+//! Run with `CODEVIS_BLESS=1` to write the current renders as new golden images after an intended change.
+use codevis::render;
+use image::RgbImage;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+
+const FIXTURE_DIR: &str = "tests/fixtures/golden";
+
+/// Render the fixture input with `opts`, sorted like the binary does it.
+fn render_fixture(opts: render::Options<'_>) -> RgbImage {
+    let (mut dir_contents, _ignored) = codevis::unicode_content(
+        &Path::new(FIXTURE_DIR).join("input"),
+        codevis::ReadOptions {
+            normalize_eol: true,
+            ..Default::default()
+        },
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+    )
+    .unwrap();
+    dir_contents
+        .children_content
+        .sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    let img = codevis::render(
+        &dir_contents,
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+        &SyntaxSet::load_defaults_newlines(),
+        &ThemeSet::load_defaults(),
+        opts,
+    )
+    .unwrap();
+    RgbImage::from_raw(img.width(), img.height(), img.to_vec()).unwrap()
+}
+
+/// Assert that `actual` matches the golden image `name`, allowing each channel of each pixel to be off by `tolerance`.
+fn assert_matches_golden(actual: &RgbImage, name: &str, tolerance: u8) {
+    let path = Path::new(FIXTURE_DIR).join(format!("{name}.png"));
+    if std::env::var_os("CODEVIS_BLESS").is_some() {
+        actual.save(&path).unwrap();
+        return;
+    }
+    let expected = image::open(&path)
+        .unwrap_or_else(|err| {
+            panic!("{path:?} should exist, run with CODEVIS_BLESS=1 to create it: {err}")
+        })
+        .to_rgb8();
+    assert_eq!(
+        actual.dimensions(),
+        expected.dimensions(),
+        "{name}: dimensions differ"
+    );
+    let mismatches = actual
+        .enumerate_pixels()
+        .zip(expected.pixels())
+        .filter(|((_, _, a), e)| a.0.iter().zip(e.0).any(|(a, e)| a.abs_diff(e) > tolerance))
+        .map(|((x, y, _), _)| (x, y))
+        .collect::<Vec<_>>();
+    assert!(
+        mismatches.is_empty(),
+        "{name}: {} pixels differ by more than {tolerance}, the first at {:?}",
+        mismatches.len(),
+        mismatches[0]
+    );
+}
+
+fn options() -> render::Options<'static> {
+    render::Options {
+        column_width: 40,
+        line_height: 1,
+        target_aspect_ratio: 1.0,
+        threads: 1,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn plain() {
+    let img = render_fixture(render::Options {
+        plain: true,
+        ..options()
+    });
+    assert_matches_golden(&img, "plain", 0);
+}
+
+#[test]
+fn single_theme() {
+    let img = render_fixture(options());
+    assert_matches_golden(&img, "single-theme", 0);
+}
+
+#[test]
+fn multi_threaded_matches_single_threaded_golden() {
+    let img = render_fixture(render::Options {
+        threads: 2,
+        ..options()
+    });
+    assert_matches_golden(&img, "single-theme", 0);
+}
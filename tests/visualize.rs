@@ -1,10 +1,44 @@
 use bstr::ByteSlice;
 use codevis::render;
+use image::{ImageBuffer, Rgb};
+use memmap2::MmapMut;
 use std::path::Path;
 use std::sync::atomic::AtomicBool;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 use syntect::highlighting::ThemeSet;
 use syntect::parsing::SyntaxSet;
 
+mod common;
+use common::TempDir;
+
+/// The default syntaxes, which are loaded only once for all tests.
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// The default themes, which are loaded only once for all tests.
+fn theme_set() -> &'static ThemeSet {
+    static THEME_SET: OnceLock<ThemeSet> = OnceLock::new();
+    THEME_SET.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Render `dir_contents` with `opts` and the default syntaxes and themes, without showing progress.
+fn render_with(
+    dir_contents: &codevis::DirContents,
+    opts: render::Options<'_>,
+) -> Result<ImageBuffer<Rgb<u8>, MmapMut>, codevis::RenderError> {
+    codevis::render(
+        dir_contents,
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+        syntax_set(),
+        theme_set(),
+        opts,
+    )
+}
+
 #[test]
 fn various_renders() {
     let ss = SyntaxSet::load_defaults_newlines();
@@ -12,7 +46,10 @@ fn various_renders() {
 
     let (paths, ignored) = codevis::unicode_content(
         Path::new("./src/"),
-        &[],
+        codevis::ReadOptions {
+            normalize_eol: true,
+            ..Default::default()
+        },
         prodash::progress::Discard,
         &AtomicBool::default(),
     )
@@ -38,6 +75,7 @@ fn various_renders() {
         readable: false,
         show_filenames: false,
         line_nums: false,
+        ..Default::default()
     };
     codevis::render(
         &paths,
@@ -91,13 +129,411 @@ fn various_renders() {
     .unwrap();
 }
 
+#[test]
+fn theme_for_extension_picks_the_theme_of_matching_files() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![
+            ("lib.rs".into(), "fn main() {}\n".to_owned()),
+            ("notes.txt".into(), "some text\n".to_owned()),
+        ],
+    };
+    let theme_for_extension = [("rs".to_owned(), "InspiredGitHub".to_owned())];
+    let img = render_with(
+        &dir_contents,
+        render::Options {
+            theme: "Solarized (dark)",
+            theme_for_extension: &theme_for_extension,
+            bg_color: render::BgColor::Style,
+            color_modulation: 0.0,
+            max_columns: Some(1),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let background_of = |theme: &str| {
+        let color = theme_set().themes[theme].settings.background.unwrap();
+        image::Rgb([color.r, color.g, color.b])
+    };
+    // each file has a single line, ending in its background.
+    let line_height = render::Options::default().line_height;
+    assert_eq!(
+        *img.get_pixel(img.width() - 1, 0),
+        background_of("InspiredGitHub")
+    );
+    assert_eq!(
+        *img.get_pixel(img.width() - 1, line_height),
+        background_of("Solarized (dark)"),
+        "files of other extensions keep the theme"
+    );
+}
+
+#[test]
+fn listed_files_are_read_from_nul_separated_paths() {
+    let files = codevis::nul_separated_paths(b"lib.rs\0with\nnewline.rs\0");
+    assert_eq!(
+        files,
+        [Path::new("lib.rs"), Path::new("with\nnewline.rs")],
+        "the empty trailing record is ignored"
+    );
+
+    let (contents, ignored) = codevis::unicode_content_of(
+        Path::new("./src/"),
+        &files,
+        codevis::ReadOptions {
+            normalize_eol: true,
+            ..Default::default()
+        },
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+    )
+    .unwrap();
+    assert_eq!(ignored, 0);
+    assert_eq!(
+        contents
+            .children_content
+            .iter()
+            .map(|(path, _)| path.as_path())
+            .collect::<Vec<_>>(),
+        [Path::new("./src/lib.rs")],
+        "files that can't be read are skipped"
+    );
+}
+
+#[test]
+fn listed_files_outside_of_the_parent_directory_are_ignored() {
+    let files = [
+        "render/../lib.rs",
+        "../Cargo.toml",
+        "render/../../Cargo.toml",
+        "./../src/lib.rs",
+    ]
+    .map(std::path::PathBuf::from);
+    let (contents, ignored) = codevis::unicode_content_of(
+        Path::new("./src/"),
+        &files,
+        codevis::ReadOptions::default(),
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+    )
+    .unwrap();
+    assert_eq!(ignored, 3, "`..` may not leave the parent directory");
+    assert_eq!(
+        contents
+            .children_content
+            .iter()
+            .map(|(path, _)| path.as_path())
+            .collect::<Vec<_>>(),
+        [Path::new("./src/lib.rs")],
+        "`..` within the parent directory is fine"
+    );
+}
+
+#[test]
+fn relative_paths_are_normalized_for_display() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: "./src/".into(),
+        children_content: Vec::new(),
+    };
+    assert_eq!(
+        dir_contents.relative_path(Path::new("./src/render/./mod.rs")),
+        Path::new("render/mod.rs")
+    );
+    assert_eq!(
+        dir_contents.relative_path(Path::new("./tests/visualize.rs")),
+        Path::new("tests/visualize.rs"),
+        "paths outside of the parent directory are kept"
+    );
+
+    // file names are written without `.` components as well.
+    let render = |path: &str| {
+        render_with(
+            &codevis::DirContents::from_memory(vec![(path.to_owned(), "text\n".to_owned())]),
+            render::Options {
+                readable: true,
+                show_filenames: true,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .into_raw()
+        .to_vec()
+    };
+    assert_eq!(render("./notes/./todo.txt"), render("notes/todo.txt"));
+    assert_ne!(
+        render("notes/todo.txt"),
+        render("notes/done.txt"),
+        "the file name is visible"
+    );
+}
+
+#[test]
+fn files_in_memory_are_rendered_without_reading_them() {
+    let script = "#!/usr/bin/env bash\n# greet\necho \"hello\"\n";
+    let render = |path: &str| {
+        render_with(
+            &codevis::DirContents::from_memory(vec![(path.to_owned(), script.to_owned())]),
+            render::Options {
+                threads: 1,
+                ignore_files_without_syntax: true,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .into_raw()
+        .to_vec()
+    };
+    assert_eq!(
+        render("does/not/exist/greet"),
+        render("does/not/exist/greet.sh"),
+        "the syntax is detected by the first line of the content"
+    );
+}
+
+#[test]
+fn ignore_files_hide_matching_files() {
+    let input_dir = Path::new("tests/fixtures/ignore-file/input");
+    let rendered_files = |ignore_file: Option<&Path>| {
+        let (contents, ignored) = codevis::unicode_content(
+            input_dir,
+            codevis::ReadOptions {
+                ignore_file,
+                normalize_eol: true,
+                ..Default::default()
+            },
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+        )
+        .unwrap();
+        let mut files = contents
+            .children_content
+            .into_iter()
+            .map(|(path, _)| path.strip_prefix(input_dir).unwrap().to_owned())
+            .collect::<Vec<_>>();
+        files.sort();
+        (files, ignored)
+    };
+    assert_eq!(
+        rendered_files(None),
+        (vec!["keep.rs".into(), "notes.md".into()], 1),
+        "the .codeviz-ignore file in the input directory is used by default"
+    );
+    assert_eq!(
+        rendered_files(Some(Path::new(
+            "tests/fixtures/ignore-file/markdown.ignore"
+        ))),
+        (vec!["keep.rs".into(), "tests/hidden.rs".into()], 1),
+        "an explicit ignore file replaces the default one"
+    );
+}
+
+#[test]
+fn hidden_files_are_only_read_on_request() {
+    let input_dir = Path::new("tests/fixtures/hidden/input");
+    let rendered_files = |hidden: bool| {
+        let (contents, _ignored) = codevis::unicode_content(
+            input_dir,
+            codevis::ReadOptions {
+                hidden,
+                normalize_eol: true,
+                ..Default::default()
+            },
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+        )
+        .unwrap();
+        let mut files = contents
+            .children_content
+            .into_iter()
+            .map(|(path, _)| path.strip_prefix(input_dir).unwrap().to_owned())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    };
+    assert_eq!(rendered_files(false), vec![Path::new("main.rs")]);
+    assert_eq!(
+        rendered_files(true),
+        vec![
+            Path::new(".env.example"),
+            Path::new(".github/workflows/ci.yml"),
+            Path::new(".gitignore"),
+            Path::new("main.rs"),
+        ],
+        "hidden files that aren't ignored by .gitignore files are read as well"
+    );
+}
+
+#[test]
+fn listing_paths_before_reading_them_reads_like_a_single_pass() {
+    let rs = [std::ffi::OsString::from("rs")];
+    for (input_dir, opts) in [
+        ("src", codevis::ReadOptions::default()),
+        (
+            "src",
+            codevis::ReadOptions {
+                ignore_extensions: &rs,
+                ..Default::default()
+            },
+        ),
+        ("tests/fixtures/ignore-file/input", Default::default()),
+        ("tests/fixtures/hidden/input", Default::default()),
+        (
+            "tests/fixtures/hidden/input",
+            codevis::ReadOptions {
+                hidden: true,
+                ..Default::default()
+            },
+        ),
+        ("tests/fixtures/binary/input", Default::default()),
+        (
+            "tests/fixtures/binary/input",
+            codevis::ReadOptions {
+                binary_as_hexdump: true,
+                ..Default::default()
+            },
+        ),
+    ] {
+        let input_dir = Path::new(input_dir);
+        let (contents, ignored) = codevis::unicode_content(
+            input_dir,
+            opts,
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+        )
+        .unwrap();
+
+        // read each entry as soon as it's found, in the order of the walk.
+        let walked_files = ignore::WalkBuilder::new(input_dir)
+            .hidden(!opts.hidden)
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build()
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .path()
+                    .strip_prefix(input_dir)
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect::<Vec<_>>();
+        let (single_pass_contents, single_pass_ignored) = codevis::unicode_content_of(
+            input_dir,
+            &walked_files,
+            opts,
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+        )
+        .unwrap();
+
+        assert_eq!(ignored, single_pass_ignored, "{input_dir:?}");
+        assert_eq!(
+            contents.children_content, single_pass_contents.children_content,
+            "{input_dir:?}"
+        );
+    }
+}
+
+#[test]
+fn crlf_line_endings_render_like_lf_line_endings_if_normalized() {
+    let read = |line_endings: &str, normalize_eol: bool| {
+        codevis::unicode_content(
+            &Path::new("tests/fixtures/line-endings").join(line_endings),
+            codevis::ReadOptions {
+                normalize_eol,
+                ..Default::default()
+            },
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+        )
+        .unwrap()
+        .0
+    };
+    let render = |dir_contents: &codevis::DirContents| {
+        render_with(
+            dir_contents,
+            render::Options {
+                threads: 1,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .into_raw()
+        .to_vec()
+    };
+    assert!(
+        read("crlf", false).children_content[0].1.contains('\r'),
+        "line endings are kept on request"
+    );
+    let crlf = read("crlf", true);
+    assert!(!crlf.children_content[0].1.contains('\r'));
+    assert_eq!(render(&crlf), render(&read("lf", true)));
+}
+
+#[test]
+fn empty_files_are_skipped() {
+    let (mut dir_contents, _ignored) = codevis::unicode_content(
+        Path::new("tests/fixtures/empty-file/input"),
+        codevis::ReadOptions {
+            normalize_eol: true,
+            ..Default::default()
+        },
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+    )
+    .unwrap();
+    dir_contents.children_content.sort();
+    assert_eq!(dir_contents.children_content.len(), 3);
+    let render = |dir_contents: &codevis::DirContents, threads| {
+        render_with(
+            dir_contents,
+            render::Options {
+                threads,
+                show_filenames: true,
+                uniform_file_height: Some(2),
+                ..Default::default()
+            },
+        )
+        .map(|img| img.into_raw().to_vec())
+    };
+
+    let without_empty_file = codevis::DirContents {
+        parent_dir: dir_contents.parent_dir.clone(),
+        children_content: dir_contents
+            .children_content
+            .iter()
+            .filter(|(_, content)| !content.is_empty())
+            .cloned()
+            .collect(),
+    };
+    let expected = render(&without_empty_file, 1).unwrap();
+    for threads in [1, 3] {
+        assert_eq!(
+            render(&dir_contents, threads).unwrap(),
+            expected,
+            "the empty file takes no space with {threads} threads"
+        );
+    }
+
+    let only_empty_file = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("empty.rs".into(), String::new())],
+    };
+    assert!(matches!(
+        render(&only_empty_file, 1),
+        Err(codevis::RenderError::NoLines { num_files: 1 })
+    ));
+}
+
 #[test]
 fn multi_threading_produces_same_result_as_single_threaded_mode() {
     let ss = SyntaxSet::load_defaults_newlines();
     let ts = ThemeSet::load_defaults();
     let (paths, ignored) = codevis::unicode_content(
         Path::new("./src/"),
-        &[],
+        codevis::ReadOptions {
+            normalize_eol: true,
+            ..Default::default()
+        },
         prodash::progress::Discard,
         &AtomicBool::default(),
     )
@@ -123,6 +559,7 @@ fn multi_threading_produces_same_result_as_single_threaded_mode() {
         readable: false,
         show_filenames: false,
         line_nums: false,
+        ..Default::default()
     };
     let expected = codevis::render(
         &paths,
@@ -149,3 +586,2842 @@ fn multi_threading_produces_same_result_as_single_threaded_mode() {
         "multi-threaded version should be pixel-perfect"
     );
 }
+
+#[test]
+fn rendering_in_bands_matches_rendering_at_once() {
+    let (paths, _ignored) = codevis::unicode_content(
+        Path::new("./src/render"),
+        codevis::ReadOptions {
+            normalize_eol: true,
+            ..Default::default()
+        },
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+    )
+    .unwrap();
+
+    let opts = render::Options {
+        column_width: 60,
+        line_height: 1,
+        target_aspect_ratio: 1.0,
+        threads: 1,
+        show_filenames: true,
+        uniform_file_height: Some(150),
+        rtl_columns: true,
+        ..Default::default()
+    };
+    let expected = render_with(&paths, opts).unwrap();
+
+    let mut actual = Vec::new();
+    let mut num_bands = 0;
+    let dimensions = codevis::render_in_bands(
+        &paths,
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+        syntax_set(),
+        theme_set(),
+        opts,
+        70,
+        |dimensions, band| {
+            assert_eq!(dimensions, expected.dimensions());
+            actual.extend_from_slice(band.as_raw());
+            num_bands += 1;
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(dimensions, expected.dimensions());
+    assert!(num_bands > 1, "the image is written in multiple bands");
+    assert!(
+        actual == expected.as_bytes(),
+        "bands should be pixel-perfect"
+    );
+}
+
+#[test]
+fn renderer_with_syntax_dir_knows_additional_syntaxes() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        // syntaxes are also detected by the first line, so the file has to exist.
+        children_content: vec![(
+            "tests/fixtures/syntaxes/loud.shout".into(),
+            "HELLO there\n".to_owned(),
+        )],
+    };
+    let opts = render::Options {
+        column_width: 20,
+        line_height: 1,
+        ignore_files_without_syntax: true,
+        ..Default::default()
+    };
+
+    let renderer = codevis::Renderer::new();
+    assert!(matches!(
+        renderer.render(
+            &dir_contents,
+            opts,
+            prodash::progress::Discard,
+            &AtomicBool::default()
+        ),
+        Err(codevis::RenderError::NoLines { num_files: 0 })
+    ));
+
+    let renderer =
+        codevis::Renderer::with_syntax_dir(Path::new("tests/fixtures/syntaxes")).unwrap();
+    let img = renderer
+        .render(
+            &dir_contents,
+            opts,
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        img.width(),
+        20,
+        "the file is rendered with the added syntax"
+    );
+}
+
+#[test]
+fn files_too_short_for_the_aspect_ratio_are_laid_out() {
+    for num_lines in 1..=40 {
+        let dir_contents = codevis::DirContents {
+            parent_dir: Default::default(),
+            children_content: vec![("file.txt".into(), "some text\n".repeat(num_lines))],
+        };
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                threads: 1,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (columns, lines_per_column) = (img.width() / 100, img.height() / 2);
+        assert!(
+            columns * lines_per_column >= num_lines as u32,
+            "all {num_lines} lines fit into {columns} columns"
+        );
+    }
+}
+
+#[test]
+fn images_are_encoded_into_any_writer() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("main.rs".into(), "fn main() {\n    42\n}\n".to_owned())],
+    };
+    let img = render_with(
+        &dir_contents,
+        render::Options {
+            threads: 1,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for &format in <codevis::Format as clap::ValueEnum>::value_variants() {
+        let mut buf = Vec::new();
+        codevis::render_to_writer(
+            &img,
+            format,
+            codevis::BitDepth::Eight,
+            &mut buf,
+            prodash::progress::Discard,
+        )
+        .unwrap();
+        let decoded = image::load_from_memory_with_format(&buf, format.into())
+            .unwrap()
+            .into_rgb8();
+        assert_eq!(decoded.dimensions(), img.dimensions());
+        if format == codevis::Format::Jpeg {
+            let mean_difference = decoded
+                .as_raw()
+                .iter()
+                .zip(img.as_raw().iter())
+                .map(|(a, b)| a.abs_diff(*b) as f64)
+                .sum::<f64>()
+                / img.as_raw().len() as f64;
+            assert!(
+                mean_difference < 8.0,
+                "{format:?} is lossy, but close: {mean_difference}"
+            );
+        } else {
+            assert_eq!(
+                decoded.as_raw(),
+                &img.as_raw()[..],
+                "{format:?} is lossless"
+            );
+        }
+    }
+}
+
+#[test]
+fn unknown_themes_are_reported_with_their_name() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "hello".into())],
+    };
+    let err = render_with(
+        &dir_contents,
+        render::Options {
+            theme: "does-not-exist",
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, codevis::RenderError::ThemeNotFound { ref name, .. } if name == "does-not-exist")
+    );
+}
+
+#[test]
+fn theme_names_are_matched_ignoring_case_with_suggestions_for_typos() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "hello".into())],
+    };
+    let render = |theme| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                theme,
+                threads: 1,
+                ..Default::default()
+            },
+        )
+    };
+    assert!(
+        render("solarized (dark)").unwrap().as_bytes()
+            == render("Solarized (dark)").unwrap().as_bytes(),
+        "the lowercase name resolves to the built-in theme"
+    );
+    let err = render("Solarised (dark)").unwrap_err();
+    assert!(
+        matches!(err, codevis::RenderError::ThemeNotFound { suggestion: Some(ref suggestion), .. } if suggestion == "Solarized (dark)")
+    );
+}
+
+#[test]
+fn stripped_control_characters_take_no_space() {
+    let render_content = |content: &str, strip_control: bool| {
+        let dir_contents = codevis::DirContents {
+            parent_dir: Default::default(),
+            children_content: vec![("file.txt".into(), content.to_owned())],
+        };
+        render_with(
+            &dir_contents,
+            render::Options {
+                threads: 1,
+                strip_control,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+
+    let expected = render_content("ab\ncd\n", false);
+    let actual = render_content("a\u{c}b\ncd\u{1b}\n", true);
+    assert!(
+        actual.as_bytes() == expected.as_bytes(),
+        "control characters are dropped entirely"
+    );
+    let with_placeholder = render_content("a\u{c}b\ncd\u{1b}\n", false);
+    assert!(
+        with_placeholder.as_bytes() != expected.as_bytes(),
+        "by default, control characters are drawn"
+    );
+}
+
+#[test]
+fn background_image_replaces_background_pixels() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "a\n\nb c\n".to_owned())],
+    };
+    let background = image::Rgb([1, 2, 3]);
+    let background_image = image::RgbImage::from_pixel(3, 2, background);
+    for bg_fit in [
+        render::BgFit::Cover,
+        render::BgFit::Stretch,
+        render::BgFit::Tile,
+    ] {
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                threads: 1,
+                fg_color: render::FgColor::Style,
+                background_image: Some(&background_image),
+                bg_fit,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let num_background_pixels = img.pixels().filter(|p| **p == background).count();
+        assert!(num_background_pixels > 0, "{bg_fit:?}");
+        assert!(
+            num_background_pixels < (img.width() * img.height()) as usize,
+            "{bg_fit:?}: the code remains visible"
+        );
+    }
+}
+
+#[test]
+fn background_image_keeps_glyphs_with_the_background_color() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "a\n".to_owned())],
+    };
+    let img = render_with(&dir_contents, render::Options::default()).unwrap();
+    // the glyph of `a` is the first cell of the line, which ends with the background.
+    let background = *img.get_pixel(img.width() - 1, 0);
+
+    let background_image = image::RgbImage::from_pixel(1, 1, image::Rgb([1, 2, 3]));
+    for threads in [1, 2] {
+        for readable in [false, true] {
+            let img = render_with(
+                &dir_contents,
+                render::Options {
+                    threads,
+                    readable,
+                    fg_color: render::FgColor::Fixed(background),
+                    background_image: Some(&background_image),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let num_glyph_pixels = img.pixels().filter(|p| **p == background).count();
+            assert!(
+                num_glyph_pixels > 0,
+                "threads: {threads}, readable: {readable}: glyphs are kept"
+            );
+            assert_eq!(
+                *img.get_pixel(img.width() - 1, 0),
+                image::Rgb([1, 2, 3]),
+                "threads: {threads}, readable: {readable}: the background is replaced"
+            );
+        }
+    }
+}
+
+#[test]
+fn multi_threading_with_highlighting_produces_same_result_as_single_threaded_mode() {
+    let (paths, _ignored) = codevis::unicode_content(
+        Path::new("./src/"),
+        codevis::ReadOptions {
+            normalize_eol: true,
+            ..Default::default()
+        },
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+    )
+    .unwrap();
+
+    let theme_for_extension = [("toml".to_owned(), "InspiredGitHub".to_owned())];
+    for (readable, show_filenames, line_nums) in [(false, false, false), (true, true, true)] {
+        let opts = render::Options {
+            column_width: 80,
+            threads: 1,
+            plain: false,
+            fg_color: render::FgColor::StyleAsciiBrightness,
+            bg_color: render::BgColor::StyleCheckerboardDarken,
+            theme_for_extension: &theme_for_extension,
+            readable,
+            show_filenames,
+            line_nums,
+            ..Default::default()
+        };
+        let expected = render_with(&paths, opts).unwrap();
+        let actual = render_with(&paths, render::Options { threads: 8, ..opts }).unwrap();
+        assert!(
+            actual.as_bytes() == expected.as_bytes(),
+            "each thread highlights with its own state, so the result is pixel-perfect (readable = {readable})"
+        );
+    }
+}
+
+#[test]
+fn multi_threading_with_many_small_files_produces_same_result_as_single_threaded_mode() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: (0..2000)
+            .map(|i| {
+                (
+                    format!("dir{}/file{i}.rs", i % 7).into(),
+                    format!("fn f{i}() {{}}\n").repeat(1 + i % 4),
+                )
+            })
+            .collect(),
+    };
+    for (show_filenames, rtl_columns) in [(false, false), (true, true)] {
+        let opts = render::Options {
+            column_width: 16,
+            line_height: 1,
+            target_aspect_ratio: 1.0,
+            threads: 1,
+            bg_color: render::BgColor::StyleCheckerboardDarken,
+            show_filenames,
+            rtl_columns,
+            ..Default::default()
+        };
+        let render = |opts| render_with(&dir_contents, opts).unwrap();
+        let expected = render(opts);
+        assert!(expected.width() > 16, "files wrap across many columns");
+        for threads in [2, 8] {
+            assert!(
+                render(render::Options { threads, ..opts }).as_bytes() == expected.as_bytes(),
+                "threads write their lines to the same places (threads = {threads}, show_filenames = {show_filenames})"
+            );
+        }
+    }
+}
+
+#[test]
+fn files_are_highlighted_independently_of_each_other() {
+    let render_second_line = |first_file: &str| {
+        let dir_contents = codevis::DirContents {
+            parent_dir: Default::default(),
+            children_content: vec![
+                ("a.rs".into(), first_file.to_owned()),
+                ("b.rs".into(), "fn main() {}\n".to_owned()),
+            ],
+        };
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                threads: 1,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                fg_color: render::FgColor::Style,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        (0..img.width())
+            .map(|x| *img.get_pixel(x, 1))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        render_second_line("/* unterminated\n"),
+        render_second_line("// terminated\n"),
+        "an unterminated comment doesn't leak into the next file"
+    );
+}
+
+#[test]
+fn canvas_color_is_used_behind_brightness_encoded_glyphs() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("main.rs".into(), "a b\n".to_owned())],
+    };
+    let canvas = image::Rgb([10, 20, 30]);
+    let img = render_with(
+        &dir_contents,
+        render::Options {
+            column_width: 10,
+            line_height: 1,
+            target_aspect_ratio: 0.0,
+            fg_color: render::FgColor::StyleAsciiBrightness,
+            canvas_color: Some(canvas),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(img.get_pixel(1, 0), &canvas, "the space between glyphs");
+    assert_eq!(img.get_pixel(9, 0), &canvas, "the rest of the line");
+    assert_ne!(img.get_pixel(0, 0), &canvas, "glyphs keep their color");
+}
+
+#[test]
+fn grayscale_output_has_equal_channels() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("main.rs".into(), "fn main() {\n    42\n}\n".to_owned())],
+    };
+    let img = render_with(
+        &dir_contents,
+        render::Options {
+            threads: 1,
+            grayscale: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(img.pixels().all(|p| p[0] == p[1] && p[1] == p[2]));
+    assert!(
+        img.pixels().any(|p| p[0] != img.get_pixel(0, 0)[0]),
+        "highlighting remains visible"
+    );
+}
+
+#[test]
+fn mono_output_has_exactly_two_colors() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![
+            (
+                "main.rs".into(),
+                "// comment\nfn main() {\n    \"text\"\n}\n".to_owned(),
+            ),
+            ("notes.txt".into(), "plain text\n".to_owned()),
+        ],
+    };
+    let (fg, bg) = (image::Rgb([51, 255, 51]), image::Rgb([0, 0, 0]));
+    for readable in [false, true] {
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                threads: 1,
+                readable,
+                show_filenames: true,
+                bg_color: render::BgColor::StyleCheckerboardDarken,
+                comment_opacity: 0.5,
+                mono: Some((fg, bg)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut colors = img.pixels().copied().collect::<Vec<_>>();
+        colors.sort_by_key(|p| p.0);
+        colors.dedup();
+        assert_eq!(colors, [bg, fg], "readable: {readable}");
+    }
+}
+
+#[test]
+fn fg_boost_scales_the_brightness_of_glyphs_without_overflowing() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "zzzz\n".to_owned())],
+    };
+    let glyphs = |fg_boost| {
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                threads: 1,
+                column_width: 4,
+                line_height: 1,
+                fg_boost,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        img.pixels().copied().collect::<Vec<_>>()
+    };
+    assert!(glyphs(0.0).iter().all(|p| p.0 == [0, 0, 0]));
+    assert!(
+        glyphs(1000.0)
+            .iter()
+            .all(|p| p.0.iter().all(|c| *c == 0 || *c == 255)),
+        "channels saturate instead of wrapping around"
+    );
+}
+
+#[test]
+fn bright_glyphs_saturate_instead_of_wrapping_around() {
+    // the highest byte value with the default boost goes well past 255 in all channels of the theme's text color.
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "\u{ff}\u{ff}\n".to_owned())],
+    };
+    let img = render_with(
+        &dir_contents,
+        render::Options {
+            threads: 1,
+            column_width: 2,
+            line_height: 1,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(img.pixels().all(|p| p.0 == [255, 255, 255]));
+}
+
+#[test]
+fn trailing_whitespace_is_highlighted_but_padding_is_not() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "ab  \n\tc\t\n".to_owned())],
+    };
+    let red_pixels = |highlight_trailing_whitespace| {
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                highlight_trailing_whitespace,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        img.enumerate_pixels()
+            .filter(|(_, _, p)| **p == image::Rgb([255, 0, 0]))
+            .map(|(x, y, _)| (x, y))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        red_pixels(true),
+        [(2, 0), (3, 0), (5, 1), (6, 1), (7, 1)],
+        "only spaces and tabs after the last visible character are red"
+    );
+    assert!(red_pixels(false).is_empty());
+}
+
+#[test]
+fn spaces_before_the_cut_of_truncated_lines_are_not_trailing_whitespace() {
+    let dir_contents =
+        codevis::DirContents::from_memory(vec![("file.txt".into(), "foo   bar\nab  \n".into())]);
+    for highlight_truncated_lines in [false, true] {
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 6,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                highlight_trailing_whitespace: true,
+                highlight_truncated_lines,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let red_pixels = img
+            .enumerate_pixels()
+            .filter(|(_, _, p)| **p == image::Rgb([255, 0, 0]))
+            .map(|(x, y, _)| (x, y))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            red_pixels,
+            [(2, 1), (3, 1)],
+            "`bar` follows the spaces even though it's cut off, with highlight_truncated_lines: {highlight_truncated_lines}"
+        );
+    }
+}
+
+#[test]
+fn length_gradient_only_shades_characters_past_the_threshold() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "abcdefgh\n".to_owned())],
+    };
+    let render = |length_gradient| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                length_gradient,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let plain = render(None);
+    let shaded = render(Some(5));
+    let changed_columns = (0..plain.width())
+        .filter(|&x| plain.get_pixel(x, 0) != shaded.get_pixel(x, 0))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changed_columns,
+        [5, 6, 7],
+        "only characters past the threshold are shaded, but not the padding"
+    );
+}
+
+#[test]
+fn rtl_columns_mirror_the_column_order() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![
+            ("a.txt".into(), "aaaaaaaaaa\n".repeat(4)),
+            ("b.txt".into(), "b\n".repeat(4)),
+            ("c.txt".into(), "ccccc\n".repeat(4)),
+        ],
+    };
+    let column = |img: &image::ImageBuffer<image::Rgb<u8>, _>, index: u32, column_width: u32| {
+        image::imageops::crop_imm(img, index * column_width, 0, column_width, img.height())
+            .to_image()
+            .into_raw()
+    };
+    for threads in [1, 2] {
+        let opts = render::Options {
+            column_width: 10,
+            line_height: 1,
+            target_aspect_ratio: 10.0,
+            threads,
+            ..Default::default()
+        };
+        let ltr = render_with(&dir_contents, opts).unwrap();
+        let rtl = render_with(
+            &dir_contents,
+            render::Options {
+                rtl_columns: true,
+                ..opts
+            },
+        )
+        .unwrap();
+        let num_columns = ltr.width() / 10;
+        assert!(num_columns > 1, "the test needs multiple columns");
+        for index in 0..num_columns {
+            assert_eq!(
+                column(&ltr, index, 10),
+                column(&rtl, num_columns - 1 - index, 10),
+                "column {index} with {threads} thread(s)"
+            );
+        }
+    }
+}
+
+#[test]
+fn cached_columns_produce_the_same_result() {
+    let (paths, _ignored) = codevis::unicode_content(
+        Path::new("./src/"),
+        codevis::ReadOptions {
+            normalize_eol: true,
+            ..Default::default()
+        },
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+    )
+    .unwrap();
+    let cache_dir = TempDir::new("cache");
+    let cache_dir = cache_dir.path();
+
+    let opts = render::Options {
+        threads: 1,
+        ..Default::default()
+    };
+    let render = |opts| render_with(&paths, opts).unwrap();
+    let expected = render(opts);
+    let cached_opts = render::Options {
+        cache_dir: Some(cache_dir),
+        ..opts
+    };
+    let populating = render(cached_opts);
+    assert_eq!(
+        std::fs::read_dir(cache_dir).unwrap().count(),
+        paths.children_content.len(),
+        "one entry per file"
+    );
+    let from_cache = render(cached_opts);
+
+    assert!(populating.as_bytes() == expected.as_bytes());
+    assert!(from_cache.as_bytes() == expected.as_bytes());
+
+    // change the last pixel of each entry, which is only seen if entries are actually reused.
+    let marker = image::Rgb([1, 2, 3]);
+    let entries = std::fs::read_dir(cache_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    let rewrite = |change: &dyn Fn(&mut Vec<u8>)| {
+        for entry in &entries {
+            let mut data = std::fs::read(entry).unwrap();
+            change(&mut data);
+            std::fs::write(entry, data).unwrap();
+        }
+    };
+    rewrite(&|data| {
+        let len = data.len();
+        data[len - 3..].copy_from_slice(&marker.0);
+    });
+    let hit = render(cached_opts);
+    assert!(
+        !expected.pixels().any(|pixel| *pixel == marker)
+            && hit.pixels().any(|pixel| *pixel == marker),
+        "the cached columns were reused"
+    );
+
+    // entries start with their key, and aren't reused if it differs from the one looked up.
+    rewrite(&|data| data[0] ^= 0xff);
+    let mismatched_key = render(cached_opts);
+    assert!(mismatched_key.as_bytes() == expected.as_bytes());
+}
+
+#[test]
+fn auto_column_width_fits_the_longest_line() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![
+            ("a.txt".into(), "ab\n\tx\n".to_owned()),
+            ("b.txt".into(), "abcdefg\n".to_owned()),
+        ],
+    };
+    let width = |auto_column_width, tab_spaces, line_nums| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                threads: 1,
+                column_width: 100,
+                auto_column_width: Some(auto_column_width),
+                tab_spaces,
+                line_nums,
+                max_columns: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .width()
+    };
+    assert_eq!(width(300, 4, false), 7, "the longest line decides");
+    assert_eq!(
+        width(300, 8, false),
+        9,
+        "tabs count up to the next tab stop"
+    );
+    assert_eq!(width(300, 4, true), 10, "line numbers take cells as well");
+    assert_eq!(width(3, 4, false), 3, "the width is capped");
+}
+
+#[test]
+fn lines_matching_grep_are_tinted() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("a.txt".into(), "a\nsome TODO\nb\n".to_owned())],
+    };
+    let grep = regex::Regex::new("TODO|FIXME").unwrap();
+    let green = image::Rgb([0, 255, 0]);
+    for threads in [1, 2] {
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                threads,
+                column_width: 20,
+                line_height: 2,
+                max_columns: Some(1),
+                grep: Some(&grep),
+                grep_color: green,
+                grep_strength: 1.0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let row_is_green = |y| (0..img.width()).all(|x| *img.get_pixel(x, y) == green);
+        assert_eq!(
+            (0..img.height()).map(row_is_green).collect::<Vec<_>>(),
+            [false, false, true, true, false, false],
+            "only the matching line is tinted, with {threads} threads"
+        );
+    }
+}
+
+#[test]
+fn lines_matching_grep_at_their_end_are_kept_and_tinted() {
+    let dir_contents = || {
+        codevis::DirContents::from_memory(vec![("a.txt".into(), "a\nsome TODO\r\nTODO b\n".into())])
+    };
+    let grep = regex::Regex::new("TODO$").unwrap();
+
+    let mut kept = dir_contents();
+    kept.keep_matching_lines(&grep);
+    assert_eq!(kept.children_content[0].1, "some TODO\r\n");
+
+    let green = image::Rgb([0, 255, 0]);
+    let img = render_with(
+        &dir_contents(),
+        render::Options {
+            column_width: 20,
+            line_height: 2,
+            max_columns: Some(1),
+            grep: Some(&grep),
+            grep_color: green,
+            grep_strength: 1.0,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let row_is_green = |y| (0..img.width()).all(|x| *img.get_pixel(x, y) == green);
+    assert_eq!(
+        (0..img.height()).map(row_is_green).collect::<Vec<_>>(),
+        [false, false, true, true, false, false],
+        "`$` matches before the line ending"
+    );
+}
+
+#[test]
+fn min_column_lines_makes_short_inputs_taller() {
+    let render = |num_lines: usize, threads| {
+        let dir_contents = codevis::DirContents {
+            parent_dir: Default::default(),
+            children_content: vec![("file.txt".into(), "line\n".repeat(num_lines))],
+        };
+        render_with(
+            &dir_contents,
+            render::Options {
+                threads,
+                column_width: 10,
+                line_height: 1,
+                min_column_lines: Some(20),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .dimensions()
+    };
+    for threads in [1, 2] {
+        assert_eq!(
+            render(5, threads),
+            (10, 20),
+            "a single column with room to spare"
+        );
+        assert_eq!(render(30, threads), (20, 20), "two columns of 20 lines");
+    }
+}
+
+#[test]
+fn target_width_determines_the_image_width() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "line\n".repeat(103))],
+    };
+    let render = |target_width| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 100,
+                target_width: Some(target_width),
+                ..Default::default()
+            },
+        )
+    };
+    for (target_width, expected_width) in [(1920, 1900), (1000, 1000), (150, 100)] {
+        let img = render(target_width).unwrap();
+        assert_eq!(img.width(), expected_width);
+    }
+    assert!(
+        render(99).is_err(),
+        "it's an error if not even a single column fits"
+    );
+}
+
+#[test]
+fn exact_aspect_pads_the_image() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "some text\n".repeat(10))],
+    };
+    let render = |exact_aspect, pad| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height: 1,
+                target_aspect_ratio: 4.0,
+                max_columns: Some(1),
+                exact_aspect,
+                pad,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let unpadded = render(false, render::Pad::Center);
+    assert_eq!(unpadded.dimensions(), (10, 10));
+
+    for (pad, left) in [(render::Pad::Center, 15), (render::Pad::BottomRight, 0)] {
+        let padded = render(true, pad);
+        assert_eq!(
+            padded.dimensions(),
+            (40, 10),
+            "the width is padded to match"
+        );
+        for (x, y, pixel) in unpadded.enumerate_pixels() {
+            assert_eq!(padded.get_pixel(left + x, y), pixel, "{pad:?}");
+        }
+    }
+}
+
+#[test]
+fn letterbox_bands_keep_their_color() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "some text\n".repeat(10))],
+    };
+    let render_for = |target_aspect_ratio, letterbox_color| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height: 1,
+                target_aspect_ratio,
+                max_columns: Some(1),
+                grayscale: true,
+                letterbox_color,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let unboxed = render_for(4.0, None);
+    let red = image::Rgb([255, 0, 0]);
+    assert_eq!(
+        render_for(1.04, Some(red)).dimensions(),
+        (10, 10),
+        "aspect ratios close to the target aren't letterboxed"
+    );
+    let boxed = render_for(4.0, Some(red));
+    assert_eq!(boxed.dimensions(), (40, 10));
+    for (x, y, pixel) in boxed.enumerate_pixels() {
+        if (15..25).contains(&x) {
+            assert_eq!(pixel, unboxed.get_pixel(x - 15, y));
+        } else {
+            assert_eq!(*pixel, red, "bands are added after turning the image gray");
+        }
+    }
+}
+
+#[test]
+fn highlighted_files_get_a_border() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: "root".into(),
+        children_content: vec![
+            ("root/src/lib.rs".into(), "x\n".repeat(4)),
+            ("root/notes.txt".into(), "x\n".repeat(4)),
+        ],
+    };
+    let green = image::Rgb([0, 255, 0]);
+    let img = render_with(
+        &dir_contents,
+        render::Options {
+            column_width: 10,
+            line_height: 1,
+            target_aspect_ratio: 1.0,
+            max_columns: Some(1),
+            highlight_files: &["src/*.rs".into()],
+            highlight_color: green,
+            highlight_border_width: 1,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(img.dimensions(), (10, 8));
+    for (x, y) in [(0, 0), (9, 0), (0, 3), (9, 3), (5, 0), (5, 3), (0, 2)] {
+        assert_eq!(
+            *img.get_pixel(x, y),
+            green,
+            "border of the matching file at {x},{y}"
+        );
+    }
+    assert_ne!(
+        *img.get_pixel(5, 1),
+        green,
+        "the inside of the border is left alone"
+    );
+    for (x, y, pixel) in img.enumerate_pixels().filter(|(_, y, _)| *y >= 4) {
+        assert_ne!(*pixel, green, "other files have no border at {x},{y}");
+    }
+}
+
+#[test]
+fn max_memory_reduces_the_line_height_until_the_image_fits() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "some text\n".repeat(100))],
+    };
+    let render = |line_height, max_memory| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height,
+                target_width: Some(40),
+                max_memory,
+                ..Default::default()
+            },
+        )
+    };
+    let thin = render(2, None).unwrap();
+    let budget = thin.len() as u64;
+    let fitted = render(4, Some(budget)).unwrap();
+    assert_eq!(
+        fitted.dimensions(),
+        thin.dimensions(),
+        "the largest line height that fits is used"
+    );
+
+    let err = render(4, Some(budget / 4)).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            codevis::RenderError::ExceedsMaxMemory { line_height: 1, max_memory, .. } if max_memory == budget / 4
+        ),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn line_spacing_leaves_blank_rows_below_each_line() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "xxxx\n".repeat(4))],
+    };
+    let render = |line_spacing| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height: 1,
+                line_spacing,
+                max_columns: Some(1),
+                target_aspect_ratio: 0.0,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let dense = render(0);
+    let spaced = render(2);
+    assert_eq!(dense.dimensions(), (10, 4));
+    assert_eq!(spaced.dimensions(), (10, 12), "each line takes three rows");
+    let background = *dense.get_pixel(9, 0);
+    for y in 0..spaced.height() {
+        for x in 0..spaced.width() {
+            let expected = if y % 3 == 0 {
+                *dense.get_pixel(x, y / 3)
+            } else {
+                background
+            };
+            assert_eq!(*spaced.get_pixel(x, y), expected, "pixel at {x},{y}");
+        }
+    }
+}
+
+#[test]
+fn uniform_file_height_truncates_and_pads_files() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![
+            ("long.txt".into(), "a\nb\nc\nd\n".to_owned()),
+            ("short.txt".into(), "x\n".to_owned()),
+        ],
+    };
+    let render = |threads| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                threads,
+                uniform_file_height: Some(2),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let img = render(1);
+    assert_eq!(img.dimensions(), (10, 4), "each file takes two lines");
+    let background = img.get_pixel(9, 2);
+    assert!(
+        (0..10).all(|x| img.get_pixel(x, 3) == background),
+        "the short file is padded with its background"
+    );
+    assert!(
+        render(2).as_bytes() == img.as_bytes(),
+        "multi-threading pads the same way"
+    );
+}
+
+#[test]
+fn indent_by_depth_leaves_a_margin_per_directory() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![
+            ("top.txt".into(), "x\n".to_owned()),
+            ("a/b/nested.txt".into(), "x\n".to_owned()),
+            ("a/b/c/d/e/f/deep.txt".into(), "x\n".to_owned()),
+        ],
+    };
+    let render = |threads| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                threads,
+                indent_by_depth: Some(2),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let img = render(1);
+    let margin = |y| {
+        let background = *img.get_pixel(9, y);
+        (0..10)
+            .take_while(|&x| *img.get_pixel(x, y) == background)
+            .count()
+    };
+    assert_eq!(margin(0), 0, "top-level files aren't indented");
+    assert_eq!(margin(1), 4, "two cells per directory");
+    assert_eq!(margin(2), 5, "the indent is clamped to half the column");
+    assert!(
+        render(2).as_bytes() == img.as_bytes(),
+        "multi-threading indents the same way"
+    );
+}
+
+#[test]
+fn max_columns_limits_the_amount_of_columns() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.txt".into(), "x\n".repeat(5000))],
+    };
+    let render = |max_columns| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 1,
+                line_height: 1,
+                max_columns,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    assert!(
+        render(None).width() > 3,
+        "the aspect ratio alone asks for many columns"
+    );
+    let img = render(Some(3));
+    assert_eq!(img.dimensions(), (3, 1667), "all lines fit into 3 columns");
+}
+
+#[test]
+fn size_desc_render_order_puts_the_largest_file_first() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![
+            ("small.txt".into(), "x\n".to_owned()),
+            ("large.txt".into(), "xxxxxxxx\n".repeat(4)),
+        ],
+    };
+    let first_line_width = |render_order| {
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                render_order,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let background = *img.get_pixel(9, 0);
+        (0..10)
+            .take_while(|&x| *img.get_pixel(x, 0) != background)
+            .count()
+    };
+    assert_eq!(first_line_width(None), 1, "files are rendered as given");
+    assert_eq!(
+        first_line_width(Some(render::RenderOrder::SizeDesc)),
+        8,
+        "the large file comes first"
+    );
+}
+
+#[test]
+fn shuffle_seed_reorders_files_reproducibly() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: (1..=8)
+            .map(|width| {
+                (
+                    format!("{width}.txt").into(),
+                    format!("{}\n", "x".repeat(width)),
+                )
+            })
+            .collect(),
+    };
+    // the width of each line tells which file it belongs to.
+    let line_widths = |shuffle_seed| {
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                shuffle_seed,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        (0..img.height())
+            .map(|y| {
+                let background = *img.get_pixel(9, y);
+                (0..10)
+                    .take_while(|&x| *img.get_pixel(x, y) != background)
+                    .count()
+            })
+            .collect::<Vec<_>>()
+    };
+    let in_order = line_widths(None);
+    assert_eq!(in_order, (1..=8).collect::<Vec<_>>());
+
+    let shuffled = line_widths(Some(42));
+    assert_ne!(shuffled, in_order, "the order changes");
+    assert_eq!(
+        line_widths(Some(42)),
+        shuffled,
+        "the same seed gives the same order"
+    );
+    assert_ne!(
+        line_widths(Some(7)),
+        shuffled,
+        "another seed gives another order"
+    );
+    let mut sorted = shuffled;
+    sorted.sort();
+    assert_eq!(sorted, in_order, "every file is still rendered once");
+}
+
+#[test]
+fn comment_opacity_fades_only_comments_into_the_background() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![("file.rs".into(), "// note\nfn f() {}\n".to_owned())],
+    };
+    let render = |comment_opacity, plain| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                threads: 1,
+                comment_opacity,
+                plain,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    fn line_is_blank(
+        img: &image::ImageBuffer<image::Rgb<u8>, impl std::ops::Deref<Target = [u8]>>,
+        y: u32,
+    ) -> bool {
+        (0..img.width()).all(|x| img.get_pixel(x, y) == img.get_pixel(0, y))
+    }
+
+    let visible = render(1.0, false);
+    let hidden = render(0.0, false);
+    assert!(!line_is_blank(&visible, 0));
+    assert!(line_is_blank(&hidden, 0), "the comment vanished");
+    assert!(
+        (0..10).all(|x| visible.get_pixel(x, 1) == hidden.get_pixel(x, 1)),
+        "code is unaffected"
+    );
+    assert!(
+        render(0.0, true).as_bytes() == render(1.0, true).as_bytes(),
+        "plain text has no comments"
+    );
+}
+
+#[test]
+fn translucent_theme_colors_are_drawn_as_is_without_comment_opacity() {
+    let mut translucent = theme_set().themes["base16-ocean.dark"].clone();
+    for item in &mut translucent.scopes {
+        if let Some(foreground) = item.style.foreground.as_mut() {
+            foreground.a = 0x40;
+        }
+    }
+    let mut opaque = translucent.clone();
+    for item in &mut opaque.scopes {
+        if let Some(foreground) = item.style.foreground.as_mut() {
+            foreground.a = 0xff;
+        }
+    }
+
+    let dir_contents = codevis::DirContents::from_memory(vec![(
+        "main.rs".into(),
+        "// note\nfn main() { let s = \"text\"; }\n".into(),
+    )]);
+    let render = |theme| {
+        let mut renderer = codevis::Renderer::new();
+        renderer.add_theme("theme", theme);
+        renderer
+            .render(
+                &dir_contents,
+                render::Options {
+                    column_width: 40,
+                    line_height: 1,
+                    threads: 1,
+                    theme: "theme",
+                    ..Default::default()
+                },
+                prodash::progress::Discard,
+                &AtomicBool::default(),
+            )
+            .unwrap()
+    };
+    assert!(render(translucent).as_bytes() == render(opaque).as_bytes());
+}
+
+#[test]
+fn missing_glyphs_are_replaced_in_readable_mode() {
+    let render = |content: &str, missing_glyph| {
+        let dir_contents = codevis::DirContents {
+            parent_dir: Default::default(),
+            children_content: vec![("file.txt".into(), content.to_owned())],
+        };
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 4,
+                target_aspect_ratio: 0.0,
+                readable: true,
+                threads: 1,
+                missing_glyph,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    // A private use codepoint that the font has no glyph for.
+    let unmapped = "a\u{10FFFD}\n";
+    assert!(
+        render(unmapped, Some(' ')).as_bytes() == render("a \n", None).as_bytes(),
+        "the unmapped character is drawn as the chosen replacement"
+    );
+    // the pixels that aren't background, which is what the empty last cell of the line is filled with.
+    let glyph_pixels = |img: ImageBuffer<Rgb<u8>, MmapMut>| {
+        let background = *img.get_pixel(img.width() - 1, 0);
+        img.enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel != background)
+            .map(|(x, y, _)| (x, y))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        glyph_pixels(render(unmapped, Some('x'))),
+        glyph_pixels(render("ax\n", None)),
+        "visible replacements are drawn in place of the character"
+    );
+    assert_ne!(
+        glyph_pixels(render(unmapped, None)),
+        glyph_pixels(render("ax\n", None)),
+        "without a replacement, the font's own placeholder is drawn"
+    );
+    assert!(
+        render("ax\n", Some(' ')).as_bytes() == render("ax\n", None).as_bytes(),
+        "characters with a glyph are left alone"
+    );
+}
+
+#[test]
+fn file_summary_draws_one_bar_per_file() {
+    let dir_contents = codevis::DirContents {
+        parent_dir: Default::default(),
+        children_content: vec![
+            ("long.txt".into(), "line\n".repeat(10)),
+            ("short.txt".into(), "line\n".repeat(5)),
+        ],
+    };
+    let img = render_with(
+        &dir_contents,
+        render::Options {
+            column_width: 10,
+            line_height: 1,
+            target_aspect_ratio: 0.0,
+            file_summary: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(img.dimensions(), (10, 2), "one line per file");
+    let bar = *img.get_pixel(0, 0);
+    assert!(
+        (0..10).all(|x| *img.get_pixel(x, 0) == bar),
+        "the longest file fills the column"
+    );
+    assert!((0..5).all(|x| *img.get_pixel(x, 1) == bar));
+    assert!(
+        (5..10).all(|x| *img.get_pixel(x, 1) != bar),
+        "half as many lines make half a bar"
+    );
+}
+
+#[test]
+fn border_and_shadow_frame_the_image() {
+    let dir_contents = codevis::DirContents::from_memory(vec![(
+        "main.rs".into(),
+        "fn main() {\n    println!(\"hello\");\n}\n".into(),
+    )]);
+    let render = |border: u32, shadow: u32| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                threads: 1,
+                border,
+                border_color: image::Rgb([255, 0, 0]),
+                shadow,
+                shadow_backdrop: image::Rgb([200, 200, 200]),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let plain = render(0, 0);
+    let (width, height) = plain.dimensions();
+
+    let framed = render(3, 0);
+    assert_eq!(framed.dimensions(), (width + 6, height + 6));
+    assert_eq!(*framed.get_pixel(0, 0), image::Rgb([255, 0, 0]));
+    assert_eq!(
+        *framed.get_pixel(width + 5, height + 5),
+        image::Rgb([255, 0, 0])
+    );
+    assert_eq!(*framed.get_pixel(3, 3), *plain.get_pixel(0, 0));
+
+    let shadowed = render(0, 4);
+    assert_eq!(shadowed.dimensions(), (width + 10, height + 10));
+    assert_eq!(
+        *shadowed.get_pixel(0, 0),
+        image::Rgb([200, 200, 200]),
+        "no shadow falls to the top left"
+    );
+    assert_eq!(*shadowed.get_pixel(4, 4), *plain.get_pixel(0, 0));
+    let below = shadowed.get_pixel(width / 2, height + 5);
+    assert!(
+        below[0] < 200 && below[0] > 100,
+        "the shadow below the image is soft, got {below:?}"
+    );
+}
+
+#[test]
+fn only_files_modified_since_the_given_time_are_read() {
+    use std::time::SystemTime;
+    let input_dir = TempDir::new("modified-since");
+    let input_dir = input_dir.path();
+    let now = SystemTime::now();
+    let day = Duration::from_secs(24 * 60 * 60);
+    for (name, age) in [("recent.rs", day), ("old.rs", 10 * day)] {
+        let path = input_dir.join(name);
+        std::fs::write(&path, "fn main() {}\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(now - age)
+            .unwrap();
+    }
+
+    let read = |modified_since: Option<SystemTime>| {
+        let (contents, ignored) = codevis::unicode_content(
+            input_dir,
+            codevis::ReadOptions {
+                normalize_eol: true,
+                modified_since,
+                ..Default::default()
+            },
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+        )
+        .unwrap();
+        let mut files = contents
+            .children_content
+            .into_iter()
+            .map(|(path, _)| path.strip_prefix(input_dir).unwrap().to_owned())
+            .collect::<Vec<_>>();
+        files.sort();
+        (files, ignored)
+    };
+    let all = read(None);
+    let recent = read(Some(now - 7 * day));
+
+    assert_eq!(
+        all,
+        (
+            vec![Path::new("old.rs").into(), Path::new("recent.rs").into()],
+            0
+        )
+    );
+    assert_eq!(
+        recent,
+        (vec![Path::new("recent.rs").into()], 1),
+        "older files count as ignored"
+    );
+}
+
+#[test]
+fn combining_characters_take_no_cell_in_readable_mode() {
+    let render = |line: &str| {
+        render_with(
+            &codevis::DirContents::from_memory(vec![("a.txt".into(), format!("{line}\nxyz\n"))]),
+            render::Options {
+                threads: 1,
+                readable: true,
+                column_width: 10,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    assert!(
+        render("e\u{301}ab").as_bytes() == render("eab").as_bytes(),
+        "the accent doesn't push the following characters to the right"
+    );
+    assert!(
+        render("中ab").as_bytes() == render("中\u{200d}ab").as_bytes(),
+        "joiners take no cell either"
+    );
+}
+
+#[test]
+fn badge_is_drawn_into_its_corner_only() {
+    let dir_contents = codevis::DirContents::from_memory(vec![(
+        "main.rs".into(),
+        "fn main() {\n    println!(\"hello\");\n}\n".repeat(2000),
+    )]);
+    let render = |badge| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                threads: 1,
+                badge,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let plain = render(render::Badge::None);
+    let badged = render(render::Badge::BottomRight);
+    assert_eq!(plain.dimensions(), badged.dimensions());
+
+    let (width, height) = plain.dimensions();
+    let differs = |x_range: std::ops::Range<u32>, y_range: std::ops::Range<u32>| {
+        y_range
+            .flat_map(|y| x_range.clone().map(move |x| (x, y)))
+            .any(|(x, y)| plain.get_pixel(x, y) != badged.get_pixel(x, y))
+    };
+    assert!(differs(width / 2..width, height / 2..height));
+    assert!(
+        !differs(0..width / 2, 0..height),
+        "the left half is untouched"
+    );
+    assert!(
+        !differs(0..width, 0..height / 2),
+        "the top half is untouched"
+    );
+}
+
+#[test]
+fn no_split_files_moves_files_to_the_next_column() {
+    let dir_contents = codevis::DirContents::from_memory(
+        ["a.txt", "b.txt", "c.txt"]
+            .into_iter()
+            .map(|name| (name.into(), format!("{name}\n").repeat(3)))
+            .collect(),
+    );
+    let mut images = Vec::new();
+    for threads in [1, 4] {
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                threads,
+                column_width: 10,
+                line_height: 1,
+                target_aspect_ratio: 100.0,
+                max_columns: Some(2),
+                no_split_files: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        // the second file would end in the middle of the first column, so the third starts the second column,
+        // which takes the line of the first column that is left.
+        assert_eq!(img.dimensions(), (20, 7), "threads: {threads}");
+        let blank_lines = (0..2)
+            .flat_map(|column| (0..7).map(move |line| (column, line)))
+            .filter(|&(column, line)| {
+                (0..10).all(|x| img.get_pixel(column * 10 + x, line) == img.get_pixel(19, 6))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blank_lines,
+            [(0, 6), (1, 3), (1, 4), (1, 5), (1, 6)],
+            "threads: {threads}"
+        );
+        let first_line_of_column = |column: u32| {
+            (0..10)
+                .map(|x| *img.get_pixel(column * 10 + x, 0))
+                .collect::<Vec<_>>()
+        };
+        assert_ne!(
+            first_line_of_column(0),
+            first_line_of_column(1),
+            "the last file doesn't wrap around to the top of the first column"
+        );
+        images.push(img);
+    }
+    assert!(
+        images[0].as_bytes() == images[1].as_bytes(),
+        "all files are in the same place no matter how many threads render them"
+    );
+}
+
+#[test]
+fn strip_leading_indentation_renders_files_as_if_dedented() {
+    let render = |content: &str, strip_leading_indentation: bool| {
+        render_with(
+            &codevis::DirContents::from_memory(vec![("main.rs".into(), content.into())]),
+            render::Options {
+                threads: 1,
+                column_width: 20,
+                line_nums: true,
+                strip_leading_indentation,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let dedented = "fn a() {\n    b();\n\n}\n";
+    let indented = "        fn a() {\n            b();\n  \n        }\n";
+    assert!(render(indented, true).as_bytes() == render(dedented, false).as_bytes());
+    assert!(
+        render(indented, false).as_bytes() != render(dedented, false).as_bytes(),
+        "indentation is kept by default"
+    );
+}
+
+#[test]
+fn render_into_reuses_a_buffer_of_the_computed_size() {
+    let dir_content = codevis::DirContents::from_memory(vec![
+        ("a.rs".into(), "fn a() {}\n".repeat(30)),
+        ("b.py".into(), "def b():\n    pass\n".repeat(20)),
+    ]);
+    let opts = render::Options {
+        threads: 1,
+        column_width: 20,
+        target_aspect_ratio: 1.0,
+        grayscale: true,
+        ..Default::default()
+    };
+    let expected = render_with(&dir_content, opts).unwrap();
+
+    let (width, height) = codevis::compute_layout(
+        &dir_content,
+        prodash::progress::Discard,
+        syntax_set(),
+        theme_set(),
+        opts,
+    )
+    .unwrap();
+    assert_eq!((width, height), expected.dimensions());
+    // the buffer is dirty from a previous render, which must not show through.
+    let mut buffer = image::RgbImage::from_pixel(width, height, image::Rgb([255, 0, 255]));
+    for _ in 0..2 {
+        codevis::render_into(
+            &mut buffer,
+            &dir_content,
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+            syntax_set(),
+            theme_set(),
+            opts,
+        )
+        .unwrap();
+        assert!(buffer.as_bytes() == expected.as_bytes());
+    }
+
+    let mut too_small = image::RgbImage::new(width, height - 1);
+    let err = codevis::render_into(
+        &mut too_small,
+        &dir_content,
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+        syntax_set(),
+        theme_set(),
+        opts,
+    )
+    .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            codevis::RenderError::BufferSize { expected, actual }
+                if expected == (width, height) && actual == (width, height - 1)
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn tab_mode_determines_the_cells_a_tab_takes() {
+    let render = |tab_mode| {
+        render_with(
+            &codevis::DirContents::from_memory(vec![("main.txt".into(), "\tx\n".into())]),
+            render::Options {
+                threads: 1,
+                column_width: 8,
+                plain: true,
+                tab_mode,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let cells = |img: &image::ImageBuffer<image::Rgb<u8>, _>| {
+        let background = *img.get_pixel(7, 0);
+        (0..8)
+            .map(|x| {
+                if *img.get_pixel(x, 0) == background {
+                    '.'
+                } else {
+                    '#'
+                }
+            })
+            .collect::<String>()
+    };
+    assert_eq!(cells(&render(render::TabMode::Expand)), "....#...");
+    assert_eq!(cells(&render(render::TabMode::Glyph)), "##......");
+    assert_eq!(cells(&render(render::TabMode::Single)), ".#......");
+}
+
+#[test]
+fn glow_brightens_the_surroundings_of_bright_pixels() {
+    let render = |glow_radius| {
+        render_with(
+            &codevis::DirContents::from_memory(vec![(
+                "main.txt".into(),
+                "\n\n\n\n    x\n\n\n\n\n".into(),
+            )]),
+            render::Options {
+                threads: 1,
+                column_width: 9,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                plain: true,
+                theme: "base16-ocean.dark",
+                glow_radius,
+                glow_intensity: 1.0,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let (plain, glowing) = (render(0), render(2));
+    assert_eq!(plain.dimensions(), glowing.dimensions());
+    let luma = |pixel: &image::Rgb<u8>| pixel.0.iter().map(|&c| c as u32).sum::<u32>();
+    for (x, y, pixel) in plain.enumerate_pixels() {
+        assert!(
+            luma(glowing.get_pixel(x, y)) >= luma(pixel),
+            "glowing never darkens, but did at {x},{y}"
+        );
+    }
+    assert!(luma(plain.get_pixel(4, 4)) > luma(plain.get_pixel(0, 0)));
+    for (x, y) in [(3, 4), (5, 4), (4, 3), (4, 5), (6, 6)] {
+        assert!(
+            luma(glowing.get_pixel(x, y)) > luma(plain.get_pixel(x, y)),
+            "{x},{y} is lit by the bright pixel at 4,4"
+        );
+    }
+    assert_eq!(
+        glowing.get_pixel(0, 0),
+        plain.get_pixel(0, 0),
+        "pixels out of reach stay as they are"
+    );
+}
+
+#[test]
+fn files_are_read_from_archives_like_from_directories() {
+    use std::io::Write;
+    let dir = TempDir::new("archive");
+    let dir = dir.path();
+    let entries: [(&str, &[u8]); 5] = [
+        ("proj/src/main.rs", b"fn main() {}\n"),
+        ("proj/README.md", b"# ignored by extension\n"),
+        ("proj/.hidden.rs", b"fn hidden() {}\n"),
+        ("proj/logo.bin", b"\xff\xfe not UTF-8"),
+        ("proj/src/lib.rs", b"fn a() {}\r\nfn b() {}\r\n"),
+    ];
+
+    let tar_gz = dir.join("proj.tar.gz");
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        std::fs::File::create(&tar_gz).unwrap(),
+        flate2::Compression::default(),
+    ));
+    for (path, content) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, content).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+
+    let zip = dir.join("proj.zip");
+    let mut writer = zip::ZipWriter::new(std::fs::File::create(&zip).unwrap());
+    for (path, content) in entries {
+        writer
+            .start_file(path, zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(content).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let read = |archive: &Path| {
+        codevis::unicode_content_of_archive(
+            archive,
+            codevis::ReadOptions {
+                ignore_extensions: &["md".into()],
+                normalize_eol: true,
+                ..Default::default()
+            },
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+        )
+    };
+    for archive in [&tar_gz, &zip] {
+        let (contents, ignored) = read(archive).unwrap();
+        assert_eq!(ignored, 1, "the markdown file");
+        assert_eq!(
+            contents.children_content,
+            [
+                ("proj/src/main.rs".into(), "fn main() {}\n".to_owned()),
+                (
+                    "proj/src/lib.rs".into(),
+                    "fn a() {}\nfn b() {}\n".to_owned()
+                )
+            ],
+            "{archive:?}: hidden and binary files are skipped, and the order is kept"
+        );
+    }
+    let unsupported = dir.join("proj.rar");
+    std::fs::write(&unsupported, b"").unwrap();
+    let err = read(&unsupported).err();
+    assert!(matches!(
+        err,
+        Some(codevis::RenderError::UnsupportedArchive { .. })
+    ));
+}
+
+#[test]
+fn dimensions_are_closest_to_the_aspect_ratio() {
+    // the amount of columns and lines per column for `num_lines` lines in columns of 10x2 pixels.
+    let layout = |num_lines: u32, target_aspect_ratio: f64, force_full_columns: bool| {
+        let (width, height) = codevis::compute_layout(
+            &codevis::DirContents::from_memory(vec![(
+                "main.txt".into(),
+                "x\n".repeat(num_lines as usize),
+            )]),
+            prodash::progress::Discard,
+            syntax_set(),
+            theme_set(),
+            render::Options {
+                column_width: 10,
+                line_height: 2,
+                target_aspect_ratio,
+                force_full_columns,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        (width / 10, height / 2)
+    };
+
+    for (num_lines, target_aspect_ratio, full_columns, partial_columns) in [
+        (10, 1.0, (1, 10), (2, 9)),
+        (10, 2.0, (2, 5), (2, 5)),
+        (37, 0.5, (2, 19), (2, 20)),
+        (37, 3.0, (5, 8), (5, 8)),
+        (100, 1.0, (4, 25), (5, 24)),
+        (100, 16.0 / 9.0, (6, 17), (6, 17)),
+        (1000, 16.0 / 9.0, (19, 53), (19, 53)),
+    ] {
+        assert_eq!(
+            layout(num_lines, target_aspect_ratio, true),
+            full_columns,
+            "{num_lines} lines at {target_aspect_ratio}"
+        );
+        assert_eq!(
+            layout(num_lines, target_aspect_ratio, false),
+            partial_columns,
+            "{num_lines} lines at {target_aspect_ratio}"
+        );
+    }
+
+    for force_full_columns in [true, false] {
+        for num_lines in (1..=64).chain([97, 1000]) {
+            for target_aspect_ratio in [0.01, 0.1, 0.5, 1.0, 16.0 / 9.0, 3.0, 10.0, 1000.0] {
+                let (columns, lines_per_column) =
+                    layout(num_lines, target_aspect_ratio, force_full_columns);
+                let case = format!(
+                    "{num_lines} lines at {target_aspect_ratio}, force_full_columns: {force_full_columns}"
+                );
+                assert_eq!(
+                    columns,
+                    num_lines.div_ceil(lines_per_column),
+                    "no column is empty: {case}"
+                );
+                if force_full_columns {
+                    assert_eq!(
+                        lines_per_column,
+                        num_lines.div_ceil(columns),
+                        "columns are no taller than needed: {case}"
+                    );
+                }
+
+                // all layouts to choose from, the widest first as it wins ties.
+                let off = |(columns, lines_per_column): (u32, u32)| {
+                    ((columns * 10) as f64 / (lines_per_column * 2) as f64 - target_aspect_ratio)
+                        .abs()
+                };
+                let closest = (1..=num_lines)
+                    .map(|lines_per_column| {
+                        (num_lines.div_ceil(lines_per_column), lines_per_column)
+                    })
+                    .filter(|&(columns, lines_per_column)| {
+                        !force_full_columns || lines_per_column == num_lines.div_ceil(columns)
+                    })
+                    .min_by(|a, b| off(*a).total_cmp(&off(*b)))
+                    .unwrap();
+                assert_eq!((columns, lines_per_column), closest, "{case}");
+            }
+        }
+    }
+}
+
+#[test]
+fn fixed_foreground_color_is_used_for_every_glyph() {
+    let fixed = image::Rgb([255, 0, 255]);
+    for threads in [1, 4] {
+        let img = render_with(
+            &codevis::DirContents::from_memory(vec![
+                (
+                    "main.rs".into(),
+                    "// a comment\nfn main() {\n    println!(\"{}\", 42);\n}\n".into(),
+                ),
+                (
+                    "lib.py".into(),
+                    "def f():\n    return 'x'  # faded\n".into(),
+                ),
+            ]),
+            render::Options {
+                threads,
+                column_width: 40,
+                line_height: 1,
+                show_filenames: true,
+                line_nums: true,
+                fg_color: render::FgColor::Fixed(fixed),
+                comment_opacity: 0.5,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let background = *img.get_pixel(img.width() - 1, img.height() - 1);
+        let mut colors = img.pixels().copied().collect::<Vec<_>>();
+        colors.sort_by_key(|pixel| pixel.0);
+        colors.dedup();
+        assert_eq!(
+            colors.len(),
+            2,
+            "only the fixed color and the background, with threads: {threads}"
+        );
+        assert!(colors.contains(&fixed) && colors.contains(&background));
+    }
+}
+
+#[test]
+fn uniform_background_is_the_same_for_all_files() {
+    // only whitespace, so every pixel is background.
+    let dir_contents = codevis::DirContents::from_memory(vec![
+        ("a.rs".into(), "  \n  \n".into()),
+        ("b.rs".into(), "  \n  \n".into()),
+        ("c.py".into(), "  \n".into()),
+    ]);
+    let theme_for_extension = [("py".to_owned(), "InspiredGitHub".to_owned())];
+    let backgrounds = |uniform_background, threads, file_summary| {
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 4,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                threads,
+                file_summary,
+                bg_color: render::BgColor::StyleCheckerboardDarken,
+                theme_for_extension: &theme_for_extension,
+                uniform_background,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut colors = img.pixels().copied().collect::<Vec<_>>();
+        colors.sort_by_key(|pixel| pixel.0);
+        colors.dedup();
+        colors
+    };
+    assert_eq!(
+        backgrounds(false, 1, false).len(),
+        3,
+        "the checkerboard and the other theme vary the background"
+    );
+    for threads in [1, 3] {
+        let colors = backgrounds(true, threads, false);
+        assert_eq!(colors.len(), 1, "threads: {threads}");
+    }
+    let uniform = backgrounds(true, 1, false)[0];
+    assert!(
+        backgrounds(true, 1, true).contains(&uniform),
+        "summaries use the same background behind their bars"
+    );
+}
+
+#[test]
+fn changed_lines_are_tinted() {
+    let changes = codevis::LineChanges::from_unified_diff(
+        b"diff --git a/new.rs b/new.rs
+new file mode 100644
+--- /dev/null
++++ b/new.rs
+@@ -0,0 +1,2 @@
++a
++b
+",
+    );
+    let dir_contents =
+        codevis::DirContents::from_memory(vec![("new.rs".into(), "a\nb\nc\n".into())]);
+    for threads in [1, 2] {
+        let img = render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 4,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                threads,
+                plain: true,
+                line_changes: Some(&changes),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let unchanged = *img.get_pixel(3, 2);
+        for y in 0..2 {
+            let added = img.get_pixel(3, y);
+            assert_ne!(added, &unchanged, "line {y}, threads: {threads}");
+            assert!(
+                added[1] > unchanged[1],
+                "added lines are tinted green, threads: {threads}"
+            );
+        }
+    }
+}
+
+#[test]
+fn syntax_of_files_without_extension_is_detected_by_their_first_line_on_request() {
+    let dir_contents = codevis::DirContents::from_memory(vec![(
+        "script".into(),
+        "#!/usr/bin/env python\nimport os\nprint(os.getcwd())\n".into(),
+    )]);
+    let render = |detect_by_content, plain, ignore_files_without_syntax| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 30,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                detect_by_content,
+                plain,
+                ignore_files_without_syntax,
+                ..Default::default()
+            },
+        )
+    };
+    let plain = render(true, true, false).unwrap();
+    assert_ne!(
+        render(true, false, false).unwrap().as_bytes(),
+        plain.as_bytes()
+    );
+    assert_eq!(
+        render(false, false, false).unwrap().as_bytes(),
+        plain.as_bytes(),
+        "without detection, the script is plain text"
+    );
+    assert!(render(true, false, true).is_ok());
+    assert!(matches!(
+        render(false, false, true),
+        Err(codevis::RenderError::NoLines { .. })
+    ));
+}
+
+#[test]
+fn empty_columns_at_the_right_are_excluded() {
+    let dir_contents = codevis::DirContents::from_memory(vec![(
+        "main.rs".into(),
+        "fn main() {}\n".repeat(5) + "a\n",
+    )]);
+    let render = |exclude_empty_columns, exact_aspect| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 20,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                exclude_empty_columns,
+                exact_aspect,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let full = render(false, false);
+    let trimmed = render(true, false);
+    assert_eq!(full.dimensions(), (20, 6));
+    assert_eq!(
+        trimmed.dimensions(),
+        (12, 6),
+        "only the longest line is kept"
+    );
+    for (x, y, pixel) in trimmed.enumerate_pixels() {
+        assert_eq!(pixel, full.get_pixel(x, y));
+    }
+    assert_eq!(
+        render(true, true).dimensions(),
+        full.dimensions(),
+        "exact aspect ratios are kept"
+    );
+}
+
+#[test]
+fn files_with_their_own_width_are_rendered_next_to_the_others() {
+    let files = [
+        ("a.rs", "fn a() {}\n"),
+        ("b.java", "class B {}\nclass C {}\nclass D {}\n"),
+        ("c.rs", "fn c() {}\n"),
+    ];
+    let contents_of = |names: &[&str]| {
+        codevis::DirContents::from_memory(
+            files
+                .iter()
+                .filter(|(name, _)| names.contains(name))
+                .map(|(name, content)| (name.to_string(), content.to_string()))
+                .collect(),
+        )
+    };
+    let width_for_extension = [("java".to_owned(), 7)];
+    let render = |dir_contents: &codevis::DirContents, column_width, width_for_extension| {
+        render_with(
+            dir_contents,
+            render::Options {
+                column_width,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                width_for_extension,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let img = render(
+        &contents_of(&["a.rs", "b.java", "c.rs"]),
+        4,
+        &width_for_extension,
+    );
+    assert_eq!(img.dimensions(), (4 + 7, 3));
+
+    let rust = render(&contents_of(&["a.rs", "c.rs"]), 4, &[]);
+    let java = render(&contents_of(&["b.java"]), 7, &[]);
+    for (x, y, pixel) in img.enumerate_pixels() {
+        let expected = match (x, y) {
+            (0..=3, 0..=1) => rust.get_pixel(x, y),
+            // the rust files are shorter, so the space between `fn` and the name fills the rest.
+            (0..=3, _) => rust.get_pixel(2, 1),
+            _ => java.get_pixel(x - 4, y),
+        };
+        assert_eq!(pixel, expected, "{x}, {y}");
+    }
+
+    let opts = render::Options {
+        column_width: 4,
+        line_height: 1,
+        target_aspect_ratio: 0.0,
+        width_for_extension: &width_for_extension,
+        ..Default::default()
+    };
+    let dir_contents = contents_of(&["a.rs", "b.java", "c.rs"]);
+    let (width, height) = codevis::compute_layout(
+        &dir_contents,
+        prodash::progress::Discard,
+        syntax_set(),
+        theme_set(),
+        opts,
+    )
+    .unwrap();
+    assert_eq!(
+        (width, height),
+        img.dimensions(),
+        "the layout knows about the groups"
+    );
+    let mut buffer = image::RgbImage::new(width, height);
+    codevis::render_into(
+        &mut buffer,
+        &dir_contents,
+        prodash::progress::Discard,
+        &AtomicBool::default(),
+        syntax_set(),
+        theme_set(),
+        opts,
+    )
+    .unwrap();
+    assert!(
+        buffer.as_bytes() == img.as_bytes(),
+        "groups are drawn into buffers too"
+    );
+
+    let only_java = render(&contents_of(&["b.java"]), 4, &width_for_extension);
+    assert_eq!(
+        only_java.as_bytes(),
+        java.as_bytes(),
+        "a single group renders as usual"
+    );
+}
+
+#[test]
+fn lines_of_columns_of_a_single_cell_stay_in_their_column() {
+    let render = |content: &str, line_nums| {
+        render_with(
+            &codevis::DirContents::from_memory(vec![("main.rs".into(), content.into())]),
+            render::Options {
+                column_width: 1,
+                line_height: 1,
+                // a column per line, with the second line left of the first.
+                target_aspect_ratio: 100.0,
+                rtl_columns: true,
+                readable: true,
+                line_nums,
+                threads: 1,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    for line_nums in [false, true] {
+        let alone = render("a\n", line_nums);
+        for second_line in ["\t\t\t", "中文", "b = 1;"] {
+            let img = render(&format!("a\n{second_line}\n"), line_nums);
+            assert_eq!(img.dimensions(), (2 * 8, 16));
+            for (x, y, pixel) in alone.enumerate_pixels() {
+                assert_eq!(
+                    img.get_pixel(8 + x, y),
+                    pixel,
+                    "{second_line:?} stays in its column, line_nums: {line_nums}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn wide_glyphs_in_columns_of_a_single_cell_fill_exactly_one_column() {
+    let dir_contents = codevis::DirContents::from_memory(vec![(
+        "main.rs".into(),
+        "中文 = 1;\n中\n\t中\na\n".into(),
+    )]);
+    for readable in [false, true] {
+        for highlight_truncated_lines in [false, true] {
+            let img = render_with(
+                &dir_contents,
+                render::Options {
+                    column_width: 1,
+                    line_height: 1,
+                    target_aspect_ratio: 0.0,
+                    readable,
+                    highlight_truncated_lines,
+                    threads: 1,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let (char_width, line_height) = if readable { (8, 16) } else { (1, 1) };
+            assert_eq!(
+                img.dimensions(),
+                (char_width, 4 * line_height),
+                "readable: {readable}, highlight_truncated_lines: {highlight_truncated_lines}"
+            );
+        }
+    }
+}
+
+#[test]
+fn single_cell_tabs_ignore_tab_stops_in_readable_mode() {
+    let render = |content: &str| {
+        render_with(
+            &codevis::DirContents::from_memory(vec![("main.txt".into(), content.into())]),
+            render::Options {
+                threads: 1,
+                column_width: 8,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                readable: true,
+                plain: true,
+                tab_mode: render::TabMode::Single,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    assert_eq!(
+        render("\t\tx\n").as_bytes(),
+        render("  x\n").as_bytes(),
+        "each tab takes one cell"
+    );
+    assert_eq!(render("a\tb\tc\n").as_bytes(), render("a b c\n").as_bytes());
+}
+
+#[test]
+fn trailing_whitespace_and_length_gradient_use_their_configured_colors() {
+    let dir_contents =
+        codevis::DirContents::from_memory(vec![("file.txt".into(), "ab  \nabcdefgh\n".into())]);
+    let color = image::Rgb([0, 0, 255]);
+    let img = render_with(
+        &dir_contents,
+        render::Options {
+            column_width: 8,
+            line_height: 1,
+            target_aspect_ratio: 0.0,
+            highlight_trailing_whitespace: true,
+            trailing_whitespace_color: color,
+            length_gradient: Some(4),
+            length_gradient_color: color,
+            length_gradient_strength: 1.0,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let colored_pixels = img
+        .enumerate_pixels()
+        .filter(|(_, _, p)| **p == color)
+        .map(|(x, y, _)| (x, y))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        colored_pixels,
+        [(2, 0), (3, 0), (7, 1)],
+        "trailing whitespace is painted, and the end of a column is fully shaded"
+    );
+}
+
+#[test]
+fn line_authors_are_tinted() {
+    let blame = b"1111111111111111111111111111111111111111 1 1 2
+author Alice
+author-mail <alice@example.com>
+summary first
+filename a.rs
+\tfn a() {
+1111111111111111111111111111111111111111 2 2
+author Alice
+author-mail <alice@example.com>
+summary first
+filename a.rs
+\t}
+2222222222222222222222222222222222222222 3 3 1
+author Bob
+author-mail <bob@example.com>
+summary second
+filename a.rs
+\tauthor Mallory
+0000000000000000000000000000000000000000 4 4 1
+author Not Committed Yet
+author-mail <not.committed.yet>
+summary Version of a.rs from a.rs
+filename a.rs
+\tuncommitted();
+";
+    let authors = codevis::LineAuthors::from_line_porcelain([("a.rs".into(), &blame[..])]);
+
+    let dir_contents =
+        codevis::DirContents::from_memory(vec![("a.rs".into(), "a\nb\nc\nd\ne\n".into())]);
+    let img = render_with(
+        &dir_contents,
+        render::Options {
+            column_width: 4,
+            line_height: 1,
+            target_aspect_ratio: 0.0,
+            plain: true,
+            line_authors: Some(&authors),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let untinted = *img.get_pixel(3, 4);
+    assert_eq!(
+        img.get_pixel(3, 0),
+        img.get_pixel(3, 1),
+        "lines of the same author look alike"
+    );
+    assert_ne!(img.get_pixel(3, 0), img.get_pixel(3, 2));
+    assert_ne!(img.get_pixel(3, 0), &untinted);
+    assert_eq!(
+        img.get_pixel(3, 3),
+        &untinted,
+        "uncommitted lines aren't tinted"
+    );
+}
+
+#[test]
+fn outlined_glyphs_stand_out_from_a_background_of_the_same_hue() {
+    let dir_contents = codevis::DirContents::from_memory(vec![("a.txt".into(), "o\n".into())]);
+    let (fg, bg) = (image::Rgb([40, 60, 200]), image::Rgb([50, 70, 210]));
+    let render = |glyph_outline, glyph_outline_color| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 1,
+                target_aspect_ratio: 0.0,
+                readable: true,
+                mono: Some((fg, bg)),
+                glyph_outline,
+                glyph_outline_color,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let count = |img: &image::ImageBuffer<image::Rgb<u8>, _>, color| {
+        img.pixels().filter(|p| **p == color).count()
+    };
+
+    let plain = render(false, None);
+    let glyph_pixels = count(&plain, fg);
+    assert!(glyph_pixels > 0);
+    assert_eq!(
+        count(&plain, fg) + count(&plain, bg),
+        plain.pixels().count(),
+        "without outline, only the barely different glyph and background are visible"
+    );
+
+    let outlined = render(true, None);
+    assert_eq!(
+        count(&outlined, fg),
+        glyph_pixels,
+        "glyphs stay as they are"
+    );
+    let outline_pixels = outlined
+        .pixels()
+        .filter(|p| **p != fg && **p != bg)
+        .collect::<Vec<_>>();
+    assert!(!outline_pixels.is_empty());
+    assert!(
+        outline_pixels
+            .iter()
+            .all(|p| (0..3).all(|channel| p[channel] < fg[channel])),
+        "the background around glyphs is darkened"
+    );
+
+    let black = image::Rgb([0, 0, 0]);
+    let colored = render(true, Some(black));
+    assert_eq!(count(&colored, black), outline_pixels.len());
+}
+
+#[test]
+fn binary_files_are_read_as_hexdump_on_request() {
+    let input_dir = Path::new("tests/fixtures/binary/input");
+    let read_files = |binary_as_hexdump: bool| {
+        let (contents, _ignored) = codevis::unicode_content(
+            input_dir,
+            codevis::ReadOptions {
+                normalize_eol: true,
+                binary_as_hexdump,
+                ..Default::default()
+            },
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+        )
+        .unwrap();
+        let mut files = contents
+            .children_content
+            .into_iter()
+            .map(|(path, content)| (path.strip_prefix(input_dir).unwrap().to_owned(), content))
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    };
+    assert_eq!(
+        read_files(false),
+        [("main.rs".into(), "fn main() {}\n".into())],
+        "binary files are skipped by default"
+    );
+    assert_eq!(
+        read_files(true),
+        [
+            (
+                "logo.png".into(),
+                "00000000: 8950 4e47 0d0a 1a0a 0000 000d 4948 4452  .PNG........IHDR\n\
+                 00000010: fffe 0001                                ....\n"
+                    .into()
+            ),
+            ("main.rs".into(), "fn main() {}\n".into())
+        ],
+        "the hexdump looks like the one of xxd"
+    );
+}
+
+#[test]
+fn files_after_the_deadline_are_left_blank_in_an_image_of_the_same_size() {
+    let dir_contents = codevis::DirContents::from_memory(vec![
+        ("a.rs".into(), "fn a() {}\n".repeat(20)),
+        ("b.rs".into(), "fn b() {}\n".repeat(10)),
+    ]);
+    for threads in [1, 2] {
+        let render = |deadline| {
+            render_with(
+                &dir_contents,
+                render::Options {
+                    column_width: 10,
+                    line_height: 1,
+                    threads,
+                    deadline,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+        let complete = render(None);
+        assert_eq!(
+            render(Some(Instant::now() + Duration::from_secs(3600))).as_bytes(),
+            complete.as_bytes(),
+            "nothing changes if there is enough time, threads: {threads}"
+        );
+        let stopped = render(Some(Instant::now()));
+        assert_eq!(stopped.dimensions(), complete.dimensions());
+        assert!(
+            stopped.pixels().all(|p| *p == image::Rgb([0, 0, 0])),
+            "no file is rendered after the deadline, threads: {threads}"
+        );
+    }
+}
+
+#[test]
+fn only_code_or_only_comments_are_shown_on_request() {
+    let dir_contents =
+        codevis::DirContents::from_memory(vec![("file.rs".into(), "// note\nfn f() {}\n".into())]);
+    let render = |only, plain| {
+        render_with(
+            &dir_contents,
+            render::Options {
+                column_width: 10,
+                line_height: 1,
+                target_aspect_ratio: 0.0,
+                threads: 1,
+                only,
+                plain,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let line = |img: &image::ImageBuffer<image::Rgb<u8>, _>, y| {
+        (0..img.width())
+            .map(|x| *img.get_pixel(x, y))
+            .collect::<Vec<_>>()
+    };
+    let is_blank = |line: &[image::Rgb<u8>]| line.iter().all(|p| *p == line[0]);
+
+    let all = render(None, false);
+    let code = render(Some(render::Only::Code), false);
+    let comments = render(Some(render::Only::Comments), false);
+    assert!(!is_blank(&line(&all, 0)) && !is_blank(&line(&all, 1)));
+    assert!(is_blank(&line(&code, 0)), "the comment vanished");
+    assert_eq!(line(&code, 1), line(&all, 1), "code is unaffected");
+    assert_eq!(line(&comments, 0), line(&all, 0), "comments are unaffected");
+    assert!(is_blank(&line(&comments, 1)), "the code vanished");
+    assert_eq!(
+        render(Some(render::Only::Comments), true).as_bytes(),
+        render(None, true).as_bytes(),
+        "plain text has no comments"
+    );
+}
+
+#[test]
+fn downscale_filters_treat_fine_patterns_differently() {
+    let checkerboard = image::RgbImage::from_fn(64, 64, |x, y| {
+        if (x + y) % 2 == 0 {
+            image::Rgb([255, 255, 255])
+        } else {
+            image::Rgb([0, 0, 0])
+        }
+    });
+    let downscale = |filter| render::downscale(&checkerboard, 10, 10, filter);
+    let filters = [
+        render::DownscaleFilter::Nearest,
+        render::DownscaleFilter::Triangle,
+        render::DownscaleFilter::Catmull,
+        render::DownscaleFilter::Lanczos,
+    ];
+    for (index, a) in filters.iter().enumerate() {
+        for b in &filters[index + 1..] {
+            assert_ne!(downscale(*a), downscale(*b), "{a:?} and {b:?}");
+        }
+    }
+    assert!(
+        downscale(render::DownscaleFilter::Nearest)
+            .pixels()
+            .all(|p| p[0] == 0 || p[0] == 255),
+        "nearest keeps colors as they are"
+    );
+    assert_eq!(
+        downscale(render::DownscaleFilter::Lanczos).dimensions(),
+        (10, 10)
+    );
+}
+
+#[test]
+fn sixteen_bit_png_widens_every_channel() {
+    let img = image::RgbImage::from_fn(3, 2, |x, y| image::Rgb([x as u8 * 100, y as u8 * 255, 7]));
+    let mut buf = Vec::new();
+    codevis::render_to_writer(
+        &img,
+        codevis::Format::Png,
+        codevis::BitDepth::Sixteen,
+        &mut buf,
+        prodash::progress::Discard,
+    )
+    .unwrap();
+    let decoded = image::load_from_memory_with_format(&buf, image::ImageFormat::Png).unwrap();
+    assert_eq!(decoded.color(), image::ColorType::Rgb16);
+    let widened = img
+        .as_raw()
+        .iter()
+        .map(|&v| v as u16 * 257)
+        .collect::<Vec<_>>();
+    assert_eq!(decoded.into_rgb16().as_raw(), &widened);
+
+    assert!(
+        codevis::render_to_writer(
+            &img,
+            codevis::Format::Bmp,
+            codevis::BitDepth::Sixteen,
+            Vec::new(),
+            prodash::progress::Discard,
+        )
+        .is_err(),
+        "only PNG supports 16 bits per channel"
+    );
+}
+
+#[test]
+fn only_lines_matching_grep_only_are_kept() {
+    let mut contents = codevis::DirContents::from_memory(vec![
+        (
+            "lib.rs".into(),
+            "use a;\nfn one() {}\n\nstruct S;\nfn two() {\r\n}\n".into(),
+        ),
+        ("other.rs".into(), "struct T;\n".into()),
+    ]);
+    let dropped = contents.keep_matching_lines(&regex::Regex::new("^fn .*[{}]$").unwrap());
+    assert_eq!(dropped, 1, "files without matches are dropped");
+    assert_eq!(
+        contents.children_content,
+        vec![("lib.rs".into(), "fn one() {}\nfn two() {\r\n".into())],
+        "line endings aren't matched but kept"
+    );
+}
+
+#[test]
+fn zebra_columns_shift_the_background_of_every_other_column() {
+    let content = "\n".repeat(20);
+    let render = |zebra_columns| {
+        render_with(
+            &codevis::DirContents::from_memory(vec![("blank.txt".into(), content.clone())]),
+            render::Options {
+                threads: 1,
+                column_width: 4,
+                line_height: 1,
+                max_columns: Some(2),
+                target_aspect_ratio: 1.0,
+                zebra_columns,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let (plain, zebra) = (render(false), render(true));
+    assert_eq!(plain.width(), 8, "two columns of four pixels each");
+    let background = *plain.get_pixel(0, 0);
+    assert!(plain.pixels().all(|pixel| *pixel == background));
+    for (x, y, pixel) in zebra.enumerate_pixels() {
+        if x < 4 {
+            assert_eq!(*pixel, background, "the first column stays as it is");
+        } else {
+            assert_ne!(
+                *pixel, background,
+                "the second column at {x},{y} is shifted"
+            );
+            assert!(
+                pixel.0.iter().zip(background.0).all(|(c, bg)| *c >= bg),
+                "the dark background gets brighter"
+            );
+        }
+    }
+}
+
+#[test]
+fn terminal_theme_highlights_with_ansi_colors() {
+    let ansi = (0..16u8)
+        .map(|index| format!("#{:02x}{:02x}{:02x}", index * 16, 0x40, 0xff - index * 16))
+        .collect::<Vec<_>>();
+    let colors = codevis::parse_ansi_colors(&ansi.join("\n")).unwrap();
+
+    let mut renderer = codevis::Renderer::new();
+    renderer.add_theme(codevis::TERMINAL_THEME, codevis::terminal_theme(&colors));
+    let img = renderer
+        .render(
+            &codevis::DirContents::from_memory(vec![(
+                "main.rs".into(),
+                "// a comment that is long enough\n".into(),
+            )]),
+            render::Options {
+                threads: 1,
+                theme: codevis::TERMINAL_THEME,
+                fg_color: render::FgColor::Style,
+                bg_color: render::BgColor::Style,
+                ..Default::default()
+            },
+            prodash::progress::Discard,
+            &AtomicBool::default(),
+        )
+        .unwrap();
+    let pixels = img
+        .pixels()
+        .copied()
+        .collect::<std::collections::HashSet<_>>();
+    assert!(pixels.contains(&colors[0]), "the background is black");
+    assert!(pixels.contains(&colors[8]), "comments are bright black");
+}
+
+#[test]
+fn files_with_only_whitespace_are_marked() {
+    let render = |mark_blank_files| {
+        render_with(
+            &codevis::DirContents::from_memory(vec![
+                ("main.rs".into(), "fn main() {}\n".into()),
+                ("blank.rs".into(), "\n  \n\t\n".into()),
+            ]),
+            render::Options {
+                threads: 1,
+                column_width: 20,
+                max_columns: Some(1),
+                mark_blank_files,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let (plain, marked) = (render(false), render(true));
+    assert_eq!(plain.dimensions(), marked.dimensions());
+    let changed = plain
+        .enumerate_pixels()
+        .zip(marked.pixels())
+        .filter(|((_, _, plain), marked)| plain != marked)
+        .map(|((x, y, _), _)| (x, y))
+        .collect::<Vec<_>>();
+    let expected = (2..8)
+        .flat_map(|y| (0..2).map(move |x| (x, y)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changed, expected,
+        "only the left edge of the three blank lines changes"
+    );
+}